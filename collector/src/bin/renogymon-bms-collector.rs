@@ -4,14 +4,29 @@ use prometheus_client::registry::Registry;
 use renogy::any_transport::AnyTransport;
 use renogy::any_transport::BT2_SCAN_RANGE;
 use renogy::any_transport::SERIAL_SCAN_RANGE;
+use renogy::any_transport::TCP_SCAN_RANGE;
 use renogy::bt2::Bt2Transport;
-use renogy::bt2::discover_bt2_devices;
 use renogy::collector::buffer::SampleBuffer;
+use renogy::collector::link::LinkSpec;
 use renogy::collector::metrics::PrometheusMetrics;
 use renogy::collector::server::MetricsServer;
 use renogy::collector::writer::VmWriter;
-use renogy::serial::SerialTransport;
+use renogy::discovery::DiscoveryOptions;
+use renogy::recording::RecordingTransport;
+use renogy::recording::ReplayMode;
+use renogy::recording::ReplayTransport;
+use renogy::retry::RetryPolicy;
+use renogy::retry::RetryingTransport;
+use renogy::rtu_tcp::DEFAULT_RTU_TCP_PORT;
+use renogy::rtu_tcp::RtuOverTcpTransport;
+use renogy::tcp::DEFAULT_TCP_PORT;
+use renogy::tcp::TcpTransport;
+use renogy::util::Bt2Args;
+use renogy::util::SerialArgs;
 use renogy::util::parse_address;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio_util::sync::CancellationToken;
@@ -47,19 +62,35 @@ struct Args {
     /// Disable /metrics endpoint (push only)
     #[arg(long)]
     disable_pull: bool,
+
+    /// Retries for reads failing with a CRC error, timeout or busy slave (0 disables)
+    #[arg(long, default_value_t = RetryPolicy::default().max_retries)]
+    max_retries: u32,
+
+    /// Record every request and response to this file (JSON Lines) for later replay
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Per-address timeout when discovering batteries, in milliseconds [default: 500,
+    /// or 5000 over BT-2]
+    #[arg(long)]
+    probe_timeout_ms: Option<u64>,
+
+    /// Stop discovery once this many batteries have answered
+    #[arg(long)]
+    max_batteries: Option<usize>,
+
+    /// Stop discovery after this many silent addresses in a row
+    #[arg(long)]
+    max_consecutive_misses: Option<usize>,
 }
 
 #[derive(Subcommand)]
 enum TransportCmd {
     /// Connect via BT-2 Bluetooth adapter
     Bt2 {
-        /// BT-2 MAC address
-        #[arg(short, long)]
-        mac: Option<String>,
-
-        /// Bluetooth adapter name
-        #[arg(short, long, default_value = "hci0")]
-        adapter: String,
+        #[command(flatten)]
+        bt2: Bt2Args,
 
         /// BMS addresses to monitor
         #[arg(short = 'b', long, value_parser = parse_address)]
@@ -67,13 +98,58 @@ enum TransportCmd {
     },
     /// Connect via serial/RS-485
     Serial {
-        /// Serial port path
-        #[arg(short, long)]
-        port: String,
+        #[command(flatten)]
+        serial: SerialArgs,
 
-        /// Baud rate
-        #[arg(short = 'r', long, default_value_t = 9600)]
-        baud_rate: u32,
+        /// BMS addresses to monitor
+        #[arg(short, long, value_parser = parse_address)]
+        bms_addresses: Vec<u8>,
+    },
+    /// Connect via a Modbus TCP gateway
+    Tcp {
+        /// Gateway host name or IP address
+        #[arg(long)]
+        host: String,
+
+        /// Gateway TCP port
+        #[arg(short, long, default_value_t = DEFAULT_TCP_PORT)]
+        port: u16,
+
+        /// BMS addresses (Modbus unit IDs) to monitor
+        #[arg(short, long, value_parser = parse_address)]
+        bms_addresses: Vec<u8>,
+    },
+    /// Connect via a transparent serial server tunnelling raw RTU frames over TCP
+    RtuTcp {
+        /// Serial server host name or IP address
+        #[arg(long)]
+        host: String,
+
+        /// Serial server TCP port
+        #[arg(short, long, default_value_t = DEFAULT_RTU_TCP_PORT)]
+        port: u16,
+
+        /// BMS addresses to monitor
+        #[arg(short, long, value_parser = parse_address)]
+        bms_addresses: Vec<u8>,
+    },
+    /// Poll several BT-2 modules and serial ports at once, labelling each battery with
+    /// its link
+    Multi {
+        /// BT-2 link as [NAME=]MAC[@ADAPTER]; repeat for more
+        #[arg(long = "bt2", value_parser = LinkSpec::parse_bt2)]
+        bt2: Vec<LinkSpec>,
+
+        /// Serial link as [NAME=]PORT[@BAUD][,OPTION...], with the `serial` line settings as
+        /// options (e.g. parity=even,timeout-ms=500,native-rtu); repeat for more
+        #[arg(long = "serial", value_parser = LinkSpec::parse_serial)]
+        serial: Vec<LinkSpec>,
+    },
+    /// Serve a session captured with --record instead of talking to hardware
+    Replay {
+        /// Recording file
+        #[arg(short, long)]
+        file: PathBuf,
 
         /// BMS addresses to monitor
         #[arg(short, long, value_parser = parse_address)]
@@ -102,89 +178,221 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         cancel_signal.cancel();
     });
 
-    let (mut transport, addresses) = match args.transport {
-        TransportCmd::Bt2 {
-            mac,
-            adapter,
+    let discovery = DiscoveryOptions {
+        probe_timeout: args.probe_timeout_ms.map(Duration::from_millis),
+        max_batteries: args.max_batteries,
+        max_consecutive_misses: args.max_consecutive_misses,
+        ..DiscoveryOptions::default()
+    };
+
+    let links = match args.transport {
+        TransportCmd::Replay {
+            file,
             bms_addresses,
         } => {
-            let mac_address = if let Some(mac) = mac {
-                mac
+            tracing::info!("Replaying {}...", file.display());
+            let transport = ReplayTransport::open(&file, ReplayMode::Keyed)?.into();
+            let mut transport = record(transport, args.record.as_deref())?;
+
+            let addresses = if bms_addresses.is_empty() {
+                let range = transport.default_scan_range();
+                discover(&mut transport, range, &discovery).await
             } else {
-                tracing::info!("Discovering BT-2 devices...");
-                let devices = discover_bt2_devices().await?;
-                if devices.is_empty() {
-                    return Err("No BT-2 devices found. Specify a MAC address with --mac".into());
+                bms_addresses
+            };
+
+            vec![Link::single(transport, addresses)]
+        }
+        TransportCmd::Multi { bt2, serial } => {
+            let specs: Vec<LinkSpec> = bt2.into_iter().chain(serial).collect();
+            if specs.is_empty() {
+                return Err("multi needs at least one --bt2 or --serial link".into());
+            }
+            for (i, spec) in specs.iter().enumerate() {
+                if specs[..i].iter().any(|other| other.name == spec.name) {
+                    return Err(format!("duplicate link name {:?}", spec.name).into());
                 }
-                for device in &devices {
-                    tracing::info!(
-                        "Found: {} ({})",
-                        device.name.as_deref().unwrap_or("unknown"),
-                        device.address
-                    );
+            }
+            if args.record.is_some() {
+                return Err("--record captures a single link; it cannot be used with multi".into());
+            }
+
+            // Links are independent, so connect and discover them all at once.
+            let tasks: Vec<_> = specs
+                .into_iter()
+                .map(|spec| {
+                    let discovery = discovery.clone();
+                    tokio::spawn(async move { connect_link(spec, &discovery).await })
+                })
+                .collect();
+            let mut links = Vec::new();
+            for task in tasks {
+                if let Some(link) = task.await? {
+                    links.push(link);
+                }
+            }
+            links
+        }
+        TransportCmd::Bt2 { bt2, bms_addresses } => {
+            let mac_address = match &bt2.mac {
+                Some(mac) => mac.clone(),
+                None => {
+                    tracing::info!("Discovering BT-2 devices on {}...", bt2.adapter);
+                    let devices = bt2.discovery().scan().await?;
+                    for device in &devices {
+                        tracing::info!(
+                            "Found: {} ({}, RSSI {})",
+                            device.alias,
+                            device.address,
+                            device
+                                .rssi
+                                .map_or("unknown".to_string(), |rssi| format!("{rssi} dBm"))
+                        );
+                    }
+                    bt2.select(&devices)?.address.clone()
                 }
-                devices[0].address.clone()
             };
 
-            tracing::info!("Connecting to {} via {}...", mac_address, adapter);
-            let mut transport: AnyTransport =
-                Bt2Transport::connect_by_address(&mac_address, &adapter)
-                    .await?
-                    .into();
+            tracing::info!("Connecting to {} via {}...", mac_address, bt2.adapter);
+            let transport = Bt2Transport::connect_by_address(&mac_address, &bt2.adapter)
+                .await?
+                .into();
+            let mut transport = record(transport, args.record.as_deref())?;
 
             let addresses = if bms_addresses.is_empty() {
-                tracing::info!("Scanning for batteries at {:02X?}...", BT2_SCAN_RANGE);
-                let found = transport.discover_batteries(BT2_SCAN_RANGE).await;
-                tracing::info!("Found {} battery(s)", found.len());
-                found
+                discover(&mut transport, BT2_SCAN_RANGE, &discovery).await
             } else {
                 bms_addresses
             };
 
-            (transport, addresses)
+            vec![Link::single(transport, addresses)]
         }
         TransportCmd::Serial {
-            port,
-            baud_rate,
+            serial,
             bms_addresses,
         } => {
-            tracing::info!("Opening {} at {} baud...", port, baud_rate);
+            tracing::info!("Opening {} at {} baud...", serial.port, serial.baud_rate);
             let first_addr = bms_addresses.first().copied().unwrap_or(0x01);
-            let mut transport: AnyTransport = SerialTransport::new(&port, baud_rate, first_addr)
+            let transport = serial.open(first_addr).await?;
+            let mut transport = record(transport, args.record.as_deref())?;
+
+            let addresses = if bms_addresses.is_empty() {
+                discover(&mut transport, SERIAL_SCAN_RANGE, &discovery).await
+            } else {
+                bms_addresses
+            };
+
+            vec![Link::single(transport, addresses)]
+        }
+        TransportCmd::Tcp {
+            host,
+            port,
+            bms_addresses,
+        } => {
+            tracing::info!("Connecting to Modbus TCP gateway {}:{}...", host, port);
+            let transport = TcpTransport::connect((host.as_str(), port)).await?.into();
+            let mut transport = record(transport, args.record.as_deref())?;
+
+            let addresses = if bms_addresses.is_empty() {
+                discover(&mut transport, TCP_SCAN_RANGE, &discovery).await
+            } else {
+                bms_addresses
+            };
+
+            vec![Link::single(transport, addresses)]
+        }
+        TransportCmd::RtuTcp {
+            host,
+            port,
+            bms_addresses,
+        } => {
+            tracing::info!("Connecting to serial server {}:{}...", host, port);
+            let transport = RtuOverTcpTransport::connect((host.as_str(), port))
                 .await?
                 .into();
+            let mut transport = record(transport, args.record.as_deref())?;
 
             let addresses = if bms_addresses.is_empty() {
-                tracing::info!("Scanning for batteries at {:02X?}...", SERIAL_SCAN_RANGE);
-                let found = transport.discover_batteries(SERIAL_SCAN_RANGE).await;
-                tracing::info!("Found {} battery(s)", found.len());
-                found
+                discover(&mut transport, TCP_SCAN_RANGE, &discovery).await
             } else {
                 bms_addresses
             };
 
-            (transport, addresses)
+            vec![Link::single(transport, addresses)]
         }
     };
 
-    if addresses.is_empty() {
+    let links: Vec<Link> = links
+        .into_iter()
+        .filter(|link| {
+            if link.addresses.is_empty() && !link.name.is_empty() {
+                tracing::warn!("No batteries found on link {}", link.name);
+            }
+            !link.addresses.is_empty()
+        })
+        .collect();
+    if links.is_empty() {
         return Err("No batteries found!".into());
     }
 
-    tracing::info!(
-        "Monitoring {} battery(s) at addresses: {:02X?}",
-        addresses.len(),
-        addresses
-    );
-
     let metrics = Arc::new(PrometheusMetrics::default());
     let mut registry = Registry::default();
     metrics.register(&mut registry);
-    let registry = Arc::new(registry);
 
     let max_samples = (buffer_duration.as_secs() / poll_interval.as_secs().max(1)) as usize;
     let buffer = SampleBuffer::new(max_samples);
 
+    let mut pollers = Vec::new();
+    for link in links {
+        if link.name.is_empty() {
+            tracing::info!(
+                "Monitoring {} battery(s) at addresses: {:02X?}",
+                link.addresses.len(),
+                link.addresses
+            );
+        } else {
+            tracing::info!(
+                "Monitoring {} battery(s) on {} at addresses: {:02X?}",
+                link.addresses.len(),
+                link.name,
+                link.addresses
+            );
+        }
+
+        let transport = RetryingTransport::with_policy(
+            link.transport,
+            RetryPolicy {
+                max_retries: args.max_retries,
+                ..RetryPolicy::default()
+            },
+        );
+        if link.name.is_empty() {
+            transport.stats().register(&mut registry);
+        } else {
+            transport.stats().register(
+                registry.sub_registry_with_label(("link".into(), link.name.clone().into())),
+            );
+        }
+        let mut transport = AnyTransport::new(transport);
+
+        let metrics = metrics.clone();
+        let buffer = buffer.clone();
+        let cancel = cancel.clone();
+        pollers.push(tokio::spawn(async move {
+            run_poller(
+                &link.name,
+                &mut transport,
+                &link.addresses,
+                poll_interval,
+                &metrics,
+                &buffer,
+                cancel,
+            )
+            .await;
+        }));
+    }
+    let registry = Arc::new(registry);
+
     let mut handles = Vec::new();
 
     if !args.disable_pull {
@@ -203,17 +411,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }));
     }
 
-    run_poller(
-        &mut transport,
-        &addresses,
-        poll_interval,
-        &metrics,
-        &buffer,
-        cancel.clone(),
-    )
-    .await;
-
-    for handle in handles {
+    for handle in pollers.into_iter().chain(handles) {
         handle.await.ok();
     }
 
@@ -221,7 +419,63 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// A connected link and the batteries to poll over it.
+struct Link {
+    /// `link` label value; empty for the single-link subcommands.
+    name: String,
+    transport: AnyTransport,
+    addresses: Vec<u8>,
+}
+
+impl Link {
+    fn single(transport: AnyTransport, addresses: Vec<u8>) -> Self {
+        Self {
+            name: String::new(),
+            transport,
+            addresses,
+        }
+    }
+}
+
+/// Wrap a single link's transport in a recorder when `--record` is given. This happens
+/// before discovery, so the recording holds the probes too and `replay` without `-b`
+/// finds the same batteries.
+fn record(
+    transport: AnyTransport,
+    path: Option<&Path>,
+) -> Result<AnyTransport, Box<dyn std::error::Error>> {
+    match path {
+        Some(path) => {
+            tracing::info!("Recording session to {}", path.display());
+            Ok(AnyTransport::new(RecordingTransport::create(
+                transport, path,
+            )?))
+        }
+        None => Ok(transport),
+    }
+}
+
+/// Connect one link of a `multi` collector and find its batteries. Failures are logged
+/// and leave the link out rather than stopping the others.
+async fn connect_link(spec: LinkSpec, options: &DiscoveryOptions) -> Option<Link> {
+    tracing::info!("Connecting to {}...", spec);
+    let mut transport = match spec.connect().await {
+        Ok(transport) => transport,
+        Err(e) => {
+            tracing::error!("Failed to connect to {}: {}", spec, e);
+            return None;
+        }
+    };
+    let addresses = discover(&mut transport, spec.scan_range(), options).await;
+    Some(Link {
+        name: spec.name,
+        transport,
+        addresses,
+    })
+}
+
 async fn run_poller(
+    link: &str,
     transport: &mut AnyTransport,
     addresses: &[u8],
     poll_interval: Duration,
@@ -245,6 +499,7 @@ async fn run_poller(
             match transport.query_battery(addr).await {
                 Some(mut info) => {
                     info.serial = format!("{}_{:02X}", info.serial, addr);
+                    info.link = link.to_string();
                     tracing::debug!(
                         "Battery 0x{:02X}: {:.1}V {:.1}A {:.1}%",
                         addr,
@@ -256,9 +511,34 @@ async fn run_poller(
                     buffer.push(info);
                 }
                 None => {
-                    tracing::warn!("Failed to query battery at 0x{:02X}", addr);
+                    if link.is_empty() {
+                        tracing::warn!("Failed to query battery at 0x{:02X}", addr);
+                    } else {
+                        tracing::warn!("Failed to query battery at 0x{:02X} on {}", addr, link);
+                    }
                 }
             }
         }
     }
 }
+
+/// Scan `range` for batteries, logging what answered.
+async fn discover(
+    transport: &mut AnyTransport,
+    range: RangeInclusive<u8>,
+    options: &DiscoveryOptions,
+) -> Vec<u8> {
+    tracing::info!("Scanning for batteries at {:02X?}...", range);
+    let found = transport.discover_batteries(range, options).await;
+    for battery in &found {
+        tracing::info!(
+            "Found 0x{:02X}: {} {} ({:.0?})",
+            battery.address,
+            battery.model,
+            battery.serial,
+            battery.latency
+        );
+    }
+    tracing::info!("Found {} battery(s)", found.len());
+    found.iter().map(|battery| battery.address).collect()
+}
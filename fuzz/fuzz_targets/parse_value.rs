@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use renogy::registers::Register;
+
+// The first two bytes pick a register address; the rest is its (possibly truncated)
+// data. Parsing must return an error for a short read rather than panic.
+fuzz_target!(|input: &[u8]| {
+    let Some((address, data)) = input.split_first_chunk::<2>() else {
+        return;
+    };
+    let Some(register) = Register::from_address(u16::from_be_bytes(*address)) else {
+        return;
+    };
+    if let Ok(value) = register.parse_value(data) {
+        let _ = value.to_string();
+    }
+    let words: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|word| u16::from_be_bytes([word[0], word[1]]))
+        .collect();
+    let _ = register.parse_registers(&words);
+});
@@ -0,0 +1,15 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use renogy::pdu::Pdu;
+
+// Whatever arrives on the wire, decoding a frame and its register words must return
+// an error rather than panic.
+fuzz_target!(|frame: &[u8]| {
+    if let Ok(pdu) = Pdu::deserialize(frame) {
+        for quantity in [0, 1, 2, 34, 125] {
+            let _ = pdu.register_values(quantity);
+        }
+    }
+    let _ = Pdu::from_bytes(frame);
+});
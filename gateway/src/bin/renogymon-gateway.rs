@@ -0,0 +1,115 @@
+use clap::Parser;
+use clap::Subcommand;
+use renogy::any_transport::AnyTransport;
+use renogy::bt2::Bt2Transport;
+use renogy::retry::RetryingTransport;
+use renogy::shared_transport::TransportHandle;
+use renogy::tcp::DEFAULT_TCP_PORT;
+use renogy::util::Bt2Args;
+use renogy::util::SerialArgs;
+use renogymon_gateway::rate_limit::RateLimit;
+use renogymon_gateway::server::Gateway;
+use renogymon_gateway::server::GatewayConfig;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser)]
+#[command(name = "renogymon-gateway")]
+#[command(about = "Serve a Renogy BT-2 or RS-485 link as a Modbus TCP server")]
+struct Args {
+    #[command(subcommand)]
+    transport: TransportCmd,
+
+    /// Address to listen on for Modbus TCP clients
+    #[arg(long, default_value_t = SocketAddr::from(([0, 0, 0, 0], DEFAULT_TCP_PORT)))]
+    listen: SocketAddr,
+
+    /// Reject write and custom (0x78/0x79) function codes
+    #[arg(long)]
+    read_only: bool,
+
+    /// Per-client sustained request rate in requests/second (unlimited if unset)
+    #[arg(long, value_parser = RateLimit::parse_per_second)]
+    rate_limit: Option<f64>,
+
+    /// Per-client burst allowance when --rate-limit is set
+    #[arg(long, default_value_t = 10)]
+    rate_burst: u32,
+}
+
+#[derive(Subcommand)]
+enum TransportCmd {
+    /// Connect via BT-2 Bluetooth adapter
+    Bt2 {
+        #[command(flatten)]
+        bt2: Bt2Args,
+    },
+    /// Connect via serial/RS-485
+    Serial {
+        #[command(flatten)]
+        serial: SerialArgs,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let args = Args::parse();
+
+    let cancel = CancellationToken::new();
+    let cancel_signal = cancel.clone();
+    tokio::spawn(async move {
+        tokio::signal::ctrl_c().await.ok();
+        tracing::info!("Received shutdown signal");
+        cancel_signal.cancel();
+    });
+
+    let transport: AnyTransport = match args.transport {
+        TransportCmd::Bt2 { bt2 } => {
+            let mac_address = match &bt2.mac {
+                Some(mac) => mac.clone(),
+                None => {
+                    tracing::info!("Discovering BT-2 devices on {}...", bt2.adapter);
+                    let devices = bt2.discovery().scan().await?;
+                    bt2.select(&devices)?.address.clone()
+                }
+            };
+
+            tracing::info!("Connecting to {} via {}...", mac_address, bt2.adapter);
+            Bt2Transport::connect_by_address(&mac_address, &bt2.adapter)
+                .await?
+                .into()
+        }
+        TransportCmd::Serial { serial } => {
+            tracing::info!("Opening {} at {} baud...", serial.port, serial.baud_rate);
+            serial.open(0x01).await?
+        }
+    };
+
+    let config = GatewayConfig {
+        read_only: args.read_only,
+        rate_limit: args.rate_limit.map(|per_second| RateLimit {
+            per_second,
+            burst: args.rate_burst.max(1),
+        }),
+    };
+    if config.read_only {
+        tracing::info!("Read-only mode: write requests will be rejected");
+    }
+
+    let listener = TcpListener::bind(args.listen).await?;
+    let transport = TransportHandle::spawn(RetryingTransport::new(transport));
+    Gateway::new(listener, transport, config, cancel)
+        .run()
+        .await?;
+
+    tracing::info!("Shutdown complete");
+    Ok(())
+}
@@ -0,0 +1,104 @@
+//! Token-bucket rate limiting for gateway clients.
+
+use std::time::Instant;
+
+/// Sustained request rate and burst allowance for one client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: u32,
+}
+
+#[derive(Debug)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimit {
+    /// Parse a `--rate-limit` value: a finite number of requests per second above 0.
+    /// A negative rate would drain the bucket and NaN would never refill it, leaving
+    /// every client answered "busy".
+    pub fn parse_per_second(s: &str) -> Result<f64, String> {
+        let per_second: f64 = s.trim().parse().map_err(|e| format!("{e}"))?;
+        if !per_second.is_finite() || per_second <= 0.0 {
+            return Err(format!("{s} is not a positive number of requests/second"));
+        }
+        Ok(per_second)
+    }
+}
+
+impl TokenBucket {
+    /// A full bucket: a new client may send `burst` requests straight away.
+    #[must_use]
+    pub fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: f64::from(limit.burst),
+            last: now,
+        }
+    }
+
+    /// Take one token if available.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last);
+        self.last = now;
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.limit.per_second)
+            .min(f64::from(self.limit.burst));
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RateLimit;
+    use super::TokenBucket;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    #[test]
+    fn allows_burst_then_refills() {
+        let start = Instant::now();
+        let limit = RateLimit {
+            per_second: 2.0,
+            burst: 3,
+        };
+        let mut bucket = TokenBucket::new(limit, start);
+
+        assert!((0..3).all(|_| bucket.try_acquire(start)));
+        assert!(!bucket.try_acquire(start));
+
+        assert!(bucket.try_acquire(start + Duration::from_millis(500)));
+        assert!(!bucket.try_acquire(start + Duration::from_millis(600)));
+    }
+
+    #[test]
+    fn refill_is_capped_at_burst() {
+        let start = Instant::now();
+        let limit = RateLimit {
+            per_second: 10.0,
+            burst: 2,
+        };
+        let mut bucket = TokenBucket::new(limit, start);
+        let later = start + Duration::from_secs(60);
+
+        assert!(bucket.try_acquire(later));
+        assert!(bucket.try_acquire(later));
+        assert!(!bucket.try_acquire(later));
+    }
+
+    #[test]
+    fn rate_must_be_positive_and_finite() {
+        assert_eq!(RateLimit::parse_per_second("2.5"), Ok(2.5));
+        for bad in ["0", "-1", "NaN", "inf", "fast"] {
+            assert!(RateLimit::parse_per_second(bad).is_err(), "{bad}");
+        }
+    }
+}
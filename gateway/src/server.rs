@@ -0,0 +1,361 @@
+//! Modbus TCP server forwarding requests to a shared `Transport`.
+//!
+//! Each client connection gets its own `TransportHandle` clone, so the owning task
+//! serves clients round-robin. Unit ids are passed through as BMS addresses. Failures
+//! are answered with Modbus exception responses rather than by closing the connection.
+
+use crate::rate_limit::RateLimit;
+use crate::rate_limit::TokenBucket;
+use renogy::error::ModbusExceptionCode;
+use renogy::error::RenogyError;
+use renogy::pdu::FunctionCode;
+use renogy::pdu::Pdu;
+use renogy::shared_transport::TransportHandle;
+use renogy::tcp::Adu;
+use renogy::transport::Transport;
+use std::net::SocketAddr;
+use std::time::Instant;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+use tokio::net::TcpStream;
+use tokio_util::sync::CancellationToken;
+
+/// Most registers a single Read Holding Registers request may ask for.
+const MAX_READ_QUANTITY: u16 = 125;
+/// Most registers a single Write Multiple Registers request may carry.
+const MAX_WRITE_QUANTITY: u16 = 123;
+
+#[derive(Debug, Clone, Default)]
+pub struct GatewayConfig {
+    /// Reject write and custom function codes with Illegal Function.
+    pub read_only: bool,
+    /// Per-client limit; requests over it are answered with Slave Device Busy.
+    pub rate_limit: Option<RateLimit>,
+}
+
+pub struct Gateway {
+    listener: TcpListener,
+    transport: TransportHandle,
+    config: GatewayConfig,
+    cancel: CancellationToken,
+}
+
+impl Gateway {
+    pub fn new(
+        listener: TcpListener,
+        transport: TransportHandle,
+        config: GatewayConfig,
+        cancel: CancellationToken,
+    ) -> Self {
+        Self {
+            listener,
+            transport,
+            config,
+            cancel,
+        }
+    }
+
+    pub async fn run(self) -> std::io::Result<()> {
+        tracing::info!(
+            "Modbus TCP gateway listening on {}",
+            self.listener.local_addr()?
+        );
+        loop {
+            let (socket, peer) = tokio::select! {
+                _ = self.cancel.cancelled() => return Ok(()),
+                accepted = self.listener.accept() => accepted?,
+            };
+            tracing::info!("Client {} connected", peer);
+
+            let client = Client {
+                socket,
+                peer,
+                transport: self.transport.clone(),
+                config: self.config.clone(),
+                bucket: self
+                    .config
+                    .rate_limit
+                    .map(|limit| TokenBucket::new(limit, Instant::now())),
+            };
+            let cancel = self.cancel.clone();
+            tokio::spawn(async move {
+                tokio::select! {
+                    _ = cancel.cancelled() => {}
+                    result = client.run() => {
+                        if let Err(e) = result {
+                            tracing::debug!("Client {} error: {}", peer, e);
+                        }
+                        tracing::info!("Client {} disconnected", peer);
+                    }
+                }
+            });
+        }
+    }
+}
+
+struct Client {
+    socket: TcpStream,
+    peer: SocketAddr,
+    transport: TransportHandle,
+    config: GatewayConfig,
+    bucket: Option<TokenBucket>,
+}
+
+impl Client {
+    async fn run(mut self) -> renogy::error::Result<()> {
+        let mut buf = Vec::new();
+        loop {
+            // A malformed MBAP header leaves the stream out of sync; drop the client.
+            while let Some(adu) = Adu::take(&mut buf)? {
+                let body = if self
+                    .bucket
+                    .as_mut()
+                    .is_some_and(|bucket| !bucket.try_acquire(Instant::now()))
+                {
+                    tracing::debug!("Client {} over rate limit", self.peer);
+                    exception(&adu.body, ModbusExceptionCode::SlaveDeviceBusy)
+                } else {
+                    handle_request(&mut self.transport, &adu.body, self.config.read_only).await
+                };
+                let reply = Adu {
+                    transaction_id: adu.transaction_id,
+                    body,
+                };
+                self.socket.write_all(&reply.encode()).await?;
+            }
+
+            let mut chunk = [0u8; 256];
+            let n = self.socket.read(&mut chunk).await?;
+            if n == 0 {
+                return Ok(());
+            }
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/// Answer one request body (unit id, function code, data) through `transport`,
+/// returning the response body.
+pub async fn handle_request(
+    transport: &mut (impl Transport + Send),
+    body: &[u8],
+    read_only: bool,
+) -> Vec<u8> {
+    match forward(transport, body, read_only).await {
+        Ok(response) => response.to_bytes(),
+        Err(code) => exception(body, code),
+    }
+}
+
+async fn forward(
+    transport: &mut (impl Transport + Send),
+    body: &[u8],
+    read_only: bool,
+) -> Result<Pdu, ModbusExceptionCode> {
+    let request = Pdu::from_bytes(body).map_err(|_| ModbusExceptionCode::IllegalFunction)?;
+    if read_only && request.is_write_operation() {
+        return Err(ModbusExceptionCode::IllegalFunction);
+    }
+
+    let slave = request.address;
+    let word = |i: usize| -> Result<u16, ModbusExceptionCode> {
+        request
+            .payload
+            .get(i..i + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or(ModbusExceptionCode::IllegalDataValue)
+    };
+
+    match request.function_code {
+        FunctionCode::ReadHoldingRegisters => {
+            let (addr, quantity) = (word(0)?, word(2)?);
+            if !(1..=MAX_READ_QUANTITY).contains(&quantity) {
+                return Err(ModbusExceptionCode::IllegalDataValue);
+            }
+            let words = transport
+                .read_holding_registers(slave, addr, quantity)
+                .await
+                .map_err(exception_code)?;
+            let mut payload = Vec::with_capacity(1 + words.len() * 2);
+            payload.push((words.len() * 2) as u8);
+            for word in words {
+                payload.extend_from_slice(&word.to_be_bytes());
+            }
+            Ok(Pdu::new(slave, request.function_code, payload))
+        }
+        FunctionCode::WriteSingleRegister => {
+            transport
+                .write_single_register(slave, word(0)?, word(2)?)
+                .await
+                .map_err(exception_code)?;
+            Ok(request.clone())
+        }
+        FunctionCode::WriteMultipleRegisters => {
+            let (addr, quantity) = (word(0)?, word(2)?);
+            let byte_count = *request
+                .payload
+                .get(4)
+                .ok_or(ModbusExceptionCode::IllegalDataValue)?;
+            if !(1..=MAX_WRITE_QUANTITY).contains(&quantity) || byte_count as u16 != quantity * 2 {
+                return Err(ModbusExceptionCode::IllegalDataValue);
+            }
+            let values = (0..quantity as usize)
+                .map(|i| word(5 + 2 * i))
+                .collect::<Result<Vec<_>, _>>()?;
+            transport
+                .write_multiple_registers(slave, addr, &values)
+                .await
+                .map_err(exception_code)?;
+            Ok(Pdu::new(
+                slave,
+                request.function_code,
+                request.payload[..4].to_vec(),
+            ))
+        }
+        FunctionCode::RestoreFactoryDefault | FunctionCode::ClearHistory => {
+            let payload = transport
+                .send_custom(slave, request.function_code as u8, &request.payload)
+                .await
+                .map_err(exception_code)?;
+            Ok(Pdu::new(slave, request.function_code, payload))
+        }
+    }
+}
+
+/// Map a downstream failure to the exception a Modbus gateway would report.
+fn exception_code(err: RenogyError) -> ModbusExceptionCode {
+    match err {
+        RenogyError::ModbusException(code) => code,
+        RenogyError::Timeout => ModbusExceptionCode::GatewayTargetDeviceFailedToRespond,
+        RenogyError::InvalidRegisterRange => ModbusExceptionCode::IllegalDataAddress,
+        RenogyError::UnsupportedOperation => ModbusExceptionCode::IllegalFunction,
+        _ => ModbusExceptionCode::GatewayPathUnavailable,
+    }
+}
+
+fn exception(body: &[u8], code: ModbusExceptionCode) -> Vec<u8> {
+    vec![body[0], body[1] | 0x80, code as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Gateway;
+    use super::GatewayConfig;
+    use super::handle_request;
+    use crate::rate_limit::RateLimit;
+    use renogy::emulator::EmulatedBattery;
+    use renogy::error::ModbusExceptionCode;
+    use renogy::error::RenogyError;
+    use renogy::pdu::Pdu;
+    use renogy::query::query_battery;
+    use renogy::registers::Register;
+    use renogy::shared_transport::TransportHandle;
+    use renogy::tcp::TcpTransport;
+    use renogy::transport::Transport;
+    use std::net::SocketAddr;
+    use tokio::net::TcpListener;
+    use tokio_util::sync::CancellationToken;
+
+    const ADDR: u8 = 0x30;
+
+    async fn spawn_gateway(config: GatewayConfig) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local = listener.local_addr().unwrap();
+        let gateway = Gateway::new(
+            listener,
+            TransportHandle::spawn(EmulatedBattery::lfp_12v(ADDR)),
+            config,
+            CancellationToken::new(),
+        );
+        tokio::spawn(gateway.run());
+        local
+    }
+
+    #[tokio::test]
+    async fn forwards_reads_and_writes() {
+        let mut client = TcpTransport::connect(spawn_gateway(GatewayConfig::default()).await)
+            .await
+            .unwrap();
+        let info = query_battery(&mut client, ADDR).await.expect("info");
+        assert_eq!(info.serial, "SN0030");
+
+        let register = Register::DeviceId.address();
+        client
+            .write_multiple_registers(ADDR, register, &[0x31])
+            .await
+            .unwrap();
+        let regs = client
+            .read_holding_registers(ADDR, register, 1)
+            .await
+            .unwrap();
+        assert_eq!(regs, [0x31]);
+    }
+
+    #[tokio::test]
+    async fn read_only_rejects_writes() {
+        let config = GatewayConfig {
+            read_only: true,
+            ..GatewayConfig::default()
+        };
+        let mut client = TcpTransport::connect(spawn_gateway(config).await)
+            .await
+            .unwrap();
+        let err = client
+            .write_single_register(ADDR, Register::DeviceId.address(), 0x31)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RenogyError::ModbusException(ModbusExceptionCode::IllegalFunction)
+        ));
+        assert!(client.read_holding_registers(ADDR, 5000, 1).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn rate_limited_client_gets_busy() {
+        let config = GatewayConfig {
+            rate_limit: Some(RateLimit {
+                per_second: 0.001,
+                burst: 2,
+            }),
+            ..GatewayConfig::default()
+        };
+        let mut client = TcpTransport::connect(spawn_gateway(config).await)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            assert!(client.read_holding_registers(ADDR, 5000, 1).await.is_ok());
+        }
+        let err = client
+            .read_holding_registers(ADDR, 5000, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RenogyError::ModbusException(ModbusExceptionCode::SlaveDeviceBusy)
+        ));
+    }
+
+    #[tokio::test]
+    async fn malformed_requests_get_exceptions() {
+        let mut bms = EmulatedBattery::lfp_12v(ADDR);
+        let short_read = [ADDR, 0x03, 0x13];
+        assert_eq!(
+            handle_request(&mut bms, &short_read, false).await,
+            [ADDR, 0x83, ModbusExceptionCode::IllegalDataValue as u8]
+        );
+
+        let unknown_function = [ADDR, 0x2B, 0x0E];
+        assert_eq!(
+            handle_request(&mut bms, &unknown_function, false).await,
+            [ADDR, 0xAB, ModbusExceptionCode::IllegalFunction as u8]
+        );
+
+        let oversized = Pdu::read_holding_registers(ADDR, 5000, 200).to_bytes();
+        assert_eq!(
+            handle_request(&mut bms, &oversized, false).await,
+            [ADDR, 0x83, ModbusExceptionCode::IllegalDataValue as u8]
+        );
+    }
+}
@@ -1,8 +1,15 @@
 use crate::bt2::Bt2Transport;
+use crate::discovery::DiscoveredBattery;
+use crate::discovery::DiscoveryOptions;
+use crate::discovery::discover;
 use crate::error::Result;
 use crate::query::BatteryInfo;
 use crate::query::query_battery;
+use crate::recording::ReplayTransport;
+use crate::rtu_serial::RtuSerialTransport;
+use crate::rtu_tcp::RtuOverTcpTransport;
 use crate::serial::SerialTransport;
+use crate::tcp::TcpTransport;
 use crate::transport::Transport;
 use crate::transport::TransportType;
 use async_trait::async_trait;
@@ -10,6 +17,7 @@ use std::ops::RangeInclusive;
 
 pub const BT2_SCAN_RANGE: RangeInclusive<u8> = 0x30..=0x3F;
 pub const SERIAL_SCAN_RANGE: RangeInclusive<u8> = 0x01..=0x10;
+pub const TCP_SCAN_RANGE: RangeInclusive<u8> = 0x01..=0x10;
 
 pub struct AnyTransport(Box<dyn Transport + Send>);
 
@@ -26,19 +34,16 @@ impl AnyTransport {
         match self.0.transport_type() {
             TransportType::Bt2 => BT2_SCAN_RANGE,
             TransportType::Serial => SERIAL_SCAN_RANGE,
+            TransportType::Tcp | TransportType::RtuOverTcp => TCP_SCAN_RANGE,
         }
     }
 
-    pub async fn discover_batteries(&mut self, range: RangeInclusive<u8>) -> Vec<u8> {
-        let mut found = Vec::new();
-        for addr in range {
-            if query_battery(self, addr).await.is_some() {
-                found.push(addr);
-            } else {
-                break;
-            }
-        }
-        found
+    pub async fn discover_batteries(
+        &mut self,
+        range: RangeInclusive<u8>,
+        options: &DiscoveryOptions,
+    ) -> Vec<DiscoveredBattery> {
+        discover(self, range, options).await
     }
 }
 
@@ -86,3 +91,27 @@ impl From<SerialTransport> for AnyTransport {
         AnyTransport::new(t)
     }
 }
+
+impl From<RtuSerialTransport> for AnyTransport {
+    fn from(t: RtuSerialTransport) -> Self {
+        AnyTransport::new(t)
+    }
+}
+
+impl From<TcpTransport> for AnyTransport {
+    fn from(t: TcpTransport) -> Self {
+        AnyTransport::new(t)
+    }
+}
+
+impl From<RtuOverTcpTransport> for AnyTransport {
+    fn from(t: RtuOverTcpTransport) -> Self {
+        AnyTransport::new(t)
+    }
+}
+
+impl From<ReplayTransport> for AnyTransport {
+    fn from(t: ReplayTransport) -> Self {
+        AnyTransport::new(t)
+    }
+}
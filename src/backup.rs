@@ -0,0 +1,511 @@
+//! Backup and restore of a battery's configuration: the charge/discharge limits
+//! requested from the inverter, every protection and warning limit, and the power
+//! settings.
+//!
+//! A backup is a TOML or JSON file of `name = value` settings in the units of the
+//! register catalog (V, A, °C, percent). Restoring one validates every value, writes
+//! only the settings that differ between an unlock and a lock, and reads each back;
+//! if a write fails or does not stick, the settings already written are put back.
+
+use crate::device::DeviceCommand;
+use crate::error::RenogyError;
+use crate::limits::BmsLimits;
+use crate::limits::LimitsError;
+use crate::registers::DataType;
+use crate::registers::Register;
+use crate::registers::Value;
+use crate::snapshot::Snapshot;
+use crate::transport::Transport;
+use crate::util::clean_string;
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use thiserror::Error;
+use uom::si::electric_current::ampere;
+use uom::si::electric_potential::volt;
+use uom::si::f32::ElectricCurrent;
+use uom::si::f32::ElectricPotential;
+use uom::si::f32::ThermodynamicTemperature;
+use uom::si::thermodynamic_temperature::degree_celsius;
+
+/// The registers a backup holds. The slave address, lock, test and ACP registers are
+/// left out: restoring them onto another battery would do harm.
+pub const CONFIG_REGISTERS: [Register; 28] = [
+    Register::ChargeVoltageLimit,
+    Register::DischargeVoltageLimit,
+    Register::ChargeCurrentLimit,
+    Register::DischargeCurrentLimit,
+    Register::CellOverVoltageLimit,
+    Register::CellHighVoltageLimit,
+    Register::CellLowVoltageLimit,
+    Register::CellUnderVoltageLimit,
+    Register::ChargeOverTemperatureLimit,
+    Register::ChargeHighTemperatureLimit,
+    Register::ChargeLowTemperatureLimit,
+    Register::ChargeUnderTemperatureLimit,
+    Register::ChargeOver2CurrentLimit,
+    Register::ChargeOver1CurrentLimit,
+    Register::ChargeHighCurrentLimit,
+    Register::ModuleOverVoltageLimit,
+    Register::ModuleHighVoltageLimit,
+    Register::ModuleLowVoltageLimit,
+    Register::ModuleUnderVoltageLimit,
+    Register::DischargeOverTemperatureLimit,
+    Register::DischargeHighTemperatureLimit,
+    Register::DischargeLowTemperatureLimit,
+    Register::DischargeUnderTemperatureLimit,
+    Register::DischargeOver2CurrentLimit,
+    Register::DischargeOver1CurrentLimit,
+    Register::DischargeHighCurrentLimit,
+    Register::ChargePowerSetting,
+    Register::DischargePowerSetting,
+];
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error(transparent)]
+    Renogy(#[from] RenogyError),
+    #[error(transparent)]
+    Limits(#[from] LimitsError),
+    #[error("{0}")]
+    File(String),
+    #[error("unknown setting {0:?}")]
+    UnknownSetting(String),
+    #[error("{name} = {value} cannot be written to the register")]
+    InvalidValue { name: String, value: f64 },
+    #[error("could not read {0} from the battery")]
+    Unreadable(String),
+    #[error("{name} reads back {read:04X?} after writing {written:04X?}")]
+    NotApplied {
+        name: String,
+        written: Vec<u16>,
+        read: Vec<u16>,
+    },
+    #[error("restore failed at {name} ({cause}); the settings written before it were rolled back")]
+    RolledBack {
+        name: String,
+        cause: Box<BackupError>,
+    },
+    #[error(
+        "restore failed at {name} ({cause}), and rolling back {rollback_name} failed too ({rollback_cause}); the battery is partly restored"
+    )]
+    RollbackFailed {
+        name: String,
+        cause: Box<BackupError>,
+        rollback_name: String,
+        rollback_cause: Box<BackupError>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, BackupError>;
+
+/// A battery's configuration, as saved to and loaded from a file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    pub taken_at: DateTime<Utc>,
+    /// Serial number of the battery it was taken from.
+    pub serial: String,
+    /// Setting name (see `Register::name`) to value, in the catalog unit.
+    pub settings: BTreeMap<String, f64>,
+}
+
+/// One setting `restore` will change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingWrite {
+    pub register: Register,
+    pub name: String,
+    /// Current words, written back on rollback.
+    pub before: Vec<u16>,
+    pub after: Vec<u16>,
+    pub before_value: f64,
+    pub after_value: f64,
+}
+
+impl Backup {
+    /// The configuration registers of a snapshot; all of them must have been read.
+    pub fn from_snapshot(snapshot: &Snapshot) -> Result<Self> {
+        let serial = snapshot
+            .value(&Register::SnNumber)
+            .and_then(|value| value.as_string().map(clean_string))
+            .unwrap_or_default();
+        let settings = CONFIG_REGISTERS
+            .iter()
+            .map(|register| {
+                let value = snapshot
+                    .value(register)
+                    .and_then(|value| setting_value(register, &value))
+                    .ok_or_else(|| BackupError::Unreadable(register.name()))?;
+                Ok((register.name(), value))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            taken_at: snapshot.taken_at,
+            serial,
+            settings,
+        })
+    }
+
+    pub async fn read<T: Transport>(transport: &mut T, slave: u8) -> Result<Self> {
+        Self::from_snapshot(&Snapshot::read(transport, slave).await?)
+    }
+
+    /// Load a backup, as TOML if the file name ends in `.toml` and JSON otherwise.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| BackupError::File(format!("{}: {e}", path.display())))?;
+        let parsed = if is_toml(path) {
+            toml::from_str(&text).map_err(|e| e.to_string())
+        } else {
+            serde_json::from_str(&text).map_err(|e| e.to_string())
+        };
+        parsed.map_err(|e| BackupError::File(format!("{}: {e}", path.display())))
+    }
+
+    /// Save the backup, as TOML if the file name ends in `.toml` and JSON otherwise.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = if is_toml(path) {
+            toml::to_string(self).map_err(|e| e.to_string())
+        } else {
+            serde_json::to_string_pretty(self)
+                .map(|json| json + "\n")
+                .map_err(|e| e.to_string())
+        };
+        text.and_then(|text| std::fs::write(path, text).map_err(|e| e.to_string()))
+            .map_err(|e| BackupError::File(format!("{}: {e}", path.display())))
+    }
+
+    /// Every setting encoded for its register. Fails on the first unknown name or
+    /// value the register cannot hold, so nothing is written from a bad file. A backup
+    /// may leave settings out; those are not touched.
+    pub fn validate(&self) -> Result<Vec<(Register, Vec<u16>)>> {
+        self.settings
+            .iter()
+            .map(|(name, value)| {
+                let register = CONFIG_REGISTERS
+                    .iter()
+                    .find(|register| register.name() == *name)
+                    .ok_or_else(|| BackupError::UnknownSetting(name.clone()))?;
+                let words =
+                    encode_setting(register, *value).ok_or_else(|| BackupError::InvalidValue {
+                        name: name.clone(),
+                        value: *value,
+                    })?;
+                Ok((register.clone(), words))
+            })
+            .collect()
+    }
+
+    /// The writes that would make the battery in `current` match this backup, in
+    /// register order; settings already at their value are skipped.
+    pub fn plan(&self, current: &Snapshot) -> Result<Vec<SettingWrite>> {
+        let mut writes = Vec::new();
+        for (register, after) in self.validate()? {
+            let name = register.name();
+            let (Some(before), Some(before_value)) = (
+                current.raw(&register),
+                current
+                    .value(&register)
+                    .and_then(|value| setting_value(&register, &value)),
+            ) else {
+                return Err(BackupError::Unreadable(name));
+            };
+            if before != after {
+                writes.push(SettingWrite {
+                    after_value: self.settings[&name],
+                    register,
+                    name,
+                    before,
+                    after,
+                    before_value,
+                });
+            }
+        }
+        writes.sort_by_key(|write| write.register.address());
+        Self::check_limits(current, &writes)?;
+        Ok(writes)
+    }
+
+    /// Refuse a plan that would leave the protection limits inconsistent, counting
+    /// the limits the backup leaves out at their current values.
+    fn check_limits(current: &Snapshot, writes: &[SettingWrite]) -> Result<()> {
+        let mut words = current.words.clone();
+        for write in writes {
+            words.extend((write.register.address()..).zip(write.after.iter().copied()));
+        }
+        let limits = BmsLimits::ADDRESSES
+            .map(|address| words.get(&address).copied())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| BackupError::Unreadable("the protection limits".into()))?;
+        Ok(BmsLimits::from_words(&limits)?.validate()?)
+    }
+}
+
+/// Apply `writes` (from `Backup::plan`) to `slave`: unlock, write and read back each
+/// setting, then lock. If a write fails or reads back wrong, every setting written so
+/// far, that one included, is written back to its `before` words. The battery is
+/// locked again whatever happens.
+pub async fn restore<T: Transport>(
+    transport: &mut T,
+    slave: u8,
+    writes: &[SettingWrite],
+) -> Result<()> {
+    DeviceCommand::Unlock.send(transport, slave).await?;
+    let result = apply(transport, slave, writes).await;
+    let locked = DeviceCommand::Lock.send(transport, slave).await;
+    result?;
+    Ok(locked?)
+}
+
+async fn apply<T: Transport>(transport: &mut T, slave: u8, writes: &[SettingWrite]) -> Result<()> {
+    for (i, write) in writes.iter().enumerate() {
+        let Err(cause) =
+            write_verified(transport, slave, &write.name, &write.register, &write.after).await
+        else {
+            continue;
+        };
+        tracing::warn!("Restoring {} failed ({cause}), rolling back", write.name);
+        for done in writes[..=i].iter().rev() {
+            if let Err(rollback_cause) =
+                write_verified(transport, slave, &done.name, &done.register, &done.before).await
+            {
+                return Err(BackupError::RollbackFailed {
+                    name: write.name.clone(),
+                    cause: Box::new(cause),
+                    rollback_name: done.name.clone(),
+                    rollback_cause: Box::new(rollback_cause),
+                });
+            }
+        }
+        return Err(BackupError::RolledBack {
+            name: write.name.clone(),
+            cause: Box::new(cause),
+        });
+    }
+    Ok(())
+}
+
+async fn write_verified<T: Transport>(
+    transport: &mut T,
+    slave: u8,
+    name: &str,
+    register: &Register,
+    words: &[u16],
+) -> Result<()> {
+    let address = register.address();
+    match words {
+        [word] => {
+            transport
+                .write_single_register(slave, address, *word)
+                .await?
+        }
+        _ => {
+            transport
+                .write_multiple_registers(slave, address, words)
+                .await?
+        }
+    }
+    let read = transport
+        .read_holding_registers(slave, address, register.quantity())
+        .await?;
+    if read != words {
+        return Err(BackupError::NotApplied {
+            name: name.to_string(),
+            written: words.to_vec(),
+            read,
+        });
+    }
+    Ok(())
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+/// `value` as a number in the catalog unit, rounded to the register's resolution so
+/// files read `3.7` rather than `3.700000047683716`.
+fn setting_value(register: &Register, value: &Value) -> Option<f64> {
+    let number = match value {
+        Value::ElectricPotential(v) => v.get::<volt>(),
+        Value::ElectricCurrent(c) => c.get::<ampere>(),
+        Value::ThermodynamicTemperature(t) => t.get::<degree_celsius>(),
+        Value::Integer(n) => return Some(f64::from(*n)),
+        _ => return None,
+    };
+    let decimals = (-register.info().scale.log10()).round().max(0.0) as usize;
+    format!("{number:.decimals$}").parse().ok()
+}
+
+/// Register words for `value`, or `None` if the register cannot hold it.
+fn encode_setting(register: &Register, value: f64) -> Option<Vec<u16>> {
+    let value = match register.info().data_type {
+        DataType::Voltage => Value::ElectricPotential(ElectricPotential::new::<volt>(value as f32)),
+        DataType::Current => Value::ElectricCurrent(ElectricCurrent::new::<ampere>(value as f32)),
+        DataType::Temperature => Value::ThermodynamicTemperature(ThermodynamicTemperature::new::<
+            degree_celsius,
+        >(value as f32)),
+        DataType::Integer
+            if value.fract() == 0.0 && (0.0..=f64::from(u32::MAX)).contains(&value) =>
+        {
+            Value::Integer(value as u32)
+        }
+        _ => return None,
+    };
+    let bytes = register.encode_value(&value).ok()?;
+    Some(
+        bytes
+            .chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Backup;
+    use super::BackupError;
+    use super::restore;
+    use crate::emulator::EmulatedBattery;
+    use crate::emulator::Fault;
+    use crate::emulator::FaultyTransport;
+    use crate::limits::LimitsError;
+    use crate::snapshot::Snapshot;
+
+    const LOCK_CONTROL: u16 = 5224;
+
+    #[tokio::test]
+    async fn backup_reads_every_setting() {
+        let backup = Backup::read(&mut EmulatedBattery::lfp_12v(0x30), 0x30)
+            .await
+            .unwrap();
+        assert_eq!(backup.serial, "SN0030");
+        assert_eq!(backup.settings.len(), super::CONFIG_REGISTERS.len());
+        assert_eq!(backup.settings["cell_over_voltage_limit"], 3.7);
+        assert_eq!(backup.settings["discharge_low_temperature_limit"], -10.0);
+        assert_eq!(backup.settings["charge_power_setting"], 100.0);
+    }
+
+    #[tokio::test]
+    async fn backup_files_round_trip() {
+        let backup = Backup::read(&mut EmulatedBattery::lfp_12v(0x30), 0x30)
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["limits.toml", "limits.json"] {
+            let path = dir.path().join(name);
+            backup.save(&path).unwrap();
+            assert_eq!(Backup::load(&path).unwrap(), backup, "{name}");
+        }
+        let toml = std::fs::read_to_string(dir.path().join("limits.toml")).unwrap();
+        assert!(toml.contains("cell_over_voltage_limit = 3.7\n"), "{toml}");
+    }
+
+    #[tokio::test]
+    async fn validate_rejects_bad_files() {
+        let mut backup = Backup::read(&mut EmulatedBattery::lfp_12v(0x30), 0x30)
+            .await
+            .unwrap();
+        backup.settings.insert("device_id".into(), 2.0);
+        assert!(
+            matches!(backup.validate(), Err(BackupError::UnknownSetting(name)) if name == "device_id")
+        );
+        backup.settings.remove("device_id");
+        backup
+            .settings
+            .insert("cell_over_voltage_limit".into(), 5.0);
+        assert!(matches!(
+            backup.validate(),
+            Err(BackupError::InvalidValue { .. })
+        ));
+        backup
+            .settings
+            .insert("cell_over_voltage_limit".into(), 3.65);
+        backup.settings.insert("charge_power_setting".into(), 50.5);
+        assert!(matches!(
+            backup.validate(),
+            Err(BackupError::InvalidValue { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn plan_rejects_inconsistent_limits() {
+        let mut bms = EmulatedBattery::lfp_12v(0x30);
+        let current = Snapshot::read(&mut bms, 0x30).await.unwrap();
+        let mut target = Backup::read(&mut bms, 0x30).await.unwrap();
+        target.settings.clear();
+        target
+            .settings
+            .insert("cell_high_voltage_limit".into(), 3.8);
+        let err = target.plan(&current).unwrap_err();
+        assert!(
+            matches!(err, BackupError::Limits(LimitsError::Inconsistent(_))),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn restore_writes_only_changes_between_unlock_and_lock() {
+        let mut target = Backup::read(&mut EmulatedBattery::lfp_12v(0x30), 0x30)
+            .await
+            .unwrap();
+        target
+            .settings
+            .insert("cell_over_voltage_limit".into(), 3.8);
+        target.settings.insert("charge_current_limit".into(), 40.0);
+
+        let mut link = FaultyTransport::new(EmulatedBattery::lfp_12v(0x30));
+        let current = Snapshot::read(&mut link, 0x30).await.unwrap();
+        let plan = target.plan(&current).unwrap();
+        let names: Vec<_> = plan.iter().map(|write| write.name.as_str()).collect();
+        assert_eq!(names, ["charge_current_limit", "cell_over_voltage_limit"]);
+        assert_eq!(plan[1].before_value, 3.7);
+
+        restore(&mut link, 0x30, &plan).await.unwrap();
+        assert_eq!(
+            link.writes(),
+            [
+                (LOCK_CONTROL, vec![0xA5A5]),
+                (5051, vec![4000]),
+                (5200, vec![38]),
+                (LOCK_CONTROL, vec![0x5A5A]),
+            ]
+        );
+        let after = Backup::read(&mut link, 0x30).await.unwrap();
+        assert_eq!(after.settings, target.settings);
+    }
+
+    #[tokio::test]
+    async fn restore_rolls_back_when_a_write_does_not_stick() {
+        let original = Backup::read(&mut EmulatedBattery::lfp_12v(0x30), 0x30)
+            .await
+            .unwrap();
+        let mut target = original.clone();
+        target.settings.insert("charge_current_limit".into(), 40.0);
+        target
+            .settings
+            .insert("cell_over_voltage_limit".into(), 3.8);
+
+        // The BMS acknowledges the over-voltage write but keeps the old value.
+        let mut link = FaultyTransport::new(EmulatedBattery::lfp_12v(0x30)).on_write(
+            |_, addr, _| match addr {
+                5200 => Fault::Answer(Ok(())),
+                _ => Fault::Pass,
+            },
+        );
+        let plan = target
+            .plan(&Snapshot::read(&mut link, 0x30).await.unwrap())
+            .unwrap();
+        let err = restore(&mut link, 0x30, &plan).await.unwrap_err();
+        assert!(
+            matches!(&err, BackupError::RolledBack { name, cause }
+                if name == "cell_over_voltage_limit"
+                    && matches!(**cause, BackupError::NotApplied { .. })),
+            "{err}"
+        );
+        assert_eq!(link.writes().last(), Some(&(LOCK_CONTROL, vec![0x5A5A])));
+        let after = Backup::read(&mut link, 0x30).await.unwrap();
+        assert_eq!(after.settings, original.settings);
+    }
+}
@@ -0,0 +1,337 @@
+use clap::Parser;
+use clap::Subcommand;
+use renogy::backup::Backup;
+use renogy::backup::restore;
+use renogy::snapshot::Change;
+use renogy::snapshot::ChangeKind;
+use renogy::snapshot::Snapshot;
+use renogy::snapshot::diff;
+use renogy::util::LinkArgs;
+use renogy::util::parse_address;
+use std::io::BufRead;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "bms-registers")]
+#[command(about = "Dump, compare, back up and restore the registers of Renogy BMS batteries")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Read every register and save a timestamped JSON snapshot per battery
+    Dump {
+        /// BMS addresses to dump (default: 0x01 on serial, 0x30 on BT-2)
+        #[arg(short, long, value_parser = parse_address)]
+        bms_addresses: Vec<u8>,
+
+        /// Directory to save snapshots in, or - to print them
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+
+        #[command(subcommand)]
+        link: LinkArgs,
+    },
+    /// Compare two snapshots, a snapshot with a live battery, or two live batteries
+    #[command(subcommand_precedence_over_arg = true)]
+    Diff {
+        /// Snapshot files, the older first
+        #[arg(num_args = 0..=2)]
+        snapshots: Vec<PathBuf>,
+
+        /// Live batteries to read and compare
+        #[arg(short, long, value_parser = parse_address)]
+        bms_addresses: Vec<u8>,
+
+        #[command(subcommand)]
+        link: Option<LinkArgs>,
+    },
+    /// Save a battery's configuration (limits and power settings) to a file
+    Backup {
+        /// BMS address (default: 0x01 on serial, 0x30 on BT-2)
+        #[arg(short, long, value_parser = parse_address)]
+        bms_address: Option<u8>,
+
+        /// File to write: TOML if it ends in .toml, JSON otherwise
+        #[arg(short, long)]
+        output: PathBuf,
+
+        #[command(subcommand)]
+        link: LinkArgs,
+    },
+    /// Write a saved configuration back to a battery, verifying every setting
+    Restore {
+        /// Backup file (.toml or .json)
+        file: PathBuf,
+
+        /// BMS address (default: 0x01 on serial, 0x30 on BT-2)
+        #[arg(short, long, value_parser = parse_address)]
+        bms_address: Option<u8>,
+
+        /// Show the changes without writing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+
+        /// Restore a backup taken from a battery with another serial number
+        #[arg(long)]
+        force: bool,
+
+        #[command(subcommand)]
+        link: LinkArgs,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    match Args::parse().command {
+        Command::Dump {
+            bms_addresses,
+            output,
+            link,
+        } => dump(&link, bms_addresses, &output).await,
+        Command::Diff {
+            snapshots,
+            bms_addresses,
+            link,
+        } => compare(snapshots, bms_addresses, link.as_ref()).await,
+        Command::Backup {
+            bms_address,
+            output,
+            link,
+        } => {
+            let addr = bms_address.unwrap_or_else(|| link.default_address());
+            let backup = Backup::read(&mut link.open().await?, addr).await?;
+            backup.save(&output)?;
+            eprintln!(
+                "Battery 0x{addr:02X} ({}): {} settings saved to {}",
+                backup.serial,
+                backup.settings.len(),
+                output.display()
+            );
+            Ok(())
+        }
+        Command::Restore {
+            file,
+            bms_address,
+            dry_run,
+            yes,
+            force,
+            link,
+        } => {
+            let addr = bms_address.unwrap_or_else(|| link.default_address());
+            restore_file(&link, addr, &file, dry_run, yes, force).await
+        }
+    }
+}
+
+async fn dump(
+    link: &LinkArgs,
+    mut addresses: Vec<u8>,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if addresses.is_empty() {
+        addresses.push(link.default_address());
+    }
+    let mut transport = link.open().await?;
+    let mut failed = 0;
+    for addr in addresses {
+        let snapshot = match Snapshot::read(&mut transport, addr).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                eprintln!("Battery 0x{addr:02X}: {e}");
+                failed += 1;
+                continue;
+            }
+        };
+        let json = serde_json::to_string_pretty(&snapshot)?;
+        if output == Path::new("-") {
+            println!("{json}");
+        } else {
+            let path = output.join(snapshot.file_name());
+            std::fs::write(&path, json + "\n")?;
+            eprintln!(
+                "Battery 0x{addr:02X}: {} words saved to {}",
+                snapshot.words.len(),
+                path.display()
+            );
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{failed} battery(s) could not be read").into());
+    }
+    Ok(())
+}
+
+async fn compare(
+    files: Vec<PathBuf>,
+    addresses: Vec<u8>,
+    link: Option<&LinkArgs>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if files.len() + addresses.len() != 2 {
+        return Err("give two snapshots in all: files, -b addresses, or one of each".into());
+    }
+    let mut snapshots = Vec::new();
+    for path in files {
+        let snapshot: Snapshot = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        snapshots.push((path.display().to_string(), snapshot));
+    }
+    if !addresses.is_empty() {
+        let Some(link) = link else {
+            return Err("-b needs a link: add serial or bt2".into());
+        };
+        let mut transport = link.open().await?;
+        for addr in addresses {
+            let snapshot = Snapshot::read(&mut transport, addr).await?;
+            snapshots.push((format!("battery 0x{addr:02X}"), snapshot));
+        }
+    }
+
+    let [(before_label, before), (after_label, after)] = &snapshots[..] else {
+        unreachable!("two snapshots checked above");
+    };
+    println!("--- {before_label} ({})", before.taken_at);
+    println!("+++ {after_label} ({})", after.taken_at);
+    let changes = diff(before, after);
+    if changes.is_empty() {
+        println!("No differences");
+    }
+    for change in &changes {
+        print_change(change);
+    }
+    Ok(())
+}
+
+async fn restore_file(
+    link: &LinkArgs,
+    addr: u8,
+    file: &Path,
+    dry_run: bool,
+    yes: bool,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let backup = Backup::load(file)?;
+    backup.validate()?;
+    let mut transport = link.open().await?;
+    let current = Snapshot::read(&mut transport, addr).await?;
+    let live = Backup::from_snapshot(&current)?;
+    if live.serial != backup.serial {
+        let message = format!(
+            "{} was taken from battery {:?}, but 0x{addr:02X} is {:?}",
+            file.display(),
+            backup.serial,
+            live.serial
+        );
+        if !force {
+            return Err(format!("{message}; pass --force to restore it anyway").into());
+        }
+        eprintln!("Warning: {message}");
+    }
+
+    let writes = backup.plan(&current)?;
+    if writes.is_empty() {
+        println!("Battery 0x{addr:02X} already matches {}", file.display());
+        return Ok(());
+    }
+    for write in &writes {
+        println!(
+            "{:>5} {:<34} {} -> {}",
+            write.register.address(),
+            write.name,
+            write.before_value,
+            write.after_value
+        );
+    }
+    if dry_run {
+        return Ok(());
+    }
+    if !yes {
+        eprint!(
+            "Write {} setting(s) to battery 0x{addr:02X}? [y/N] ",
+            writes.len()
+        );
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().lock().read_line(&mut answer)?;
+        if !matches!(answer.trim(), "y" | "Y" | "yes") {
+            return Err("aborted".into());
+        }
+    }
+    restore(&mut transport, addr, &writes).await?;
+    eprintln!(
+        "Battery 0x{addr:02X}: {} setting(s) restored and verified",
+        writes.len()
+    );
+    Ok(())
+}
+
+fn print_change(change: &Change) {
+    let marker = match change.kind {
+        ChangeKind::Limit => "LIMIT",
+        ChangeKind::Flags => "FLAGS",
+        ChangeKind::Reading => "",
+    };
+    println!(
+        "{marker:>5} {:>5} {:<34} {} -> {}",
+        change.address,
+        change.name,
+        change.before.as_deref().unwrap_or("(not read)"),
+        change.after.as_deref().unwrap_or("(not read)")
+    );
+    if !change.flags.is_empty() {
+        println!("{:>5} {:>5} {:<34} {}", "", "", "", change.flags.join(" "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Args;
+    use super::Command;
+    use clap::Parser;
+    use renogy::util::LinkArgs;
+    use std::path::PathBuf;
+
+    fn diff(args: &[&str]) -> (Vec<PathBuf>, Vec<u8>, Option<LinkArgs>) {
+        let args = Args::try_parse_from([&["bms-registers", "diff"], args].concat()).unwrap();
+        let Command::Diff {
+            snapshots,
+            bms_addresses,
+            link,
+        } = args.command
+        else {
+            panic!("not diff");
+        };
+        (snapshots, bms_addresses, link)
+    }
+
+    #[test]
+    fn diff_link_is_not_taken_for_a_snapshot() {
+        for args in [
+            &["old.json", "-b", "0x30", "serial", "--port", "/dev/ttyUSB0"][..],
+            &["-b", "0x30", "old.json", "serial", "--port", "/dev/ttyUSB0"],
+        ] {
+            let (snapshots, addresses, link) = diff(args);
+            assert_eq!(snapshots, [PathBuf::from("old.json")], "{args:?}");
+            assert_eq!(addresses, [0x30]);
+            assert!(
+                matches!(link, Some(LinkArgs::Serial(serial)) if serial.port == "/dev/ttyUSB0"),
+                "{args:?}"
+            );
+        }
+
+        let (snapshots, addresses, link) = diff(&["old.json", "new.json"]);
+        assert_eq!(snapshots, ["old.json", "new.json"].map(PathBuf::from));
+        assert!(addresses.is_empty());
+        assert!(link.is_none());
+    }
+}
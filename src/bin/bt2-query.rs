@@ -1,7 +1,7 @@
 use clap::Parser;
 use renogy::bt2::Bt2Transport;
-use renogy::bt2::discover_bt2_devices;
 use renogy::query::query_battery;
+use renogy::util::Bt2Args;
 use renogy::util::parse_address;
 use renogy::util::print_battery_info;
 
@@ -9,13 +9,8 @@ use renogy::util::print_battery_info;
 #[command(name = "bt2-query")]
 #[command(about = "Query Renogy BMS batteries via BT-2 Bluetooth adapter")]
 struct Args {
-    /// BT-2 MAC address (e.g., FD:86:6D:73:XX:XX). If not specified, discovers and uses the first BT-2 found.
-    #[arg(short, long)]
-    mac: Option<String>,
-
-    /// Bluetooth adapter name
-    #[arg(short, long, default_value = "hci0")]
-    adapter: String,
+    #[command(flatten)]
+    bt2: Bt2Args,
 
     /// BMS addresses to scan (hex values like 0x30 or decimal)
     #[arg(short = 'b', long, value_parser = parse_address, default_values_t = vec![0x30, 0x31, 0x32, 0x33])]
@@ -26,28 +21,36 @@ struct Args {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
-    let mac_address = if let Some(mac) = args.mac {
-        mac
-    } else {
-        println!("Discovering BT-2 devices...");
-        let devices = discover_bt2_devices().await?;
-        if devices.is_empty() {
-            eprintln!("No BT-2 devices found. Specify a MAC address with --mac");
-            std::process::exit(1);
-        }
-        for device in &devices {
-            println!(
-                "  Found: {} ({})",
-                device.name.as_deref().unwrap_or("unknown"),
-                device.address
-            );
+    let mac_address = match &args.bt2.mac {
+        Some(mac) => mac.clone(),
+        None => {
+            println!("Discovering BT-2 devices on {}...", args.bt2.adapter);
+            let devices = args.bt2.discovery().scan().await?;
+            for device in &devices {
+                println!(
+                    "  Found: {} ({}) RSSI {} paired={} connected={}",
+                    device.alias,
+                    device.address,
+                    device
+                        .rssi
+                        .map_or("unknown".to_string(), |rssi| format!("{rssi} dBm")),
+                    device.paired,
+                    device.connected
+                );
+            }
+            match args.bt2.select(&devices) {
+                Ok(device) => device.address.clone(),
+                Err(e) => {
+                    eprintln!("{e}. Specify one with --alias or --mac");
+                    std::process::exit(1);
+                }
+            }
         }
-        devices[0].address.clone()
     };
 
-    println!("Connecting to {} via {}...", mac_address, args.adapter);
+    println!("Connecting to {} via {}...", mac_address, args.bt2.adapter);
 
-    let mut transport = Bt2Transport::connect_by_address(&mac_address, &args.adapter).await?;
+    let mut transport = Bt2Transport::connect_by_address(&mac_address, &args.bt2.adapter).await?;
     println!("Connected!\n");
 
     println!("Scanning for batteries...\n");
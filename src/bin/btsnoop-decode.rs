@@ -0,0 +1,38 @@
+use clap::Parser;
+use renogy::btsnoop;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "btsnoop-decode")]
+#[command(about = "Decode BT-2 Modbus traffic from an Android btsnoop HCI log")]
+struct Args {
+    /// btsnoop trace (e.g. traces/renogy-bt.btsnoop)
+    trace: PathBuf,
+
+    /// Also write the session as a JSON Lines replay fixture
+    #[arg(short, long)]
+    fixture: Option<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let transactions = btsnoop::parse(&std::fs::read(&args.trace)?)?;
+    print!("{}", btsnoop::dump(&transactions));
+
+    if let Some(path) = args.fixture {
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let exchanges = btsnoop::to_exchanges(&transactions);
+        for exchange in &exchanges {
+            serde_json::to_writer(&mut writer, exchange)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        eprintln!("Wrote {} exchanges to {}", exchanges.len(), path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,179 @@
+use clap::Parser;
+use renogy::device::DeviceCommand;
+use renogy::error::ModbusExceptionCode;
+use renogy::error::RenogyError;
+use renogy::registers::Register;
+use renogy::transport::Transport;
+use renogy::util::LinkArgs;
+use renogy::util::clean_string;
+use renogy::util::parse_address;
+use std::io::BufRead;
+use std::io::Write;
+
+#[derive(Parser)]
+#[command(name = "renogy-ctl")]
+#[command(about = "Send device control commands to a Renogy BMS over BT-2 or serial")]
+#[command(subcommand_value_name = "LINK")]
+struct Args {
+    /// Command to send
+    #[arg(value_enum, value_name = "ACTION")]
+    action: Action,
+
+    /// BMS address (default: 0x01 on serial, 0x30 on BT-2; required for --dry-run
+    /// without a link)
+    #[arg(short, long, value_parser = parse_address)]
+    bms_address: Option<u8>,
+
+    /// Print the frames that would be sent, without connecting (no link needed)
+    #[arg(long)]
+    dry_run: bool,
+
+    /// The battery's serial number, instead of typing it when asked
+    #[arg(long)]
+    confirm_serial: Option<String>,
+
+    #[command(subcommand)]
+    link: Option<LinkArgs>,
+}
+
+/// The `DeviceCommand`s, as named on the command line.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Action {
+    /// Restore factory default settings
+    RestoreFactoryDefault,
+    /// Clear stored history data
+    ClearHistory,
+    /// Shutdown device
+    Shutdown,
+    /// Lock device (prevent configuration changes)
+    Lock,
+    /// Unlock device (allow configuration changes)
+    Unlock,
+    /// Begin test mode
+    TestBegin,
+    /// End test mode
+    TestEnd,
+}
+
+impl From<Action> for DeviceCommand {
+    fn from(action: Action) -> Self {
+        match action {
+            Action::RestoreFactoryDefault => DeviceCommand::RestoreFactoryDefault,
+            Action::ClearHistory => DeviceCommand::ClearHistory,
+            Action::Shutdown => DeviceCommand::Shutdown,
+            Action::Lock => DeviceCommand::Lock,
+            Action::Unlock => DeviceCommand::Unlock,
+            Action::TestBegin => DeviceCommand::TestBegin,
+            Action::TestEnd => DeviceCommand::TestEnd,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let command = DeviceCommand::from(args.action);
+    let addr = match (args.bms_address, &args.link) {
+        (Some(addr), _) => addr,
+        (None, Some(link)) => link.default_address(),
+        (None, None) => return Err("give a link (serial or bt2), or -b with --dry-run".into()),
+    };
+
+    if args.dry_run {
+        for step in command.sequence() {
+            let frame = step.create_pdu(addr).serialize();
+            let hex: Vec<_> = frame.iter().map(|byte| format!("{byte:02X}")).collect();
+            println!("{:<21} {}", format!("{step:?}"), hex.join(" "));
+        }
+        return Ok(());
+    }
+
+    let Some(mut link) = args.link else {
+        return Err("give a link: serial or bt2".into());
+    };
+    // tokio-modbus's RTU codec refuses the custom function codes (0x78, 0x79) before
+    // they reach the wire, so those commands always go through the built-in framer.
+    if let LinkArgs::Serial(serial) = &mut link
+        && command.register_write().is_none()
+    {
+        serial.native_rtu = true;
+    }
+    let mut transport = link.open().await?;
+    if command.is_destructive() {
+        confirm(
+            &mut transport,
+            addr,
+            &command,
+            args.confirm_serial.as_deref(),
+        )
+        .await?;
+    }
+    match command.send(&mut transport, addr).await {
+        Ok(()) => {
+            println!("Battery 0x{addr:02X}: {command:?} acknowledged");
+            Ok(())
+        }
+        Err(e) => Err(explain(&command, &e).into()),
+    }
+}
+
+/// Read the battery's serial number and require it typed back (or given with
+/// `--confirm-serial`) before a destructive command.
+async fn confirm<T: Transport>(
+    transport: &mut T,
+    addr: u8,
+    command: &DeviceCommand,
+    given: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let register = Register::SnNumber;
+    let words = transport
+        .read_holding_registers(addr, register.address(), register.quantity())
+        .await
+        .map_err(|e| format!("could not read the serial number of 0x{addr:02X}: {e}"))?;
+    let value = register.parse_registers(&words)?;
+    let serial = value.as_string().map(clean_string).unwrap_or_default();
+    if serial.is_empty() {
+        return Err(format!("battery 0x{addr:02X} reports no serial number; nothing sent").into());
+    }
+
+    let answer = match given {
+        Some(answer) => answer.to_string(),
+        None => {
+            eprint!(
+                "{command:?} battery 0x{addr:02X} ({serial})? Type its serial number to confirm: "
+            );
+            std::io::stderr().flush()?;
+            let mut answer = String::new();
+            std::io::stdin().lock().read_line(&mut answer)?;
+            answer
+        }
+    };
+    if answer.trim() != serial {
+        return Err(format!("that is not {serial}; nothing sent").into());
+    }
+    Ok(())
+}
+
+fn explain(command: &DeviceCommand, error: &RenogyError) -> String {
+    let hint = match error {
+        RenogyError::ModbusException(ModbusExceptionCode::IllegalFunction) => {
+            "the battery does not support this command"
+        }
+        RenogyError::ModbusException(ModbusExceptionCode::IllegalDataAddress) => {
+            "the battery has no such register"
+        }
+        RenogyError::ModbusException(ModbusExceptionCode::IllegalDataValue) => {
+            "the battery refused the value; it may still be locked"
+        }
+        RenogyError::ModbusException(ModbusExceptionCode::SlaveDeviceBusy) => {
+            "the battery is busy; try again"
+        }
+        RenogyError::ModbusException(ModbusExceptionCode::SlaveDeviceFailure) => {
+            "the battery could not carry it out"
+        }
+        RenogyError::DeviceControlFailed => "the reply was not an echo of the request",
+        RenogyError::Timeout => "no reply; check the address and the link",
+        _ => return format!("{command:?} failed: {error}"),
+    };
+    format!("{command:?} failed: {error} ({hint})")
+}
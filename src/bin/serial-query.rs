@@ -1,33 +1,89 @@
 use clap::Parser;
+use clap::Subcommand;
+use renogy::any_transport::SERIAL_SCAN_RANGE;
+use renogy::discovery::DEFAULT_PROBE_TIMEOUT;
 use renogy::query::query_battery;
-use renogy::serial::SerialTransport;
+use renogy::registers::catalog_json;
+use renogy::serial_scan::COMMON_BAUD_RATES;
+use renogy::serial_scan::ScanReport;
+use renogy::serial_scan::available_ports;
+use renogy::serial_scan::scan_port;
+use renogy::util::SerialArgs;
 use renogy::util::parse_address;
 use renogy::util::print_battery_info;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "serial-query")]
 #[command(about = "Query Renogy BMS batteries via serial/RS-485")]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Serial port path (e.g., /dev/ttyUSB0 or COM3)
-    #[arg(short, long)]
-    port: String,
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    /// Baud rate
-    #[arg(short = 'r', long, default_value_t = 9600)]
-    baud_rate: u32,
+    #[command(flatten)]
+    serial: Option<SerialArgs>,
 
     /// BMS addresses to scan (hex values like 0x01 or decimal)
     #[arg(short, long, value_parser = parse_address, default_values_t = vec![0x01, 0x02, 0x03, 0x04])]
     bms_addresses: Vec<u8>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Find ports, baud rates and addresses with batteries; prints a JSON report
+    Scan {
+        /// Port to probe; repeatable (default: every USB serial port)
+        #[arg(short, long)]
+        port: Vec<String>,
+
+        /// Probe every enumerated port, not just USB adapters
+        #[arg(long)]
+        all_ports: bool,
+
+        /// Baud rates to try, in order
+        #[arg(short = 'r', long, value_delimiter = ',', default_values_t = COMMON_BAUD_RATES)]
+        baud_rates: Vec<u32>,
+
+        /// Per-address response timeout in milliseconds
+        #[arg(long, default_value_t = DEFAULT_PROBE_TIMEOUT.as_millis() as u64)]
+        timeout_ms: u64,
+    },
+    /// Print the register catalog (addresses, types, units, ranges) as JSON
+    Registers,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
 
-    println!("Opening {} at {} baud...", args.port, args.baud_rate);
-    let mut transport =
-        SerialTransport::new(&args.port, args.baud_rate, args.bms_addresses[0]).await?;
+    match args.command {
+        Some(Command::Scan {
+            port,
+            all_ports,
+            baud_rates,
+            timeout_ms,
+        }) => {
+            return scan(
+                port,
+                all_ports,
+                &baud_rates,
+                Duration::from_millis(timeout_ms),
+            )
+            .await;
+        }
+        Some(Command::Registers) => {
+            println!("{}", catalog_json());
+            return Ok(());
+        }
+        None => {}
+    }
+    let Some(serial) = args.serial else {
+        return Err("--port is required (or use the scan subcommand)".into());
+    };
+
+    println!("Opening {} at {} baud...", serial.port, serial.baud_rate);
+    let mut transport = serial.open(args.bms_addresses[0]).await?;
     println!("Connected!\n");
 
     println!(
@@ -43,3 +99,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+async fn scan(
+    paths: Vec<String>,
+    all_ports: bool,
+    baud_rates: &[u32],
+    probe_timeout: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut report = ScanReport {
+        ports: available_ports()?,
+        ..ScanReport::default()
+    };
+    let candidates = if paths.is_empty() {
+        report
+            .ports
+            .iter()
+            .filter(|port| all_ports || port.is_usb())
+            .map(|port| port.path.clone())
+            .collect()
+    } else {
+        paths
+    };
+
+    for path in candidates {
+        eprintln!("Probing {}...", path);
+        match scan_port(&path, baud_rates, SERIAL_SCAN_RANGE, probe_timeout).await {
+            Ok(Some(result)) => {
+                eprintln!(
+                    "  {} battery(s) at {} baud",
+                    result.batteries.len(),
+                    result.baud_rate
+                );
+                report.found.push(result);
+            }
+            Ok(None) => eprintln!("  no answer"),
+            Err(e) => eprintln!("  cannot open: {}", e),
+        }
+    }
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
@@ -2,6 +2,9 @@ use crate::error::RenogyError;
 use crate::error::Result;
 use crate::pdu::FunctionCode;
 use crate::pdu::Pdu;
+use crate::retry::RetryPolicy;
+use crate::rtu::FrameAssembler;
+use crate::rtu::is_response_to;
 use crate::transport::Transport;
 use crate::transport::TransportType;
 use async_trait::async_trait;
@@ -10,48 +13,94 @@ use bluebus::DeviceProxy;
 use bluebus::GattCharacteristic1Proxy;
 use bluebus::ObjectManagerProxy;
 use futures::StreamExt;
+use regex::Regex;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::sync::watch;
 use tokio::task::AbortHandle;
 use tokio::time::timeout;
 use zbus::Connection;
+use zbus::fdo::PropertiesChangedStream;
+use zbus::fdo::PropertiesProxy;
+use zbus::proxy::PropertyStream;
 
 const BT2_NAME_PREFIX: &str = "BT-TH-";
-const BT2_WRITE_CHAR_UUID: &str = "0000ffd1-0000-1000-8000-00805f9b34fb";
-const BT2_NOTIFY_CHAR_UUID: &str = "0000fff1-0000-1000-8000-00805f9b34fb";
+pub(crate) const BT2_WRITE_CHAR_UUID: &str = "0000ffd1-0000-1000-8000-00805f9b34fb";
+pub(crate) const BT2_NOTIFY_CHAR_UUID: &str = "0000fff1-0000-1000-8000-00805f9b34fb";
+
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Backoff between reconnect attempts after the BLE link drops.
+const RECONNECT_BACKOFF: RetryPolicy = RetryPolicy {
+    max_retries: u32::MAX,
+    initial_backoff: Duration::from_secs(1),
+    max_backoff: Duration::from_secs(30),
+};
+
+/// State of the BLE link, as tracked by the supervisor task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    /// The link dropped; `attempt` counts failed reconnects since then.
+    Reconnecting {
+        attempt: u32,
+    },
+}
 
-const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// One established link: notifications started, characteristics resolved.
+///
+/// The streams are subscribed before the session is handed out, so nothing the
+/// module sends once the link reports connected can be missed.
+struct Session {
+    /// PropertiesChanged on the notify characteristic, read as raw signals: a proxy's
+    /// property stream only yields the latest value, so notifications arriving in a
+    /// burst would be lost.
+    notifications: PropertiesChangedStream,
+    connected_changed: PropertyStream<'static, bool>,
+    write_char_path: String,
+}
 
 /// BT-2 Bluetooth transport for communicating with Renogy BMS devices.
+///
+/// A supervisor task forwards notifications and watches the link. When BlueZ reports
+/// the device disconnected, or the notification stream ends, it reconnects with
+/// backoff, re-resolves the GATT characteristics and restarts notifications. Requests
+/// issued meanwhile wait for the link (up to the response timeout).
 pub struct Bt2Transport {
     connection: Arc<Connection>,
-    write_char_path: String,
+    write_char_path: Arc<Mutex<String>>,
     notify_rx: mpsc::Receiver<Vec<u8>>,
+    assembler: FrameAssembler,
+    state_rx: watch::Receiver<ConnectionState>,
     timeout: Duration,
-    listener_handle: AbortHandle,
+    supervisor_handle: AbortHandle,
 }
 
 impl Bt2Transport {
     pub async fn connect(device_path: &str) -> Result<Self> {
-        let connection = Arc::new(bluebus::get_system_connection().await?);
-
-        let device = DeviceProxy::builder(&connection)
-            .path(device_path)?
-            .build()
-            .await?;
-
-        if !device.connected().await? {
-            device.connect().await?;
-            Self::wait_for_services(&device).await?;
-        }
+        Self::connect_with(bluebus::get_system_connection().await?, device_path).await
+    }
 
-        let (write_char_path, notify_char_path) =
-            Self::find_characteristics(&connection, device_path).await?;
+    /// Connect over an existing D-Bus connection, e.g. to a mock BlueZ in tests.
+    pub async fn connect_with(connection: Connection, device_path: &str) -> Result<Self> {
+        let connection = Arc::new(connection);
+        let session = Self::open_session(&connection, device_path).await?;
 
+        let write_char_path = Arc::new(Mutex::new(session.write_char_path.clone()));
+        let (state_tx, state_rx) = watch::channel(ConnectionState::Connected);
         let (tx, notify_rx) = mpsc::channel(16);
-        let listener_handle =
-            Self::spawn_notification_listener(Arc::clone(&connection), notify_char_path, tx);
+        let supervisor_handle = tokio::spawn(Self::supervise(
+            Arc::clone(&connection),
+            device_path.to_string(),
+            session,
+            Arc::clone(&write_char_path),
+            tx,
+            state_tx,
+        ))
+        .abort_handle();
 
         tokio::time::sleep(Duration::from_millis(100)).await;
 
@@ -59,8 +108,10 @@ impl Bt2Transport {
             connection,
             write_char_path,
             notify_rx,
+            assembler: FrameAssembler::responses(),
+            state_rx,
             timeout: DEFAULT_TIMEOUT,
-            listener_handle,
+            supervisor_handle,
         })
     }
 
@@ -73,6 +124,53 @@ impl Bt2Transport {
         self.timeout = timeout;
     }
 
+    #[must_use]
+    pub fn connection_state(&self) -> ConnectionState {
+        *self.state_rx.borrow()
+    }
+
+    /// Receiver that observes every connection state change.
+    #[must_use]
+    pub fn subscribe_connection_state(&self) -> watch::Receiver<ConnectionState> {
+        self.state_rx.clone()
+    }
+
+    async fn open_session(connection: &Connection, device_path: &str) -> Result<Session> {
+        let device = DeviceProxy::builder(connection)
+            .path(device_path.to_string())?
+            .build()
+            .await?;
+
+        if !device.connected().await? {
+            device.connect().await?;
+            Self::wait_for_services(&device).await?;
+        }
+
+        let (write_char_path, notify_char_path) =
+            Self::find_characteristics(connection, device_path).await?;
+
+        let mut notify_char = GattCharacteristic1Proxy::builder(connection)
+            .destination("org.bluez")?
+            .path(notify_char_path.clone())?
+            .build()
+            .await?;
+        let notifications = PropertiesProxy::builder(connection)
+            .destination("org.bluez")?
+            .path(notify_char_path)?
+            .build()
+            .await?
+            .receive_properties_changed()
+            .await?;
+        let connected_changed = device.receive_connected_changed().await;
+        notify_char.start_notify().await?;
+
+        Ok(Session {
+            notifications,
+            connected_changed,
+            write_char_path,
+        })
+    }
+
     async fn wait_for_services(device: &DeviceProxy<'_>) -> Result<()> {
         for _ in 0..50 {
             if device.services_resolved().await? {
@@ -129,45 +227,75 @@ impl Bt2Transport {
             .ok_or_else(|| RenogyError::Bluetooth("BT-2 characteristics not found".into()))
     }
 
-    fn spawn_notification_listener(
+    /// Forward notifications while the link is up; re-establish it whenever it drops.
+    async fn supervise(
         connection: Arc<Connection>,
-        notify_path: String,
+        device_path: String,
+        mut session: Session,
+        write_char_path: Arc<Mutex<String>>,
         tx: mpsc::Sender<Vec<u8>>,
-    ) -> AbortHandle {
-        tokio::spawn(async move {
-            let Ok(proxy) = GattCharacteristic1Proxy::builder(&connection)
-                .destination("org.bluez")
-                .and_then(|b| b.path(notify_path.as_str()))
-                .map(zbus::proxy::Builder::build)
-            else {
-                return;
-            };
-            let Ok(mut char) = proxy.await else {
-                return;
-            };
-
-            if char.start_notify().await.is_err() {
+        state_tx: watch::Sender<ConnectionState>,
+    ) {
+        loop {
+            Self::forward_notifications(&mut session, &tx).await;
+            if tx.is_closed() {
                 return;
             }
+            tracing::warn!("BT-2 link to {} lost, reconnecting", device_path);
+
+            let mut attempt = 0;
+            session = loop {
+                state_tx.send_replace(ConnectionState::Reconnecting { attempt });
+                tokio::time::sleep(RECONNECT_BACKOFF.backoff(attempt + 1)).await;
+                match Self::open_session(&connection, &device_path).await {
+                    Ok(session) => break session,
+                    Err(e) => {
+                        attempt += 1;
+                        tracing::debug!("BT-2 reconnect attempt {} failed: {}", attempt, e);
+                    }
+                }
+            };
 
-            let mut value_changed = char.receive_value_changed().await;
+            tracing::info!("BT-2 link to {} re-established", device_path);
+            *write_char_path.lock().unwrap() = session.write_char_path.clone();
+            state_tx.send_replace(ConnectionState::Connected);
+        }
+    }
 
-            while let Some(signal) = value_changed.next().await {
-                if let Ok(value) = signal.get().await
-                    && let Some(data) = value.as_ref()
-                    && !data.is_empty()
-                {
-                    let _ = tx.send(data.clone()).await;
+    /// Returns once BlueZ reports the device disconnected or either stream ends.
+    async fn forward_notifications(session: &mut Session, tx: &mpsc::Sender<Vec<u8>>) {
+        loop {
+            tokio::select! {
+                signal = session.notifications.next() => {
+                    let Some(signal) = signal else {
+                        return;
+                    };
+                    if let Ok(args) = signal.args()
+                        && args.interface_name == "org.bluez.GattCharacteristic1"
+                        && let Some(value) = args.changed_properties.get("Value")
+                        && let Ok(data) = value.try_clone().and_then(Vec::<u8>::try_from)
+                        && !data.is_empty()
+                        && tx.send(data).await.is_err()
+                    {
+                        return;
+                    }
+                }
+                change = session.connected_changed.next() => {
+                    let Some(change) = change else {
+                        return;
+                    };
+                    if !change.get().await.unwrap_or(false) {
+                        return;
+                    }
                 }
             }
-        })
-        .abort_handle()
+        }
     }
 }
 
 impl Drop for Bt2Transport {
     fn drop(&mut self) {
-        self.listener_handle.abort();
+        self.supervisor_handle.abort();
     }
 }
 
@@ -175,11 +303,22 @@ impl Bt2Transport {
     async fn send_pdu(&mut self, pdu: &Pdu) -> Result<Pdu> {
         let frame = pdu.serialize();
 
+        timeout(
+            self.timeout,
+            self.state_rx
+                .wait_for(|state| *state == ConnectionState::Connected),
+        )
+        .await
+        .map_err(|_| RenogyError::Timeout)?
+        .map_err(|_| RenogyError::Bluetooth("connection supervisor stopped".into()))?;
+
         while self.notify_rx.try_recv().is_ok() {}
+        self.assembler.clear();
 
+        let write_char_path = self.write_char_path.lock().unwrap().clone();
         let mut write_char = GattCharacteristic1Proxy::builder(&self.connection)
-            .destination("org.bluez")
-            .and_then(|b| b.path(self.write_char_path.as_str()))?
+            .destination("org.bluez")?
+            .path(write_char_path)?
             .build()
             .await?;
 
@@ -187,13 +326,33 @@ impl Bt2Transport {
             .write_value(frame, std::collections::HashMap::new())
             .await?;
 
-        let response = timeout(self.timeout, self.notify_rx.recv())
+        let response = timeout(self.timeout, self.receive(pdu))
             .await
-            .map_err(|_| RenogyError::Bluetooth("timeout waiting for response".into()))?
-            .ok_or_else(|| RenogyError::Bluetooth("notification channel closed".into()))?;
+            .map_err(|_| RenogyError::Timeout)??;
 
         Pdu::deserialize(&response)
     }
+
+    /// Reassemble notifications until a frame answering `request` arrives. A response
+    /// may span several notifications; frames from other slaves or for other function
+    /// codes are late answers to earlier requests and are dropped.
+    async fn receive(&mut self, request: &Pdu) -> Result<Vec<u8>> {
+        loop {
+            while let Some(frame) = self.assembler.next_frame() {
+                if is_response_to(&frame, request) {
+                    return Ok(frame);
+                }
+                tracing::debug!("Discarding stale BT-2 frame {:02X?}", frame);
+            }
+
+            let chunk = self
+                .notify_rx
+                .recv()
+                .await
+                .ok_or_else(|| RenogyError::Bluetooth("notification channel closed".into()))?;
+            self.assembler.push(&chunk);
+        }
+    }
 }
 
 #[async_trait]
@@ -204,30 +363,13 @@ impl Transport for Bt2Transport {
         addr: u16,
         quantity: u16,
     ) -> Result<Vec<u16>> {
-        let payload = [addr.to_be_bytes(), quantity.to_be_bytes()].concat();
-        let response = self
-            .send_pdu(&Pdu::new(
-                slave,
-                FunctionCode::ReadHoldingRegisters,
-                payload,
-            ))
-            .await?;
-
-        let byte_count = *response.payload.first().ok_or(RenogyError::InvalidData)? as usize;
-        if response.payload.len() < 1 + byte_count {
-            return Err(RenogyError::InvalidData);
-        }
-
-        Ok(response.payload[1..]
-            .chunks_exact(2)
-            .take(quantity as usize)
-            .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
-            .collect())
+        self.send_pdu(&Pdu::read_holding_registers(slave, addr, quantity))
+            .await?
+            .register_values(quantity)
     }
 
     async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
-        let payload = [addr.to_be_bytes(), value.to_be_bytes()].concat();
-        self.send_pdu(&Pdu::new(slave, FunctionCode::WriteSingleRegister, payload))
+        self.send_pdu(&Pdu::write_single_register(slave, addr, value))
             .await?;
         Ok(())
     }
@@ -238,20 +380,8 @@ impl Transport for Bt2Transport {
         addr: u16,
         values: &[u16],
     ) -> Result<()> {
-        let mut payload = Vec::with_capacity(5 + values.len() * 2);
-        payload.extend_from_slice(&addr.to_be_bytes());
-        payload.extend_from_slice(&(values.len() as u16).to_be_bytes());
-        payload.push((values.len() * 2) as u8);
-        for value in values {
-            payload.extend_from_slice(&value.to_be_bytes());
-        }
-
-        self.send_pdu(&Pdu::new(
-            slave,
-            FunctionCode::WriteMultipleRegisters,
-            payload,
-        ))
-        .await?;
+        self.send_pdu(&Pdu::write_multiple_registers(slave, addr, values))
+            .await?;
         Ok(())
     }
 
@@ -270,34 +400,446 @@ impl Transport for Bt2Transport {
 
 const DEFAULT_SCAN_DURATION: Duration = Duration::from_secs(5);
 
-pub async fn discover_bt2_devices() -> Result<Vec<bluebus::DeviceInfo>> {
-    discover_bt2_devices_with_options("hci0", DEFAULT_SCAN_DURATION).await
+/// A BT-2 seen by BlueZ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bt2Device {
+    pub address: String,
+    pub adapter: String,
+    /// Advertised name, e.g. "BT-TH-6D73XXXX".
+    pub name: Option<String>,
+    /// BlueZ alias: the name unless renamed locally (`bluetoothctl set-alias`).
+    pub alias: String,
+    /// Signal strength of the last advertisement, absent for cached devices not seen
+    /// during the scan.
+    pub rssi: Option<i16>,
+    pub paired: bool,
+    pub connected: bool,
 }
 
-async fn discover_bt2_devices_with_options(
-    adapter: &str,
-    scan_duration: Duration,
-) -> Result<Vec<bluebus::DeviceInfo>> {
-    let connection = bluebus::get_system_connection().await?;
+impl Bt2Device {
+    fn from_properties(
+        adapter: &str,
+        properties: &HashMap<String, zbus::zvariant::OwnedValue>,
+    ) -> Option<Self> {
+        let string = |key: &str| {
+            properties
+                .get(key)
+                .and_then(|v| String::try_from(v.clone()).ok())
+        };
+        let flag = |key: &str| {
+            properties
+                .get(key)
+                .and_then(|v| bool::try_from(v.clone()).ok())
+                .unwrap_or(false)
+        };
+        let address = string("Address")?;
+        Some(Self {
+            adapter: adapter.to_string(),
+            name: string("Name"),
+            alias: string("Alias").unwrap_or_else(|| address.clone()),
+            rssi: properties
+                .get("RSSI")
+                .and_then(|v| i16::try_from(v.clone()).ok()),
+            paired: flag("Paired"),
+            connected: flag("Connected"),
+            address,
+        })
+    }
+}
 
-    let adapter_path = format!("/org/bluez/{adapter}");
-    let adapter_proxy = AdapterProxy::builder(&connection)
-        .path(adapter_path.as_str())?
-        .build()
-        .await?;
+/// Builder for a BT-2 scan.
+///
+/// ```ignore
+/// let devices = Bt2Discovery::new()
+///     .adapter("hci1")
+///     .duration(Duration::from_secs(10))
+///     .min_rssi(-85)
+///     .scan()
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Bt2Discovery {
+    adapter: String,
+    duration: Duration,
+    name_prefix: Option<String>,
+    name_regex: Option<Regex>,
+    min_rssi: Option<i16>,
+}
 
-    if adapter_proxy.start_discovery().await.is_ok() {
-        tokio::time::sleep(scan_duration).await;
-        adapter_proxy.stop_discovery().await.ok();
+impl Default for Bt2Discovery {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    Ok(bluebus::list_devices()
-        .await
-        .into_iter()
-        .filter(|d| {
-            d.name
+impl Bt2Discovery {
+    /// Scan `hci0` for 5 s for devices named like a BT-2 ("BT-TH-").
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            adapter: "hci0".to_string(),
+            duration: DEFAULT_SCAN_DURATION,
+            name_prefix: Some(BT2_NAME_PREFIX.to_string()),
+            name_regex: None,
+            min_rssi: None,
+        }
+    }
+
+    #[must_use]
+    pub fn adapter(mut self, adapter: impl Into<String>) -> Self {
+        self.adapter = adapter.into();
+        self
+    }
+
+    #[must_use]
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Keep devices whose name (or alias, if unnamed) starts with `prefix`. `None`
+    /// keeps every device.
+    #[must_use]
+    pub fn name_prefix(mut self, prefix: Option<String>) -> Self {
+        self.name_prefix = prefix;
+        self
+    }
+
+    /// Keep devices whose name or alias matches `regex`.
+    #[must_use]
+    pub fn name_regex(mut self, regex: Regex) -> Self {
+        self.name_regex = Some(regex);
+        self
+    }
+
+    /// Drop devices heard weaker than `dbm`, or not heard at all during the scan.
+    #[must_use]
+    pub fn min_rssi(mut self, dbm: i16) -> Self {
+        self.min_rssi = Some(dbm);
+        self
+    }
+
+    /// Whether `device` passes the name and RSSI filters.
+    #[must_use]
+    pub fn matches(&self, device: &Bt2Device) -> bool {
+        let name = device.name.as_deref().unwrap_or(&device.alias);
+        self.name_prefix
+            .as_ref()
+            .is_none_or(|prefix| name.starts_with(prefix.as_str()))
+            && self
+                .name_regex
                 .as_ref()
-                .is_some_and(|n| n.starts_with(BT2_NAME_PREFIX))
-        })
-        .collect())
+                .is_none_or(|regex| regex.is_match(name) || regex.is_match(&device.alias))
+            && self
+                .min_rssi
+                .is_none_or(|min| device.rssi.is_some_and(|rssi| rssi >= min))
+    }
+
+    /// Scan over the system bus.
+    pub async fn scan(&self) -> Result<Vec<Bt2Device>> {
+        let connection = bluebus::get_system_connection().await?;
+        self.scan_with(&connection).await
+    }
+
+    /// Run discovery on the adapter for the configured duration, then list the
+    /// matching devices BlueZ knows on that adapter, strongest first.
+    pub async fn scan_with(&self, connection: &Connection) -> Result<Vec<Bt2Device>> {
+        let adapter_path = format!("/org/bluez/{}", self.adapter);
+        let adapter_proxy = AdapterProxy::builder(connection)
+            .path(adapter_path.as_str())?
+            .build()
+            .await?;
+
+        if adapter_proxy.start_discovery().await.is_ok() {
+            tokio::time::sleep(self.duration).await;
+            adapter_proxy.stop_discovery().await.ok();
+        }
+
+        let device_prefix = format!("{adapter_path}/dev_");
+        let objects = ObjectManagerProxy::new(connection)
+            .await?
+            .get_managed_objects()
+            .await?;
+        let mut devices: Vec<Bt2Device> = objects
+            .iter()
+            .filter(|(path, _)| path.as_str().starts_with(&device_prefix))
+            .filter_map(|(_, interfaces)| interfaces.get("org.bluez.Device1"))
+            .filter_map(|properties| Bt2Device::from_properties(&self.adapter, properties))
+            .filter(|device| self.matches(device))
+            .collect();
+        devices.sort_by_key(|device| std::cmp::Reverse(device.rssi.unwrap_or(i16::MIN)));
+        Ok(devices)
+    }
+}
+
+/// Scan `hci0` with the default options.
+pub async fn discover_bt2_devices() -> Result<Vec<Bt2Device>> {
+    Bt2Discovery::new().scan().await
+}
+
+/// Pick the device to connect to: the one whose alias or address is `wanted`, or else
+/// the only one found. Several candidates without a choice is an error, not a guess.
+pub fn select_device<'a>(devices: &'a [Bt2Device], wanted: Option<&str>) -> Result<&'a Bt2Device> {
+    let candidates: Vec<&Bt2Device> = match wanted {
+        Some(wanted) => devices
+            .iter()
+            .filter(|d| d.alias == wanted || d.address.eq_ignore_ascii_case(wanted))
+            .collect(),
+        None => devices.iter().collect(),
+    };
+    match candidates.as_slice() {
+        [device] => Ok(device),
+        [] => Err(RenogyError::Bluetooth(match wanted {
+            Some(wanted) => format!("no BT-2 device with alias or address {wanted:?}"),
+            None => "no BT-2 devices found".to_string(),
+        })),
+        several => {
+            let names: Vec<String> = several
+                .iter()
+                .map(|d| format!("{} ({})", d.alias, d.address))
+                .collect();
+            Err(RenogyError::Bluetooth(format!(
+                "{} BT-2 devices match: {}; choose one by alias or MAC address",
+                several.len(),
+                names.join(", ")
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bt2Device;
+    use super::Bt2Discovery;
+    use super::Bt2Transport;
+    use super::ConnectionState;
+    use super::select_device;
+    use crate::emulator::EmulatedBattery;
+    use crate::error::RenogyError;
+    use crate::mock_bluez::BT2_ADDRESS;
+    use crate::mock_bluez::MockBluez;
+    use crate::pdu::Pdu;
+    use crate::query::query_battery;
+    use crate::transport::Transport;
+    use regex::Regex;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    fn device(address: &str, alias: &str, rssi: Option<i16>) -> Bt2Device {
+        Bt2Device {
+            address: address.to_string(),
+            adapter: "hci0".to_string(),
+            name: Some(format!("BT-TH-{}", &address[12..].replace(':', ""))),
+            alias: alias.to_string(),
+            rssi,
+            paired: false,
+            connected: false,
+        }
+    }
+
+    #[test]
+    fn filters_on_name_and_rssi() {
+        let near = device("C4:D3:6A:01:02:03", "shed", Some(-60));
+        let far = device("C4:D3:6A:01:02:04", "barn", Some(-95));
+        let cached = device("C4:D3:6A:01:02:05", "house", None);
+        let mut phone = device("10:20:30:40:50:60", "phone", Some(-40));
+        phone.name = Some("Pixel".to_string());
+
+        let discovery = Bt2Discovery::new().min_rssi(-80);
+        assert!(discovery.matches(&near));
+        assert!(!discovery.matches(&far));
+        assert!(!discovery.matches(&cached));
+        assert!(!discovery.matches(&phone));
+
+        let discovery = Bt2Discovery::new()
+            .name_prefix(None)
+            .name_regex(Regex::new("^(shed|Pixel)$").unwrap());
+        assert!(discovery.matches(&near));
+        assert!(discovery.matches(&phone));
+        assert!(!discovery.matches(&far));
+    }
+
+    #[test]
+    fn selection_refuses_to_guess() {
+        let devices = [
+            device("C4:D3:6A:01:02:03", "shed", Some(-60)),
+            device("C4:D3:6A:01:02:04", "barn", Some(-70)),
+        ];
+        assert!(select_device(&devices, None).is_err());
+        assert_eq!(
+            select_device(&devices, Some("barn")).unwrap().address,
+            "C4:D3:6A:01:02:04"
+        );
+        assert_eq!(
+            select_device(&devices, Some("c4:d3:6a:01:02:03"))
+                .unwrap()
+                .alias,
+            "shed"
+        );
+        assert!(select_device(&devices, Some("house")).is_err());
+        assert!(select_device(&devices[..1], None).is_ok());
+        assert!(select_device(&[], None).is_err());
+    }
+
+    async fn connect(mock: &MockBluez) -> Bt2Transport {
+        let mut transport = Bt2Transport::connect_with(mock.connection().clone(), &mock.bt2_path())
+            .await
+            .expect("connect");
+        transport.set_timeout(Duration::from_secs(2));
+        transport
+    }
+
+    #[tokio::test]
+    async fn connects_and_queries_through_bluez() {
+        let mock = MockBluez::start(vec![
+            EmulatedBattery::lfp_12v(0x30),
+            EmulatedBattery::lfp_12v(0x31),
+        ])
+        .await
+        .unwrap();
+        let mut transport = connect(&mock).await;
+
+        assert!(mock.bt2_connected().await.unwrap());
+        assert_eq!(transport.connection_state(), ConnectionState::Connected);
+        let info = query_battery(&mut transport, 0x31).await.expect("info");
+        assert_eq!(info.serial, "SN0031");
+        assert_eq!(info.cell_count, 4);
+    }
+
+    #[tokio::test]
+    async fn device_without_bt2_characteristics_is_rejected() {
+        let mock = MockBluez::start(Vec::new()).await.unwrap();
+        let path = mock
+            .add_device("hci0", "10:20:30:40:50:60", "Pixel", -40)
+            .await
+            .unwrap();
+        let result = Bt2Transport::connect_with(mock.connection().clone(), &path).await;
+        assert!(matches!(result, Err(RenogyError::Bluetooth(_))));
+    }
+
+    #[tokio::test]
+    async fn discovery_lists_bt2s_on_the_adapter() {
+        let mock = MockBluez::start(Vec::new()).await.unwrap();
+        mock.add_device("hci0", "C4:D3:6A:00:00:02", "BT-TH-6A000002", -85)
+            .await
+            .unwrap();
+        mock.add_device("hci0", "10:20:30:40:50:60", "Pixel", -40)
+            .await
+            .unwrap();
+        mock.add_device("hci1", "C4:D3:6A:00:00:03", "BT-TH-6A000003", -50)
+            .await
+            .unwrap();
+
+        let discovery = Bt2Discovery::new().duration(Duration::from_millis(10));
+        let devices = discovery.scan_with(mock.connection()).await.unwrap();
+        let addresses: Vec<&str> = devices.iter().map(|d| d.address.as_str()).collect();
+        assert_eq!(addresses, [BT2_ADDRESS, "C4:D3:6A:00:00:02"]);
+        assert_eq!(devices[0].rssi, Some(-60));
+        assert_eq!(devices[0].name.as_deref(), Some("BT-TH-6A000001"));
+
+        let devices = discovery
+            .min_rssi(-70)
+            .scan_with(mock.connection())
+            .await
+            .unwrap();
+        assert_eq!(devices.len(), 1);
+
+        let devices = Bt2Discovery::new()
+            .adapter("hci1")
+            .duration(Duration::from_millis(10))
+            .scan_with(mock.connection())
+            .await
+            .unwrap();
+        assert_eq!(devices[0].adapter, "hci1");
+        assert_eq!(devices[0].address, "C4:D3:6A:00:00:03");
+    }
+
+    #[tokio::test]
+    async fn reassembles_fragmented_notifications() {
+        let mock = MockBluez::start(vec![EmulatedBattery::lfp_12v(0x30)])
+            .await
+            .unwrap();
+        let mut transport = connect(&mock).await;
+
+        for chunk_size in [1, 3, 20, 255] {
+            mock.set_chunk_size(chunk_size);
+            let words = transport
+                .read_holding_registers(0x30, 5000, 34)
+                .await
+                .unwrap_or_else(|e| panic!("chunk size {chunk_size}: {e}"));
+            assert_eq!(words.len(), 34);
+        }
+    }
+
+    #[tokio::test]
+    async fn stale_notification_is_discarded() {
+        let mock = MockBluez::start(vec![EmulatedBattery::lfp_12v(0x30)])
+            .await
+            .unwrap();
+        let mut transport = connect(&mock).await;
+
+        let mut late = EmulatedBattery::lfp_12v(0x31);
+        let late = late
+            .respond(&Pdu::read_holding_registers(0x31, 5000, 2))
+            .unwrap();
+        mock.notify(&late.serialize()).await.unwrap();
+        let info = query_battery(&mut transport, 0x30).await.expect("info");
+        assert_eq!(info.serial, "SN0030");
+    }
+
+    #[tokio::test]
+    async fn silent_module_times_out() {
+        let mock = MockBluez::start(vec![EmulatedBattery::lfp_12v(0x30)])
+            .await
+            .unwrap();
+        let mut transport = connect(&mock).await;
+        transport.set_timeout(Duration::from_millis(200));
+
+        mock.set_silent(true);
+        assert!(matches!(
+            transport.read_holding_registers(0x30, 5000, 1).await,
+            Err(RenogyError::Timeout)
+        ));
+        assert!(matches!(
+            transport.read_holding_registers(0x32, 5000, 1).await,
+            Err(RenogyError::Timeout)
+        ));
+
+        mock.set_silent(false);
+        assert!(
+            transport
+                .read_holding_registers(0x30, 5000, 1)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_the_link_drops() {
+        let mock = MockBluez::start(vec![EmulatedBattery::lfp_12v(0x30)])
+            .await
+            .unwrap();
+        let mut transport = connect(&mock).await;
+        let mut state = transport.subscribe_connection_state();
+
+        mock.drop_link().await.unwrap();
+        timeout(
+            Duration::from_secs(2),
+            state.wait_for(|s| matches!(s, ConnectionState::Reconnecting { .. })),
+        )
+        .await
+        .expect("disconnect noticed")
+        .unwrap();
+        timeout(
+            Duration::from_secs(5),
+            state.wait_for(|s| *s == ConnectionState::Connected),
+        )
+        .await
+        .expect("reconnected")
+        .unwrap();
+
+        assert!(mock.bt2_connected().await.unwrap());
+        let info = query_battery(&mut transport, 0x30).await.expect("info");
+        assert_eq!(info.serial, "SN0030");
+    }
 }
@@ -0,0 +1,463 @@
+//! btsnoop HCI trace importer.
+//!
+//! Android's Bluetooth HCI snoop log records every HCI packet between the phone and its
+//! controller. The BT-2 carries Modbus RTU frames as ATT payloads (see
+//! `doc/bt2-gatt-modbus-protocol.txt`): requests are Write Commands to handle 0x001D,
+//! responses are Handle Value Notifications from handle 0x002E. This module digs those
+//! frames out, pairs each response with its request and turns the session into a
+//! readable dump or a `ReplayTransport` fixture.
+
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::pdu::FunctionCode;
+use crate::pdu::Pdu;
+use crate::recording::Exchange;
+use crate::recording::RecordedError;
+use crate::recording::Request;
+use crate::recording::Response;
+use crate::registers::Register;
+use crate::rtu::FrameAssembler;
+use crate::rtu::is_response_to;
+use crate::transport::TransportType;
+use chrono::DateTime;
+use chrono::Utc;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+/// GATT handle the phone app writes Modbus requests to.
+pub const WRITE_HANDLE: u16 = 0x001D;
+/// GATT handle the BT-2 sends Modbus responses from.
+pub const NOTIFY_HANDLE: u16 = 0x002E;
+
+const MAGIC: &[u8; 8] = b"btsnoop\0";
+const FILE_HEADER_LEN: usize = 16;
+const RECORD_HEADER_LEN: usize = 24;
+/// HCI packets without a type byte; direction/kind come from the record flags.
+const DATALINK_HCI: u32 = 1001;
+/// HCI UART (H4): each packet starts with a packet type byte.
+const DATALINK_H4: u32 = 1002;
+const H4_ACL: u8 = 0x02;
+const L2CAP_CID_ATT: u16 = 0x0004;
+const ATT_WRITE_REQUEST: u8 = 0x12;
+const ATT_WRITE_COMMAND: u8 = 0x52;
+const ATT_NOTIFICATION: u8 = 0x1B;
+/// Microseconds from 0000-01-01 (the btsnoop epoch) to 1970-01-01.
+const BTSNOOP_EPOCH_OFFSET_US: i64 = 0x00dc_ddb3_0f2f_8000;
+
+/// One HCI ACL data packet from the trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AclPacket {
+    pub timestamp: DateTime<Utc>,
+    /// Sent by the controller to the host (i.e. from the remote device).
+    pub received: bool,
+    /// ACL header and payload, without an H4 type byte.
+    pub data: Vec<u8>,
+}
+
+/// Extract the ACL data packets from a btsnoop file.
+pub fn acl_packets(trace: &[u8]) -> Result<Vec<AclPacket>> {
+    if trace.len() < FILE_HEADER_LEN || &trace[..8] != MAGIC {
+        return Err(RenogyError::InvalidData);
+    }
+    let datalink = u32::from_be_bytes([trace[12], trace[13], trace[14], trace[15]]);
+    if datalink != DATALINK_HCI && datalink != DATALINK_H4 {
+        return Err(RenogyError::UnsupportedOperation);
+    }
+
+    let be32 = |b: &[u8]| u32::from_be_bytes([b[0], b[1], b[2], b[3]]);
+    let mut packets = Vec::new();
+    let mut rest = &trace[FILE_HEADER_LEN..];
+    while rest.len() >= RECORD_HEADER_LEN {
+        let included = be32(&rest[4..8]) as usize;
+        let flags = be32(&rest[8..12]);
+        let micros = i64::from_be_bytes(rest[16..24].try_into().unwrap_or_default());
+        let data = rest
+            .get(RECORD_HEADER_LEN..RECORD_HEADER_LEN + included)
+            .ok_or(RenogyError::InvalidData)?;
+        rest = &rest[RECORD_HEADER_LEN + included..];
+
+        let data = match datalink {
+            DATALINK_H4 if data.first() == Some(&H4_ACL) => &data[1..],
+            DATALINK_HCI if flags & 0x02 == 0 => data,
+            _ => continue,
+        };
+        let timestamp =
+            DateTime::from_timestamp_micros(micros.saturating_sub(BTSNOOP_EPOCH_OFFSET_US))
+                .unwrap_or_default();
+        packets.push(AclPacket {
+            timestamp,
+            received: flags & 0x01 != 0,
+            data: data.to_vec(),
+        });
+    }
+    Ok(packets)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttOperation {
+    Write,
+    Notification,
+}
+
+/// An ATT write or notification carrying a characteristic value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttValue {
+    pub timestamp: DateTime<Utc>,
+    pub operation: AttOperation,
+    pub handle: u16,
+    pub value: Vec<u8>,
+}
+
+/// Reassemble L2CAP frames from ACL packets and keep the ATT writes and notifications.
+#[must_use]
+pub fn att_values(packets: &[AclPacket]) -> Vec<AttValue> {
+    struct Partial {
+        timestamp: DateTime<Utc>,
+        expected: usize,
+        frame: Vec<u8>,
+    }
+
+    let mut partials: HashMap<(u16, bool), Partial> = HashMap::new();
+    let mut values = Vec::new();
+
+    for packet in packets {
+        let Some(header) = packet.data.get(..4) else {
+            continue;
+        };
+        let handle = u16::from_le_bytes([header[0], header[1]]) & 0x0FFF;
+        let boundary = header[1] >> 4 & 0x03;
+        let payload = &packet.data[4..];
+        let key = (handle, packet.received);
+
+        if boundary == 0x01 {
+            let Some(partial) = partials.get_mut(&key) else {
+                continue;
+            };
+            partial.frame.extend_from_slice(payload);
+        } else {
+            let Some(length) = payload.get(..2) else {
+                continue;
+            };
+            partials.insert(
+                key,
+                Partial {
+                    timestamp: packet.timestamp,
+                    expected: u16::from_le_bytes([length[0], length[1]]) as usize + 4,
+                    frame: payload.to_vec(),
+                },
+            );
+        }
+
+        if partials[&key].frame.len() < partials[&key].expected {
+            continue;
+        }
+        let Some(partial) = partials.remove(&key) else {
+            continue;
+        };
+        let frame = &partial.frame[..partial.expected];
+        let cid = u16::from_le_bytes([frame[2], frame[3]]);
+        let att = &frame[4..];
+        if cid != L2CAP_CID_ATT || att.len() < 3 {
+            continue;
+        }
+        let operation = match att[0] {
+            ATT_WRITE_COMMAND | ATT_WRITE_REQUEST => AttOperation::Write,
+            ATT_NOTIFICATION => AttOperation::Notification,
+            _ => continue,
+        };
+        values.push(AttValue {
+            timestamp: partial.timestamp,
+            operation,
+            handle: u16::from_le_bytes([att[1], att[2]]),
+            value: att[3..].to_vec(),
+        });
+    }
+    values
+}
+
+/// A Modbus request seen in the trace and the response the BT-2 gave, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transaction {
+    pub timestamp: DateTime<Utc>,
+    pub request: Pdu,
+    /// `None` if no matching response arrived before the next request.
+    pub response: Option<std::result::Result<Pdu, RecordedError>>,
+}
+
+/// Decode Modbus traffic on the BT-2 handles and pair responses with requests.
+///
+/// Notifications are reassembled with the RTU framer, so responses split across
+/// several notifications are joined; frames that answer no outstanding request are
+/// dropped.
+#[must_use]
+pub fn transactions(values: &[AttValue]) -> Vec<Transaction> {
+    let mut requests = FrameAssembler::requests();
+    let mut responses = FrameAssembler::responses();
+    let mut transactions: Vec<Transaction> = Vec::new();
+
+    for value in values {
+        match (value.operation, value.handle) {
+            (AttOperation::Write, WRITE_HANDLE) => {
+                requests.push(&value.value);
+                while let Some(frame) = requests.next_frame() {
+                    let Ok(request) = Pdu::deserialize(&frame) else {
+                        continue;
+                    };
+                    responses.clear();
+                    transactions.push(Transaction {
+                        timestamp: value.timestamp,
+                        request,
+                        response: None,
+                    });
+                }
+            }
+            (AttOperation::Notification, NOTIFY_HANDLE) => {
+                responses.push(&value.value);
+                while let Some(frame) = responses.next_frame() {
+                    let Some(pending) = transactions
+                        .last_mut()
+                        .filter(|t| t.response.is_none() && is_response_to(&frame, &t.request))
+                    else {
+                        continue;
+                    };
+                    pending.response = Some(Pdu::deserialize(&frame).map_err(|e| (&e).into()));
+                }
+            }
+            _ => {}
+        }
+    }
+    transactions
+}
+
+/// Parse a btsnoop file straight to Modbus transactions.
+pub fn parse(trace: &[u8]) -> Result<Vec<Transaction>> {
+    Ok(transactions(&att_values(&acl_packets(trace)?)))
+}
+
+fn recorded_request(pdu: &Pdu) -> Option<Request> {
+    let word = |i: usize| {
+        pdu.payload
+            .get(i..i + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+    };
+    let slave = pdu.address;
+    Some(match pdu.function_code {
+        FunctionCode::ReadHoldingRegisters => Request::ReadHoldingRegisters {
+            slave,
+            addr: word(0)?,
+            quantity: word(2)?,
+        },
+        FunctionCode::WriteSingleRegister => Request::WriteSingleRegister {
+            slave,
+            addr: word(0)?,
+            value: word(2)?,
+        },
+        FunctionCode::WriteMultipleRegisters => Request::WriteMultipleRegisters {
+            slave,
+            addr: word(0)?,
+            values: (0..word(2)? as usize)
+                .map(|i| word(5 + 2 * i))
+                .collect::<Option<_>>()?,
+        },
+        FunctionCode::RestoreFactoryDefault | FunctionCode::ClearHistory => Request::SendCustom {
+            slave,
+            function_code: pdu.function_code as u8,
+            data: pdu.payload.clone(),
+        },
+    })
+}
+
+/// Convert transactions into exchanges for `ReplayTransport`. Unanswered requests
+/// replay as timeouts.
+#[must_use]
+pub fn to_exchanges(transactions: &[Transaction]) -> Vec<Exchange> {
+    transactions
+        .iter()
+        .filter_map(|t| {
+            let request = recorded_request(&t.request)?;
+            let response = match (&t.response, &request) {
+                (None, _) => Response::Error(RecordedError::Timeout),
+                (Some(Err(e)), _) => Response::Error(e.clone()),
+                (Some(Ok(pdu)), Request::ReadHoldingRegisters { quantity, .. }) => {
+                    match pdu.register_values(*quantity) {
+                        Ok(words) => Response::Registers(words),
+                        Err(e) => Response::Error((&e).into()),
+                    }
+                }
+                (Some(Ok(pdu)), Request::SendCustom { .. }) => {
+                    Response::Payload(pdu.payload.clone())
+                }
+                (Some(Ok(_)), _) => Response::Written,
+            };
+            Some(Exchange {
+                timestamp: t.timestamp,
+                transport: TransportType::Bt2,
+                request,
+                response,
+            })
+        })
+        .collect()
+}
+
+/// Human-readable listing of a session, naming each register in read responses.
+#[must_use]
+pub fn dump(transactions: &[Transaction]) -> String {
+    let mut out = String::new();
+    for exchange in to_exchanges(transactions) {
+        let time = exchange.timestamp.format("%H:%M:%S%.3f");
+        match &exchange.request {
+            Request::ReadHoldingRegisters {
+                slave,
+                addr,
+                quantity,
+            } => {
+                let _ = writeln!(out, "{time} 0x{slave:02X} read {addr}+{quantity}");
+                if let Response::Registers(words) = &exchange.response {
+                    describe_registers(&mut out, *addr, words);
+                }
+            }
+            other => {
+                let _ = writeln!(out, "{time} {other:?}");
+            }
+        }
+        if let Response::Error(e) = &exchange.response {
+            let _ = writeln!(out, "    error: {}", RenogyError::from(e.clone()));
+        }
+    }
+    out
+}
+
+fn describe_registers(out: &mut String, start: u16, words: &[u16]) {
+    let mut offset = 0;
+    while offset < words.len() {
+        let addr = start + offset as u16;
+        let register =
+            Register::from_address(addr).filter(|r| offset + r.quantity() as usize <= words.len());
+        match register {
+            Some(register) => {
+                let quantity = register.quantity() as usize;
+                match register.parse_registers(&words[offset..offset + quantity]) {
+                    Ok(value) => {
+                        let _ = writeln!(out, "    {addr} {register:?} = {value}");
+                    }
+                    Err(e) => {
+                        let _ = writeln!(out, "    {addr} {register:?}: {e}");
+                    }
+                }
+                offset += quantity;
+            }
+            None => {
+                let _ = writeln!(out, "    {addr} 0x{:04X}", words[offset]);
+                offset += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AclPacket;
+    use super::AttOperation;
+    use super::NOTIFY_HANDLE;
+    use super::WRITE_HANDLE;
+    use super::att_values;
+    use super::dump;
+    use super::parse;
+    use super::to_exchanges;
+    use super::transactions;
+    use crate::pdu::Pdu;
+    use crate::recording::ReplayMode;
+    use crate::recording::ReplayTransport;
+    use crate::registers::Register;
+    use crate::transport::Transport;
+    use chrono::DateTime;
+
+    const TRACE: &[u8] = include_bytes!("../traces/renogy-bt.btsnoop");
+
+    /// ACL packet carrying one ATT PDU, split into `pieces` ACL fragments.
+    fn acl(received: bool, opcode: u8, handle: u16, value: &[u8], pieces: usize) -> Vec<AclPacket> {
+        let mut att = vec![opcode];
+        att.extend_from_slice(&handle.to_le_bytes());
+        att.extend_from_slice(value);
+        let mut l2cap = (att.len() as u16).to_le_bytes().to_vec();
+        l2cap.extend_from_slice(&0x0004u16.to_le_bytes());
+        l2cap.extend_from_slice(&att);
+
+        let chunk = l2cap.len().div_ceil(pieces);
+        l2cap
+            .chunks(chunk)
+            .enumerate()
+            .map(|(i, piece)| {
+                let flags: u16 = if i == 0 { 0x2000 } else { 0x1000 };
+                let mut data = (0x0040 | flags).to_le_bytes().to_vec();
+                data.extend_from_slice(&(piece.len() as u16).to_le_bytes());
+                data.extend_from_slice(piece);
+                AclPacket {
+                    timestamp: DateTime::default(),
+                    received,
+                    data,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reassembles_fragmented_acl_and_notifications() {
+        let request = Pdu::read_holding_registers(0x30, 5000, 2).serialize();
+        let response = Pdu::new(
+            0x30,
+            crate::pdu::FunctionCode::ReadHoldingRegisters,
+            vec![0x04, 0x00, 0x04, 0x00, 0x21],
+        )
+        .serialize();
+
+        let mut packets = acl(false, 0x52, WRITE_HANDLE, &request, 1);
+        packets.extend(acl(true, 0x1B, NOTIFY_HANDLE, &response[..4], 2));
+        packets.extend(acl(true, 0x1B, NOTIFY_HANDLE, &response[4..], 1));
+
+        let values = att_values(&packets);
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[1].operation, AttOperation::Notification);
+
+        let transactions = transactions(&values);
+        assert_eq!(transactions.len(), 1);
+        let response = transactions[0].response.clone().unwrap().unwrap();
+        assert_eq!(response.register_values(2).unwrap(), [4, 0x21]);
+    }
+
+    #[test]
+    fn decodes_phone_app_trace() {
+        let transactions = parse(TRACE).unwrap();
+        assert!(!transactions.is_empty());
+        assert!(
+            transactions
+                .iter()
+                .all(|t| matches!(t.request.address, 0x30 | 0x31))
+        );
+        assert!(
+            transactions
+                .iter()
+                .any(|t| matches!(t.response, Some(Ok(_))))
+        );
+
+        let dump = dump(&transactions);
+        assert!(dump.contains("read 5042+6"));
+        assert!(dump.contains("ModuleVoltage = "));
+    }
+
+    #[tokio::test]
+    async fn trace_replays_as_regression_fixture() {
+        let exchanges = to_exchanges(&parse(TRACE).unwrap());
+        let mut replay = ReplayTransport::new(exchanges, ReplayMode::Keyed);
+
+        let register = Register::ModuleVoltage;
+        let words = replay
+            .read_holding_registers(0x31, Register::Current.address(), 6)
+            .await
+            .unwrap();
+        let offset = (register.address() - Register::Current.address()) as usize;
+        let voltage = register.parse_registers(&words[offset..=offset]).unwrap();
+        let volts = voltage.as_voltage().unwrap().value;
+        assert!((8.0..=60.0).contains(&volts), "{volts}");
+    }
+}
@@ -0,0 +1,267 @@
+//! Links for a collector that polls several BT-2 modules and serial ports at once.
+//!
+//! Each link is given on the command line as `[NAME=]TARGET[@OPTION]`: a BT-2 as
+//! `shed=C4:D3:6A:12:34:56@hci1` (adapter defaults to hci0) and a serial port as
+//! `bank=/dev/ttyUSB0@19200` (baud defaults to 9600). A serial link takes the rest of
+//! the `serial` subcommand's line settings as comma-separated options under the same
+//! names, e.g. `bank=/dev/ttyUSB0@19200,parity=even,timeout-ms=500,native-rtu`. The
+//! name becomes the `link` label on every series from that link; it defaults to the
+//! MAC or the port's file name.
+
+use crate::any_transport::AnyTransport;
+use crate::any_transport::BT2_SCAN_RANGE;
+use crate::any_transport::SERIAL_SCAN_RANGE;
+use crate::bt2::Bt2Transport;
+use crate::error::Result;
+use crate::serial::SerialConfig;
+use crate::util::open_serial;
+use crate::util::parse_data_bits;
+use crate::util::parse_parity;
+use crate::util::parse_stop_bits;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    Bt2 {
+        mac: String,
+        adapter: String,
+    },
+    Serial {
+        port: String,
+        config: SerialConfig,
+        /// Use the built-in RTU framer instead of tokio-modbus.
+        native_rtu: bool,
+    },
+}
+
+/// One link of a multi-link collector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkSpec {
+    pub name: String,
+    pub target: LinkTarget,
+}
+
+impl LinkSpec {
+    /// Parse a `[NAME=]MAC[@ADAPTER]` BT-2 link.
+    pub fn parse_bt2(s: &str) -> std::result::Result<Self, String> {
+        let (name, rest) = split_name(s)?;
+        let (mac, adapter) = match rest.split_once('@') {
+            Some((mac, adapter)) if !adapter.is_empty() => (mac, adapter),
+            Some(_) => return Err(format!("missing adapter after '@' in {s:?}")),
+            None => (rest, "hci0"),
+        };
+        let octets: Vec<&str> = mac.split(':').collect();
+        if octets.len() != 6
+            || !octets
+                .iter()
+                .all(|o| o.len() == 2 && o.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return Err(format!("invalid MAC address {mac:?}"));
+        }
+        let mac = mac.to_uppercase();
+        Ok(Self {
+            name: name.unwrap_or(&mac).to_string(),
+            target: LinkTarget::Bt2 {
+                mac,
+                adapter: adapter.to_string(),
+            },
+        })
+    }
+
+    /// Parse a `[NAME=]PORT[@BAUD][,OPTION...]` serial link.
+    pub fn parse_serial(s: &str) -> std::result::Result<Self, String> {
+        let (link, options) = match s.split_once(',') {
+            Some((link, options)) => (link, Some(options)),
+            None => (s, None),
+        };
+        let (name, rest) = split_name(link)?;
+        let mut config = SerialConfig::default();
+        let port = match rest.rsplit_once('@') {
+            Some((port, baud)) => {
+                config.baud_rate = baud
+                    .parse()
+                    .map_err(|_| format!("invalid baud rate {baud:?}"))?;
+                port
+            }
+            None => rest,
+        };
+        if port.is_empty() {
+            return Err(format!("missing serial port in {s:?}"));
+        }
+
+        let mut native_rtu = false;
+        for option in options.into_iter().flat_map(|options| options.split(',')) {
+            let invalid = |e: String| format!("invalid {option:?} in {s:?}: {e}");
+            let millis = |value: &str| {
+                value
+                    .parse()
+                    .map(Duration::from_millis)
+                    .map_err(|_| invalid("expected milliseconds".into()))
+            };
+            match option.split_once('=') {
+                Some(("data-bits", value)) => {
+                    config.data_bits = parse_data_bits(value).map_err(invalid)?;
+                }
+                Some(("parity", value)) => config.parity = parse_parity(value).map_err(invalid)?,
+                Some(("stop-bits", value)) => {
+                    config.stop_bits = parse_stop_bits(value).map_err(invalid)?;
+                }
+                Some(("timeout-ms", value)) => config.timeout = millis(value)?,
+                Some(("inter-request-delay-ms", value)) => {
+                    config.inter_request_delay = millis(value)?;
+                }
+                None if option == "rs485" => config.rs485 = true,
+                None if option == "rts-direction" => config.rts_direction = true,
+                None if option == "native-rtu" => native_rtu = true,
+                _ => return Err(format!("unknown serial option {option:?} in {s:?}")),
+            }
+        }
+        if config.rts_direction && !native_rtu {
+            return Err(format!("rts-direction requires native-rtu in {s:?}"));
+        }
+
+        let default_name = port.rsplit(['/', '\\']).next().unwrap_or(port);
+        Ok(Self {
+            name: name.unwrap_or(default_name).to_string(),
+            target: LinkTarget::Serial {
+                port: port.to_string(),
+                config,
+                native_rtu,
+            },
+        })
+    }
+
+    /// Addresses to probe when none are given.
+    pub fn scan_range(&self) -> RangeInclusive<u8> {
+        match self.target {
+            LinkTarget::Bt2 { .. } => BT2_SCAN_RANGE,
+            LinkTarget::Serial { .. } => SERIAL_SCAN_RANGE,
+        }
+    }
+
+    pub async fn connect(&self) -> Result<AnyTransport> {
+        match &self.target {
+            LinkTarget::Bt2 { mac, adapter } => {
+                Ok(Bt2Transport::connect_by_address(mac, adapter).await?.into())
+            }
+            LinkTarget::Serial {
+                port,
+                config,
+                native_rtu,
+            } => open_serial(port, config, *native_rtu, *SERIAL_SCAN_RANGE.start()).await,
+        }
+    }
+}
+
+impl std::fmt::Display for LinkSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.target {
+            LinkTarget::Bt2 { mac, adapter } => {
+                write!(f, "{} ({} via {})", self.name, mac, adapter)
+            }
+            LinkTarget::Serial { port, config, .. } => {
+                write!(f, "{} ({} at {} baud)", self.name, port, config.baud_rate)
+            }
+        }
+    }
+}
+
+fn split_name(s: &str) -> std::result::Result<(Option<&str>, &str), String> {
+    match s.split_once('=') {
+        Some(("", _)) => Err(format!("empty link name in {s:?}")),
+        Some((name, rest)) => Ok((Some(name), rest)),
+        None => Ok((None, s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LinkSpec;
+    use super::LinkTarget;
+    use crate::serial::SerialConfig;
+    use std::time::Duration;
+    use tokio_serial::DataBits;
+    use tokio_serial::Parity;
+    use tokio_serial::StopBits;
+
+    #[test]
+    fn bt2_links() {
+        let link = LinkSpec::parse_bt2("shed=c4:d3:6a:12:34:56@hci1").unwrap();
+        assert_eq!(link.name, "shed");
+        assert_eq!(
+            link.target,
+            LinkTarget::Bt2 {
+                mac: "C4:D3:6A:12:34:56".into(),
+                adapter: "hci1".into()
+            }
+        );
+
+        let link = LinkSpec::parse_bt2("C4:D3:6A:12:34:56").unwrap();
+        assert_eq!(link.name, "C4:D3:6A:12:34:56");
+        assert!(matches!(link.target, LinkTarget::Bt2 { adapter, .. } if adapter == "hci0"));
+
+        assert!(LinkSpec::parse_bt2("C4:D3:6A:12:34").is_err());
+        assert!(LinkSpec::parse_bt2("=C4:D3:6A:12:34:56").is_err());
+        assert!(LinkSpec::parse_bt2("C4:D3:6A:12:34:56@").is_err());
+    }
+
+    #[test]
+    fn serial_links() {
+        let link = LinkSpec::parse_serial("bank=/dev/ttyUSB0@19200").unwrap();
+        assert_eq!(link.name, "bank");
+        assert_eq!(
+            link.target,
+            LinkTarget::Serial {
+                port: "/dev/ttyUSB0".into(),
+                config: SerialConfig {
+                    baud_rate: 19_200,
+                    ..SerialConfig::default()
+                },
+                native_rtu: false,
+            }
+        );
+
+        let link = LinkSpec::parse_serial("/dev/ttyUSB1").unwrap();
+        assert_eq!(link.name, "ttyUSB1");
+        assert!(matches!(
+            link.target,
+            LinkTarget::Serial { config, native_rtu: false, .. } if config == SerialConfig::default()
+        ));
+
+        assert!(LinkSpec::parse_serial("/dev/ttyUSB0@fast").is_err());
+        assert!(LinkSpec::parse_serial("bank=").is_err());
+    }
+
+    #[test]
+    fn serial_link_options() {
+        let link = LinkSpec::parse_serial(
+            "bank=/dev/ttyUSB0@4800,data-bits=7,parity=even,stop-bits=2,timeout-ms=250,\
+             inter-request-delay-ms=20,rs485,native-rtu,rts-direction",
+        )
+        .unwrap();
+        assert_eq!(
+            link.target,
+            LinkTarget::Serial {
+                port: "/dev/ttyUSB0".into(),
+                config: SerialConfig {
+                    baud_rate: 4800,
+                    data_bits: DataBits::Seven,
+                    parity: Parity::Even,
+                    stop_bits: StopBits::Two,
+                    timeout: Duration::from_millis(250),
+                    inter_request_delay: Duration::from_millis(20),
+                    rs485: true,
+                    rts_direction: true,
+                },
+                native_rtu: true,
+            }
+        );
+
+        assert!(LinkSpec::parse_serial("/dev/ttyUSB0,parity=mark").is_err());
+        assert!(LinkSpec::parse_serial("/dev/ttyUSB0,timeout-ms=soon").is_err());
+        assert!(LinkSpec::parse_serial("/dev/ttyUSB0,fast").is_err());
+        assert!(LinkSpec::parse_serial("/dev/ttyUSB0,rts-direction").is_err());
+        assert!(LinkSpec::parse_serial("bank=/dev/ttyUSB0,").is_err());
+    }
+}
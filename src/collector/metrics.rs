@@ -1,5 +1,6 @@
 use crate::query::BatteryInfo;
 use influxdb_line_protocol::LineProtocolBuilder;
+use influxdb_line_protocol::builder::AfterMeasurement;
 use prometheus_client::encoding::EncodeLabelSet;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
@@ -13,17 +14,23 @@ fn bool_to_f64(b: bool) -> f64 {
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 pub struct BatteryLabels {
     pub battery: String,
+    /// Collector link the battery is polled over; empty with a single link.
+    pub link: String,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 pub struct CellLabels {
     pub battery: String,
+    /// Collector link the battery is polled over; empty with a single link.
+    pub link: String,
     pub cell: String,
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
 pub struct SensorLabels {
     pub battery: String,
+    /// Collector link the battery is polled over; empty with a single link.
+    pub link: String,
     pub sensor: String,
 }
 
@@ -193,11 +200,13 @@ impl PrometheusMetrics {
         let serial = &info.serial;
         let battery_labels = BatteryLabels {
             battery: serial.clone(),
+            link: info.link.clone(),
         };
 
         for (i, &voltage) in info.cell_voltages.iter().enumerate() {
             let labels = CellLabels {
                 battery: serial.clone(),
+                link: info.link.clone(),
                 cell: (i + 1).to_string(),
             };
             self.cell_voltage.get_or_create(&labels).set(voltage as f64);
@@ -206,6 +215,7 @@ impl PrometheusMetrics {
         for (i, &temp) in info.cell_temperatures.iter().enumerate() {
             let labels = CellLabels {
                 battery: serial.clone(),
+                link: info.link.clone(),
                 cell: (i + 1).to_string(),
             };
             self.cell_temperature
@@ -222,6 +232,7 @@ impl PrometheusMetrics {
         for (i, &temp) in info.environment_temperatures.iter().enumerate() {
             let labels = SensorLabels {
                 battery: serial.clone(),
+                link: info.link.clone(),
                 sensor: (i + 1).to_string(),
             };
             self.environment_temperature
@@ -232,6 +243,7 @@ impl PrometheusMetrics {
         for (i, &temp) in info.heater_temperatures.iter().enumerate() {
             let labels = SensorLabels {
                 battery: serial.clone(),
+                link: info.link.clone(),
                 sensor: (i + 1).to_string(),
             };
             self.heater_temperature
@@ -330,40 +342,58 @@ impl PrometheusMetrics {
     }
 }
 
+/// Tag a line with its link, which is omitted when empty (single-link collectors).
+fn tag_link(
+    builder: LineProtocolBuilder<Vec<u8>, AfterMeasurement>,
+    link: &str,
+) -> LineProtocolBuilder<Vec<u8>, AfterMeasurement> {
+    if link.is_empty() {
+        builder
+    } else {
+        builder.tag("link", link)
+    }
+}
+
 pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
     use crate::alarm::ChargeDischargeStatus;
     use crate::alarm::Status1;
     use crate::alarm::Status2;
 
     macro_rules! measurement {
-        ($b:expr, $name:expr, $serial:expr, $value:expr, $ts:expr) => {
-            $b.measurement($name)
-                .tag("battery", $serial)
-                .field("value", $value)
-                .timestamp($ts)
-                .close_line()
+        ($b:expr, $name:expr, $info:expr, $value:expr, $ts:expr) => {
+            tag_link(
+                $b.measurement($name).tag("battery", &$info.serial),
+                &$info.link,
+            )
+            .field("value", $value)
+            .timestamp($ts)
+            .close_line()
         };
     }
 
     macro_rules! cell_measurement {
-        ($b:expr, $name:expr, $serial:expr, $cell:expr, $value:expr, $ts:expr) => {
-            $b.measurement($name)
-                .tag("battery", $serial)
-                .tag("cell", $cell)
-                .field("value", $value)
-                .timestamp($ts)
-                .close_line()
+        ($b:expr, $name:expr, $info:expr, $cell:expr, $value:expr, $ts:expr) => {
+            tag_link(
+                $b.measurement($name).tag("battery", &$info.serial),
+                &$info.link,
+            )
+            .tag("cell", $cell)
+            .field("value", $value)
+            .timestamp($ts)
+            .close_line()
         };
     }
 
     macro_rules! sensor_measurement {
-        ($b:expr, $name:expr, $serial:expr, $sensor:expr, $value:expr, $ts:expr) => {
-            $b.measurement($name)
-                .tag("battery", $serial)
-                .tag("sensor", $sensor)
-                .field("value", $value)
-                .timestamp($ts)
-                .close_line()
+        ($b:expr, $name:expr, $info:expr, $sensor:expr, $value:expr, $ts:expr) => {
+            tag_link(
+                $b.measurement($name).tag("battery", &$info.serial),
+                &$info.link,
+            )
+            .tag("sensor", $sensor)
+            .field("value", $value)
+            .timestamp($ts)
+            .close_line()
         };
     }
 
@@ -371,14 +401,13 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
 
     for info in samples {
         let ts = info.timestamp.timestamp_nanos_opt().unwrap_or(0);
-        let serial = &info.serial;
 
         for (i, &voltage) in info.cell_voltages.iter().enumerate() {
             let cell = (i + 1).to_string();
             builder = cell_measurement!(
                 builder,
                 "renogy_cell_voltage",
-                serial,
+                info,
                 &cell,
                 voltage as f64,
                 ts
@@ -390,7 +419,7 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
             builder = cell_measurement!(
                 builder,
                 "renogy_cell_temperature",
-                serial,
+                info,
                 &cell,
                 temp as f64,
                 ts
@@ -398,7 +427,7 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
         }
 
         if let Some(temp) = info.bms_temperature {
-            builder = measurement!(builder, "renogy_bms_temperature", serial, temp as f64, ts);
+            builder = measurement!(builder, "renogy_bms_temperature", info, temp as f64, ts);
         }
 
         for (i, &temp) in info.environment_temperatures.iter().enumerate() {
@@ -406,7 +435,7 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
             builder = sensor_measurement!(
                 builder,
                 "renogy_environment_temperature",
-                serial,
+                info,
                 &sensor,
                 temp as f64,
                 ts
@@ -418,7 +447,7 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
             builder = sensor_measurement!(
                 builder,
                 "renogy_heater_temperature",
-                serial,
+                info,
                 &sensor,
                 temp as f64,
                 ts
@@ -428,36 +457,36 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
         builder = measurement!(
             builder,
             "renogy_module_voltage",
-            serial,
+            info,
             info.module_voltage as f64,
             ts
         );
-        builder = measurement!(builder, "renogy_current", serial, info.current as f64, ts);
+        builder = measurement!(builder, "renogy_current", info, info.current as f64, ts);
         builder = measurement!(
             builder,
             "renogy_remaining_capacity_ah",
-            serial,
+            info,
             info.remaining_capacity as f64,
             ts
         );
         builder = measurement!(
             builder,
             "renogy_total_capacity_ah",
-            serial,
+            info,
             info.total_capacity as f64,
             ts
         );
         builder = measurement!(
             builder,
             "renogy_soc_percent",
-            serial,
+            info,
             info.soc_percent as f64,
             ts
         );
         builder = measurement!(
             builder,
             "renogy_cycle_count",
-            serial,
+            info,
             info.cycle_count as f64,
             ts
         );
@@ -466,7 +495,7 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
             builder = measurement!(
                 builder,
                 "renogy_charge_voltage_limit",
-                serial,
+                info,
                 limit as f64,
                 ts
             );
@@ -475,7 +504,7 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
             builder = measurement!(
                 builder,
                 "renogy_discharge_voltage_limit",
-                serial,
+                info,
                 limit as f64,
                 ts
             );
@@ -484,7 +513,7 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
             builder = measurement!(
                 builder,
                 "renogy_charge_current_limit",
-                serial,
+                info,
                 limit as f64,
                 ts
             );
@@ -493,57 +522,57 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
             builder = measurement!(
                 builder,
                 "renogy_discharge_current_limit",
-                serial,
+                info,
                 limit as f64,
                 ts
             );
         }
 
         if let Some(s) = info.status1 {
-            builder = measurement!(builder, "renogy_status1", serial, s.bits() as f64, ts);
+            builder = measurement!(builder, "renogy_status1", info, s.bits() as f64, ts);
             builder = measurement!(
                 builder,
                 "renogy_charge_mosfet_on",
-                serial,
+                info,
                 bool_to_f64(s.contains(Status1::CHARGE_MOSFET)),
                 ts
             );
             builder = measurement!(
                 builder,
                 "renogy_discharge_mosfet_on",
-                serial,
+                info,
                 bool_to_f64(s.contains(Status1::DISCHARGE_MOSFET)),
                 ts
             );
         }
 
         if let Some(s) = info.status2 {
-            builder = measurement!(builder, "renogy_status2", serial, s.bits() as f64, ts);
+            builder = measurement!(builder, "renogy_status2", info, s.bits() as f64, ts);
             builder = measurement!(
                 builder,
                 "renogy_fully_charged",
-                serial,
+                info,
                 bool_to_f64(s.contains(Status2::FULLY_CHARGED)),
                 ts
             );
             builder = measurement!(
                 builder,
                 "renogy_heater_on",
-                serial,
+                info,
                 bool_to_f64(s.contains(Status2::HEATER_ON)),
                 ts
             );
         }
 
         if let Some(s) = info.status3 {
-            builder = measurement!(builder, "renogy_status3", serial, s.bits() as f64, ts);
+            builder = measurement!(builder, "renogy_status3", info, s.bits() as f64, ts);
         }
 
         if let Some(s) = info.other_alarm_info {
             builder = measurement!(
                 builder,
                 "renogy_other_alarm_info",
-                serial,
+                info,
                 s.bits() as f64,
                 ts
             );
@@ -553,14 +582,14 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
             builder = measurement!(
                 builder,
                 "renogy_charge_enabled",
-                serial,
+                info,
                 bool_to_f64(s.contains(ChargeDischargeStatus::CHARGE_ENABLE)),
                 ts
             );
             builder = measurement!(
                 builder,
                 "renogy_discharge_enabled",
-                serial,
+                info,
                 bool_to_f64(s.contains(ChargeDischargeStatus::DISCHARGE_ENABLE)),
                 ts
             );
@@ -569,3 +598,86 @@ pub fn batch_to_influx(samples: &[BatteryInfo]) -> String {
 
     String::from_utf8(builder.build()).expect("line protocol should be valid UTF-8")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PrometheusMetrics;
+    use super::batch_to_influx;
+    use crate::emulator::EmulatedBattery;
+    use crate::query::query_battery;
+    use crate::registers::Register;
+    use prometheus_client::encoding::text::encode;
+    use prometheus_client::registry::Registry;
+
+    #[tokio::test]
+    async fn link_label_only_when_named() {
+        let single = query_battery(&mut EmulatedBattery::lfp_12v(0x30), 0x30)
+            .await
+            .expect("info");
+        let mut named = single.clone();
+        named.serial = "SN5678".into();
+        named.link = "shed".into();
+
+        let lines = batch_to_influx(&[single.clone(), named.clone()]);
+        assert!(lines.contains("renogy_module_voltage,battery=SN0030 "));
+        assert!(lines.contains("renogy_module_voltage,battery=SN5678,link=shed "));
+        assert!(lines.contains("renogy_cell_voltage,battery=SN5678,link=shed,cell=1 "));
+
+        let metrics = PrometheusMetrics::default();
+        let mut registry = Registry::default();
+        metrics.register(&mut registry);
+        metrics.update(&single);
+        metrics.update(&named);
+        let mut text = String::new();
+        encode(&mut text, &registry).unwrap();
+        assert!(text.contains(r#"battery="SN5678",link="shed""#));
+    }
+
+    #[tokio::test]
+    async fn sub_zero_temperatures_reach_influx_negative() {
+        let mut bms = EmulatedBattery::lfp_12v(0x30);
+        bms.set_temperature(Register::CellTemperature(1), -12.5)
+            .unwrap();
+        bms.set_temperature(Register::CellTemperature(2), -0.1)
+            .unwrap();
+        bms.set_temperature(Register::BmsTemperature, -3.0).unwrap();
+        bms.set_integer(Register::EnvironmentTemperatureCount, 1)
+            .unwrap();
+        bms.set_temperature(Register::EnvironmentTemperature(1), -20.0)
+            .unwrap();
+        bms.set_integer(Register::HeaterTemperatureCount, 1)
+            .unwrap();
+        bms.set_temperature(Register::HeaterTemperature(1), -5.2)
+            .unwrap();
+
+        let info = query_battery(&mut bms, 0x30).await.expect("info");
+        let lines = batch_to_influx(&[info]);
+
+        let value = |prefix: &str| -> f64 {
+            let line = lines
+                .lines()
+                .find(|line| line.starts_with(prefix))
+                .unwrap_or_else(|| panic!("no {prefix} in {lines}"));
+            let field = line.split(' ').nth(1).unwrap();
+            field.trim_start_matches("value=").parse().unwrap()
+        };
+        let close = |actual: f64, expected: f64| (actual - expected).abs() < 0.05;
+        assert!(close(
+            value("renogy_cell_temperature,battery=SN0030,cell=1 "),
+            -12.5
+        ));
+        assert!(close(
+            value("renogy_cell_temperature,battery=SN0030,cell=2 "),
+            -0.1
+        ));
+        assert!(close(value("renogy_bms_temperature,battery=SN0030 "), -3.0));
+        assert!(close(
+            value("renogy_environment_temperature,battery=SN0030,sensor=1 "),
+            -20.0
+        ));
+        assert!(close(
+            value("renogy_heater_temperature,battery=SN0030,sensor=1 "),
+            -5.2
+        ));
+    }
+}
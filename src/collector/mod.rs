@@ -1,4 +1,5 @@
 pub mod buffer;
+pub mod link;
 pub mod metrics;
 pub mod server;
 pub mod writer;
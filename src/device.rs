@@ -1,7 +1,9 @@
+use crate::error::RenogyError;
 use crate::error::Result;
 use crate::pdu::FunctionCode;
 use crate::pdu::Pdu;
 use crate::registers::Register;
+use crate::transport::Transport;
 
 const SHUTDOWN_VALUE: u16 = 1;
 const LOCK_VALUE: u16 = 0x5A5A;
@@ -42,27 +44,69 @@ impl DeviceCommand {
                 FunctionCode::ClearHistory,
                 vec![0x00, 0x00, 0x00, 0x01], // Supplement data as per PDF
             ),
-            DeviceCommand::Shutdown => {
-                Self::create_write_pdu(device_address, Register::ShutdownCommand, SHUTDOWN_VALUE)
-            }
-            DeviceCommand::Lock => {
-                Self::create_write_pdu(device_address, Register::LockControl, LOCK_VALUE)
-            }
-            DeviceCommand::Unlock => {
-                Self::create_write_pdu(device_address, Register::LockControl, UNLOCK_VALUE)
-            }
-            DeviceCommand::TestBegin => {
-                Self::create_write_pdu(device_address, Register::TestReady, TEST_BEGIN_VALUE)
-            }
-            DeviceCommand::TestEnd => {
-                Self::create_write_pdu(device_address, Register::TestReady, TEST_END_VALUE)
+            _ => {
+                let (register, value) = self
+                    .register_write()
+                    .expect("every other command is a register write");
+                let payload = [register.address().to_be_bytes(), value.to_be_bytes()].concat();
+                Pdu::new(device_address, FunctionCode::WriteSingleRegister, payload)
             }
         }
     }
 
-    fn create_write_pdu(device_address: u8, register: Register, value: u16) -> Pdu {
-        let payload = [register.address().to_be_bytes(), value.to_be_bytes()].concat();
-        Pdu::new(device_address, FunctionCode::WriteSingleRegister, payload)
+    /// The register and value written to carry out this command; `None` for the
+    /// commands with their own function code.
+    #[must_use]
+    pub const fn register_write(&self) -> Option<(Register, u16)> {
+        match self {
+            DeviceCommand::RestoreFactoryDefault | DeviceCommand::ClearHistory => None,
+            DeviceCommand::Shutdown => Some((Register::ShutdownCommand, SHUTDOWN_VALUE)),
+            DeviceCommand::Lock => Some((Register::LockControl, LOCK_VALUE)),
+            DeviceCommand::Unlock => Some((Register::LockControl, UNLOCK_VALUE)),
+            DeviceCommand::TestBegin => Some((Register::TestReady, TEST_BEGIN_VALUE)),
+            DeviceCommand::TestEnd => Some((Register::TestReady, TEST_END_VALUE)),
+        }
+    }
+
+    /// The commands sent to carry out this one: those that need it are wrapped in an
+    /// unlock and a lock.
+    #[must_use]
+    pub fn sequence(&self) -> Vec<DeviceCommand> {
+        if self.requires_unlock() {
+            vec![DeviceCommand::Unlock, self.clone(), DeviceCommand::Lock]
+        } else {
+            vec![self.clone()]
+        }
+    }
+
+    /// Carry out the command on `slave`, following `sequence`. The battery is locked
+    /// again even if the command itself fails.
+    pub async fn send<T: Transport>(&self, transport: &mut T, slave: u8) -> Result<()> {
+        if !self.requires_unlock() {
+            return self.send_one(transport, slave).await;
+        }
+        DeviceCommand::Unlock.send_one(transport, slave).await?;
+        let result = self.send_one(transport, slave).await;
+        let locked = DeviceCommand::Lock.send_one(transport, slave).await;
+        result?;
+        locked
+    }
+
+    async fn send_one<T: Transport>(&self, transport: &mut T, slave: u8) -> Result<()> {
+        if let Some((register, value)) = self.register_write() {
+            return transport
+                .write_single_register(slave, register.address(), value)
+                .await;
+        }
+        // The BMS acknowledges 0x78/0x79 by echoing the request.
+        let pdu = self.create_pdu(slave);
+        let reply = transport
+            .send_custom(slave, pdu.function_code as u8, &pdu.payload)
+            .await?;
+        if reply != pdu.payload {
+            return Err(RenogyError::DeviceControlFailed);
+        }
+        Ok(())
     }
 
     #[must_use]
@@ -72,6 +116,12 @@ impl DeviceCommand {
             DeviceCommand::RestoreFactoryDefault | DeviceCommand::ClearHistory
         )
     }
+
+    /// Commands that lose data or take the battery offline.
+    #[must_use]
+    pub const fn is_destructive(&self) -> bool {
+        self.requires_unlock() || matches!(self, DeviceCommand::Shutdown)
+    }
 }
 
 /// Device identification and configuration
@@ -158,7 +208,34 @@ impl AcpConfig {
 mod tests {
     use super::DeviceCommand;
     use super::PowerSettings;
+    use crate::emulator::EmulatedBattery;
+    use crate::emulator::Fault;
+    use crate::emulator::FaultyTransport;
+    use crate::error::ModbusExceptionCode;
+    use crate::error::RenogyError;
     use crate::pdu::FunctionCode;
+    use crate::recording::Request;
+
+    const LOCK_CONTROL: u16 = 5224;
+
+    /// A battery that acknowledges the custom function codes by echoing them, as the
+    /// BMS does, or answers them with `exception`.
+    fn battery(exception: Option<ModbusExceptionCode>) -> FaultyTransport<EmulatedBattery> {
+        FaultyTransport::new(EmulatedBattery::lfp_12v(0x30)).on_custom(move |_, _, data| {
+            Fault::Answer(match exception {
+                Some(exception) => Err(RenogyError::ModbusException(exception)),
+                None => Ok(data.to_vec()),
+            })
+        })
+    }
+
+    fn lock_write(value: u16) -> Request {
+        Request::WriteSingleRegister {
+            slave: 0x30,
+            addr: LOCK_CONTROL,
+            value,
+        }
+    }
 
     #[test]
     fn requires_unlock_only_for_destructive() {
@@ -209,4 +286,57 @@ mod tests {
         let value = &pdu.payload[pdu.payload.len() - 2..];
         assert_eq!(value, [0x5A, 0x5A]);
     }
+
+    #[test]
+    fn only_unlocking_commands_are_wrapped() {
+        assert_eq!(
+            DeviceCommand::ClearHistory.sequence(),
+            [
+                DeviceCommand::Unlock,
+                DeviceCommand::ClearHistory,
+                DeviceCommand::Lock
+            ]
+        );
+        assert_eq!(
+            DeviceCommand::Shutdown.sequence(),
+            [DeviceCommand::Shutdown]
+        );
+        assert!(DeviceCommand::Shutdown.is_destructive());
+        assert!(!DeviceCommand::TestBegin.is_destructive());
+    }
+
+    #[tokio::test]
+    async fn send_unlocks_and_relocks_around_factory_reset() {
+        let mut link = battery(None);
+        DeviceCommand::RestoreFactoryDefault
+            .send(&mut link, 0x30)
+            .await
+            .unwrap();
+        assert_eq!(
+            link.requests(),
+            [
+                lock_write(0xA5A5),
+                Request::SendCustom {
+                    slave: 0x30,
+                    function_code: 0x78,
+                    data: vec![0x00, 0x00, 0x00, 0x01]
+                },
+                lock_write(0x5A5A),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn send_relocks_after_an_exception() {
+        let mut link = battery(Some(ModbusExceptionCode::IllegalFunction));
+        let err = DeviceCommand::ClearHistory
+            .send(&mut link, 0x30)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RenogyError::ModbusException(ModbusExceptionCode::IllegalFunction)
+        ));
+        assert_eq!(link.requests().last(), Some(&lock_write(0x5A5A)));
+    }
 }
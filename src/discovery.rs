@@ -0,0 +1,298 @@
+//! Battery discovery on one link.
+//!
+//! Every address in the range gets one short probe read, bounded by its own timeout,
+//! and silent addresses are skipped rather than ending the scan: a faulty battery in
+//! the middle of a bank no longer hides the ones after it. A link carries one request
+//! at a time (RS-485 and BT-2 are half duplex), so addresses are probed in turn;
+//! separate links are discovered concurrently by their own tasks.
+
+use crate::bt2;
+use crate::registers::Register;
+use crate::transport::Transport;
+use crate::transport::TransportType;
+use crate::util::clean_string;
+use serde::Serialize;
+use serde::Serializer;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio::time::timeout;
+
+/// Per-address timeout on wired links when none is configured. A present battery
+/// answers a single register read in well under this over serial or TCP.
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The probe timeout for a link when none is configured. A BT-2 request crosses the
+/// BLE link and the module's own serial hop, so it gets the BT-2 request timeout.
+pub fn default_probe_timeout(transport: TransportType) -> Duration {
+    match transport {
+        TransportType::Bt2 => bt2::DEFAULT_TIMEOUT,
+        TransportType::Serial | TransportType::Tcp | TransportType::RtuOverTcp => {
+            DEFAULT_PROBE_TIMEOUT
+        }
+    }
+}
+
+/// The register read to decide whether an address is populated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Probe {
+    /// Read the serial number, which the result needs anyway.
+    #[default]
+    SnNumber,
+    /// Read the one-word cell count, for links where every word counts.
+    CellCount,
+}
+
+impl Probe {
+    fn register(self) -> Register {
+        match self {
+            Probe::SnNumber => Register::SnNumber,
+            Probe::CellCount => Register::CellCount,
+        }
+    }
+}
+
+/// How to scan a range, and when to stop early.
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryOptions {
+    pub probe: Probe,
+    /// `None` for `default_probe_timeout` of the link being scanned.
+    pub probe_timeout: Option<Duration>,
+    /// Stop once this many batteries have answered, e.g. the known bank size.
+    pub max_batteries: Option<usize>,
+    /// Stop after this many silent addresses in a row. `Some(1)` is the old
+    /// stop-at-first-miss behaviour.
+    pub max_consecutive_misses: Option<usize>,
+    /// Stop probing new addresses once the scan has run this long.
+    pub deadline: Option<Duration>,
+}
+
+/// A battery that answered its probe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiscoveredBattery {
+    pub address: u8,
+    /// Empty if the serial number could not be read after the probe.
+    pub serial: String,
+    /// `BatteryName`, e.g. "RBT100LFP12S-G1"; empty if unreadable.
+    pub model: String,
+    /// Round trip of the probe read.
+    #[serde(rename = "latency_ms", serialize_with = "as_millis")]
+    pub latency: Duration,
+}
+
+fn as_millis<S: Serializer>(latency: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(latency.as_secs_f64() * 1000.0)
+}
+
+/// Probe every address in `range`, returning the batteries that answered in address
+/// order.
+pub async fn discover(
+    transport: &mut (impl Transport + Send),
+    range: RangeInclusive<u8>,
+    options: &DiscoveryOptions,
+) -> Vec<DiscoveredBattery> {
+    let started = Instant::now();
+    let probe_timeout = options
+        .probe_timeout
+        .unwrap_or_else(|| default_probe_timeout(transport.transport_type()));
+    let mut found = Vec::new();
+    let mut misses = 0;
+
+    for address in range {
+        if options
+            .deadline
+            .is_some_and(|deadline| started.elapsed() >= deadline)
+        {
+            tracing::debug!("Discovery deadline reached before 0x{:02X}", address);
+            break;
+        }
+
+        let probe_started = Instant::now();
+        let Some(regs) = read(transport, address, &options.probe.register(), probe_timeout).await
+        else {
+            misses += 1;
+            if options
+                .max_consecutive_misses
+                .is_some_and(|max| misses >= max)
+            {
+                tracing::debug!("{} silent addresses in a row, stopping", misses);
+                break;
+            }
+            continue;
+        };
+        let latency = probe_started.elapsed();
+        misses = 0;
+
+        let serial = match options.probe {
+            Probe::SnNumber => string(Register::SnNumber, &regs),
+            Probe::CellCount => {
+                read_string(transport, address, Register::SnNumber, probe_timeout).await
+            }
+        };
+        let model = read_string(transport, address, Register::BatteryName, probe_timeout).await;
+        found.push(DiscoveredBattery {
+            address,
+            serial,
+            model,
+            latency,
+        });
+
+        if options.max_batteries.is_some_and(|max| found.len() >= max) {
+            break;
+        }
+    }
+    found
+}
+
+async fn read(
+    transport: &mut (impl Transport + Send),
+    address: u8,
+    register: &Register,
+    probe_timeout: Duration,
+) -> Option<Vec<u16>> {
+    let read = transport.read_holding_registers(address, register.address(), register.quantity());
+    match timeout(probe_timeout, read).await {
+        Ok(Ok(regs)) => Some(regs),
+        Ok(Err(e)) => {
+            tracing::debug!("No answer from 0x{:02X}: {}", address, e);
+            None
+        }
+        Err(_) => {
+            tracing::debug!(
+                "No answer from 0x{:02X} within {:?}",
+                address,
+                probe_timeout
+            );
+            None
+        }
+    }
+}
+
+async fn read_string(
+    transport: &mut (impl Transport + Send),
+    address: u8,
+    register: Register,
+    probe_timeout: Duration,
+) -> String {
+    read(transport, address, &register, probe_timeout)
+        .await
+        .map(|regs| string(register, &regs))
+        .unwrap_or_default()
+}
+
+fn string(register: Register, regs: &[u16]) -> String {
+    register
+        .parse_registers(regs)
+        .ok()
+        .and_then(|value| value.as_string().map(clean_string))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DEFAULT_PROBE_TIMEOUT;
+    use super::DiscoveryOptions;
+    use super::Probe;
+    use super::default_probe_timeout;
+    use super::discover;
+    use crate::emulator::EmulatedBattery;
+    use crate::emulator::EmulatedBus;
+    use crate::emulator::Fault;
+    use crate::emulator::FaultyTransport;
+    use crate::transport::TransportType;
+    use std::time::Duration;
+
+    fn addresses(found: &[super::DiscoveredBattery]) -> Vec<u8> {
+        found.iter().map(|b| b.address).collect()
+    }
+
+    #[tokio::test]
+    async fn silent_address_does_not_hide_later_batteries() {
+        let mut bus = EmulatedBus::new(vec![
+            EmulatedBattery::lfp_12v(0x31),
+            EmulatedBattery::lfp_12v(0x33),
+        ]);
+        let found = discover(&mut bus, 0x30..=0x3F, &DiscoveryOptions::default()).await;
+
+        assert_eq!(addresses(&found), [0x31, 0x33]);
+        assert_eq!(found[0].serial, "SN0031");
+        assert_eq!(found[0].model, "RBT100LFP12S-G1");
+    }
+
+    #[tokio::test]
+    async fn cell_count_probe_still_reads_identity() {
+        let mut bus = EmulatedBus::new(vec![EmulatedBattery::lfp_12v(0x02)]);
+        let options = DiscoveryOptions {
+            probe: Probe::CellCount,
+            ..DiscoveryOptions::default()
+        };
+        let found = discover(&mut bus, 0x01..=0x04, &options).await;
+
+        assert_eq!(addresses(&found), [0x02]);
+        assert_eq!(found[0].serial, "SN0002");
+    }
+
+    #[tokio::test]
+    async fn early_termination_rules() {
+        let mut bus = EmulatedBus::new(vec![
+            EmulatedBattery::lfp_12v(0x01),
+            EmulatedBattery::lfp_12v(0x02),
+            EmulatedBattery::lfp_12v(0x06),
+        ]);
+
+        let options = DiscoveryOptions {
+            max_batteries: Some(2),
+            ..DiscoveryOptions::default()
+        };
+        assert_eq!(
+            addresses(&discover(&mut bus, 0x01..=0x10, &options).await),
+            [0x01, 0x02]
+        );
+
+        let options = DiscoveryOptions {
+            max_consecutive_misses: Some(3),
+            ..DiscoveryOptions::default()
+        };
+        assert_eq!(
+            addresses(&discover(&mut bus, 0x01..=0x10, &options).await),
+            [0x01, 0x02]
+        );
+    }
+
+    #[tokio::test]
+    async fn probe_timeout_bounds_a_hung_address() {
+        // 0x32 swallows requests without ever answering.
+        let bus = EmulatedBus::new(vec![
+            EmulatedBattery::lfp_12v(0x31),
+            EmulatedBattery::lfp_12v(0x33),
+        ]);
+        let mut bus = FaultyTransport::new(bus).on_read(|slave, _, _| match slave {
+            0x32 => Fault::Hang,
+            _ => Fault::Pass,
+        });
+        let options = DiscoveryOptions {
+            probe_timeout: Some(Duration::from_millis(50)),
+            ..DiscoveryOptions::default()
+        };
+        let started = tokio::time::Instant::now();
+        let found = discover(&mut bus, 0x31..=0x33, &options).await;
+
+        assert_eq!(addresses(&found), [0x31, 0x33]);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn bt2_probes_wait_as_long_as_any_bt2_request() {
+        assert_eq!(
+            default_probe_timeout(TransportType::Bt2),
+            Duration::from_secs(5)
+        );
+        for transport in [
+            TransportType::Serial,
+            TransportType::Tcp,
+            TransportType::RtuOverTcp,
+        ] {
+            assert_eq!(default_probe_timeout(transport), DEFAULT_PROBE_TIMEOUT);
+        }
+    }
+}
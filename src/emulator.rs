@@ -3,7 +3,8 @@
 //! Used by tests (and, behind the `emulator` feature, a future standalone Modbus
 //! server) to feed the real collector pipeline without hardware. Register words are
 //! produced by `Register::encode_value`, so the emulator stays in lockstep with the
-//! parser -- no hand-coded wire formats to drift.
+//! parser -- no hand-coded wire formats to drift. `FaultyTransport` wraps one to log
+//! requests and inject the faults a real link or BMS produces.
 
 use std::collections::BTreeMap;
 
@@ -11,6 +12,9 @@ use async_trait::async_trait;
 
 use crate::error::RenogyError;
 use crate::error::Result;
+use crate::pdu::FunctionCode;
+use crate::pdu::Pdu;
+use crate::recording::Request;
 use crate::registers::Register;
 use crate::registers::Value;
 use crate::transport::Transport;
@@ -37,6 +41,92 @@ impl EmulatedBattery {
         }
     }
 
+    /// A 12 V, 4-cell LiFePO4 battery with a serial number of `SN` and its address in
+    /// hex (`SN0030`), a consistent set of limits and a few live readings: a plausible
+    /// battery for tests that don't care about particular words.
+    #[must_use]
+    pub fn lfp_12v(slave: u8) -> Self {
+        let mut bms = Self::new(slave);
+        bms.configure_lfp_12v()
+            .expect("every preset value fits its register");
+        bms
+    }
+
+    fn configure_lfp_12v(&mut self) -> Result<()> {
+        self.set_string(
+            Register::SnNumber,
+            &format!("SN{slave:04X}", slave = self.slave),
+        )?;
+        self.set_string(Register::BatteryName, "RBT100LFP12S-G1")?;
+        self.set_string(Register::ManufacturerName, "RENOGY")?;
+        self.set_integer(Register::CellCount, 4)?;
+        for cell in 1..=4 {
+            self.set_voltage(Register::CellVoltage(cell), 3.3)?;
+        }
+        self.set_integer(Register::CellTemperatureCount, 2)?;
+        for sensor in 1..=2 {
+            self.set_temperature(Register::CellTemperature(sensor), 21.5)?;
+        }
+        self.set_voltage(Register::ModuleVoltage, 13.2)?;
+        self.set_current(Register::Current, -5.0)?;
+        self.set_current(Register::RemainingCapacity, 80.0)?;
+        self.set_current(Register::TotalCapacity, 100.0)?;
+
+        for (register, volts) in [
+            (Register::ChargeVoltageLimit, 14.4),
+            (Register::DischargeVoltageLimit, 10.0),
+            (Register::CellOverVoltageLimit, 3.7),
+            (Register::CellHighVoltageLimit, 3.6),
+            (Register::CellLowVoltageLimit, 2.8),
+            (Register::CellUnderVoltageLimit, 2.5),
+            (Register::ModuleOverVoltageLimit, 14.8),
+            (Register::ModuleHighVoltageLimit, 14.4),
+            (Register::ModuleLowVoltageLimit, 11.2),
+            (Register::ModuleUnderVoltageLimit, 10.0),
+        ] {
+            self.set_voltage(register, volts)?;
+        }
+        for (register, amps) in [
+            (Register::ChargeCurrentLimit, 50.0),
+            (Register::DischargeCurrentLimit, 100.0),
+            (Register::ChargeOver2CurrentLimit, 120.0),
+            (Register::ChargeOver1CurrentLimit, 110.0),
+            (Register::ChargeHighCurrentLimit, 105.0),
+            (Register::DischargeOver2CurrentLimit, 150.0),
+            (Register::DischargeOver1CurrentLimit, 130.0),
+            (Register::DischargeHighCurrentLimit, 120.0),
+        ] {
+            self.set_current(register, amps)?;
+        }
+        for (register, celsius) in [
+            (Register::ChargeOverTemperatureLimit, 55.0),
+            (Register::ChargeHighTemperatureLimit, 50.0),
+            (Register::ChargeLowTemperatureLimit, 5.0),
+            (Register::ChargeUnderTemperatureLimit, 0.0),
+            (Register::DischargeOverTemperatureLimit, 65.0),
+            (Register::DischargeHighTemperatureLimit, 60.0),
+            (Register::DischargeLowTemperatureLimit, -10.0),
+            (Register::DischargeUnderTemperatureLimit, -20.0),
+        ] {
+            self.set_temperature(register, celsius)?;
+        }
+        self.set_integer(Register::ChargePowerSetting, 100)?;
+        self.set_integer(Register::DischargePowerSetting, 100)?;
+        for register in [
+            Register::AcpBroadcast,
+            Register::AcpConfigure,
+            Register::AcpShake,
+        ] {
+            self.set_integer(register, 1)?;
+        }
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn slave(&self) -> u8 {
+        self.slave
+    }
+
     /// Set a register to `value`, encoding via the register's own serializer.
     pub fn set(&mut self, register: Register, value: &Value) -> Result<()> {
         let bytes = register.encode_value(value)?;
@@ -79,6 +169,51 @@ impl EmulatedBattery {
             )),
         )
     }
+
+    /// Answer a Modbus request the way the BMS would, for serving the emulator over a
+    /// real wire protocol. Requests for another slave are rejected.
+    pub fn respond(&mut self, request: &Pdu) -> Result<Pdu> {
+        if request.address != self.slave {
+            return Err(RenogyError::InvalidData);
+        }
+        let word = |i: usize| -> Result<u16> {
+            request
+                .payload
+                .get(i..i + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .ok_or(RenogyError::InvalidData)
+        };
+
+        match request.function_code {
+            FunctionCode::ReadHoldingRegisters => {
+                let (addr, quantity) = (word(0)?, word(2)?);
+                let mut payload = vec![(quantity * 2) as u8];
+                for a in addr..addr + quantity {
+                    let value = self.words.get(&a).copied().unwrap_or(0);
+                    payload.extend_from_slice(&value.to_be_bytes());
+                }
+                Ok(Pdu::new(self.slave, request.function_code, payload))
+            }
+            FunctionCode::WriteSingleRegister => {
+                self.words.insert(word(0)?, word(2)?);
+                Ok(request.clone())
+            }
+            FunctionCode::WriteMultipleRegisters => {
+                let (addr, quantity) = (word(0)?, word(2)?);
+                for i in 0..quantity {
+                    self.words.insert(addr + i, word(5 + 2 * i as usize)?);
+                }
+                Ok(Pdu::new(
+                    self.slave,
+                    request.function_code,
+                    request.payload[..4].to_vec(),
+                ))
+            }
+            FunctionCode::RestoreFactoryDefault | FunctionCode::ClearHistory => {
+                Err(RenogyError::UnsupportedOperation)
+            }
+        }
+    }
 }
 
 #[async_trait]
@@ -128,12 +263,279 @@ impl Transport for EmulatedBattery {
     }
 }
 
+/// Several emulated batteries sharing one bus. Requests to an address nobody holds
+/// time out, as on a real RS-485 line.
+#[derive(Default)]
+pub struct EmulatedBus {
+    batteries: Vec<EmulatedBattery>,
+}
+
+impl EmulatedBus {
+    #[must_use]
+    pub fn new(batteries: Vec<EmulatedBattery>) -> Self {
+        Self { batteries }
+    }
+
+    pub fn battery_mut(&mut self, slave: u8) -> Option<&mut EmulatedBattery> {
+        self.batteries.iter_mut().find(|bms| bms.slave == slave)
+    }
+
+    fn route(&mut self, slave: u8) -> Result<&mut EmulatedBattery> {
+        self.battery_mut(slave).ok_or(RenogyError::Timeout)
+    }
+}
+
+#[async_trait]
+impl Transport for EmulatedBus {
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        self.route(slave)?
+            .read_holding_registers(slave, addr, quantity)
+            .await
+    }
+
+    async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
+        self.route(slave)?
+            .write_single_register(slave, addr, value)
+            .await
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        self.route(slave)?
+            .write_multiple_registers(slave, addr, values)
+            .await
+    }
+
+    async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
+        self.route(slave)?
+            .send_custom(slave, function_code, data)
+            .await
+    }
+
+    fn transport_type(&self) -> TransportType {
+        TransportType::Serial
+    }
+}
+
+/// What a `FaultyTransport` hook does with a request.
+pub enum Fault<T> {
+    /// Pass the request on to the wrapped transport.
+    Pass,
+    /// Answer with this instead of passing the request on: an error, or `Ok(())` to
+    /// report a write done while dropping it.
+    Answer(Result<T>),
+    /// Never answer, like a slave that swallows the request.
+    Hang,
+}
+
+impl<T> Fault<T> {
+    /// The answer in place of the wrapped transport's, or `None` to pass it on.
+    async fn answer(self) -> Option<Result<T>> {
+        match self {
+            Fault::Pass => None,
+            Fault::Answer(answer) => Some(answer),
+            Fault::Hang => std::future::pending().await,
+        }
+    }
+}
+
+type ReadHook = Box<dyn FnMut(u8, u16, u16) -> Fault<Vec<u16>> + Send>;
+type WriteHook = Box<dyn FnMut(u8, u16, &[u16]) -> Fault<()> + Send>;
+type CustomHook = Box<dyn FnMut(u8, u8, &[u8]) -> Fault<Vec<u8>> + Send>;
+
+/// Wraps a transport, usually an emulator, logging every request and letting hooks
+/// fail, drop, hang or answer chosen ones: a BMS that rejects some reads, a write that
+/// does not stick, or replies to the custom function codes.
+pub struct FaultyTransport<T> {
+    inner: T,
+    requests: Vec<Request>,
+    on_read: Option<ReadHook>,
+    on_write: Option<WriteHook>,
+    on_custom: Option<CustomHook>,
+}
+
+impl<T: Transport> FaultyTransport<T> {
+    #[must_use]
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            requests: Vec::new(),
+            on_read: None,
+            on_write: None,
+            on_custom: None,
+        }
+    }
+
+    /// Decide the fate of each read from its slave, address and quantity.
+    #[must_use]
+    pub fn on_read(
+        mut self,
+        hook: impl FnMut(u8, u16, u16) -> Fault<Vec<u16>> + Send + 'static,
+    ) -> Self {
+        self.on_read = Some(Box::new(hook));
+        self
+    }
+
+    /// Decide the fate of each write, single or multiple, from its slave, address and
+    /// values.
+    #[must_use]
+    pub fn on_write(
+        mut self,
+        hook: impl FnMut(u8, u16, &[u16]) -> Fault<()> + Send + 'static,
+    ) -> Self {
+        self.on_write = Some(Box::new(hook));
+        self
+    }
+
+    /// Decide the fate of each custom function code request from its slave, function
+    /// code and data.
+    #[must_use]
+    pub fn on_custom(
+        mut self,
+        hook: impl FnMut(u8, u8, &[u8]) -> Fault<Vec<u8>> + Send + 'static,
+    ) -> Self {
+        self.on_custom = Some(Box::new(hook));
+        self
+    }
+
+    /// Every request so far, in order, whatever became of it.
+    #[must_use]
+    pub fn requests(&self) -> &[Request] {
+        &self.requests
+    }
+
+    /// The reads so far.
+    #[must_use]
+    pub fn reads(&self) -> usize {
+        self.requests
+            .iter()
+            .filter(|request| matches!(request, Request::ReadHoldingRegisters { .. }))
+            .count()
+    }
+
+    /// The writes so far as (address, values), single writes as one value.
+    #[must_use]
+    pub fn writes(&self) -> Vec<(u16, Vec<u16>)> {
+        self.requests
+            .iter()
+            .filter_map(|request| match request {
+                Request::WriteSingleRegister { addr, value, .. } => Some((*addr, vec![*value])),
+                Request::WriteMultipleRegisters { addr, values, .. } => {
+                    Some((*addr, values.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+#[async_trait]
+impl<T: Transport + Send> Transport for FaultyTransport<T> {
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        self.requests.push(Request::ReadHoldingRegisters {
+            slave,
+            addr,
+            quantity,
+        });
+        let fault = self
+            .on_read
+            .as_mut()
+            .map_or(Fault::Pass, |hook| hook(slave, addr, quantity));
+        if let Some(answer) = fault.answer().await {
+            return answer;
+        }
+        self.inner
+            .read_holding_registers(slave, addr, quantity)
+            .await
+    }
+
+    async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
+        self.requests
+            .push(Request::WriteSingleRegister { slave, addr, value });
+        let fault = self
+            .on_write
+            .as_mut()
+            .map_or(Fault::Pass, |hook| hook(slave, addr, &[value]));
+        if let Some(answer) = fault.answer().await {
+            return answer;
+        }
+        self.inner.write_single_register(slave, addr, value).await
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        self.requests.push(Request::WriteMultipleRegisters {
+            slave,
+            addr,
+            values: values.to_vec(),
+        });
+        let fault = self
+            .on_write
+            .as_mut()
+            .map_or(Fault::Pass, |hook| hook(slave, addr, values));
+        if let Some(answer) = fault.answer().await {
+            return answer;
+        }
+        self.inner
+            .write_multiple_registers(slave, addr, values)
+            .await
+    }
+
+    async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
+        self.requests.push(Request::SendCustom {
+            slave,
+            function_code,
+            data: data.to_vec(),
+        });
+        let fault = self
+            .on_custom
+            .as_mut()
+            .map_or(Fault::Pass, |hook| hook(slave, function_code, data));
+        if let Some(answer) = fault.answer().await {
+            return answer;
+        }
+        self.inner.send_custom(slave, function_code, data).await
+    }
+
+    fn transport_type(&self) -> TransportType {
+        self.inner.transport_type()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::EmulatedBattery;
+    use super::Fault;
+    use super::FaultyTransport;
+    use crate::error::ModbusExceptionCode;
+    use crate::error::RenogyError;
     use crate::query::query_battery;
+    use crate::recording::Request;
     use crate::registers::Register;
     use crate::registers::Value;
+    use crate::transport::Transport;
     use uom::si::electric_current::ampere;
     use uom::si::electric_potential::volt;
     use uom::si::f32::ElectricCurrent;
@@ -179,4 +581,49 @@ mod tests {
             .unwrap();
         assert!(query_battery(&mut bms, 0x31).await.is_none());
     }
+
+    #[tokio::test]
+    async fn faulty_transport_answers_in_place_of_the_battery() {
+        let mut link = FaultyTransport::new(EmulatedBattery::lfp_12v(0x30))
+            .on_read(|_, addr, _| match addr {
+                5000 => Fault::Answer(Err(RenogyError::ModbusException(
+                    ModbusExceptionCode::IllegalDataAddress,
+                ))),
+                _ => Fault::Pass,
+            })
+            .on_write(|_, addr, _| match addr {
+                5200 => Fault::Answer(Ok(())),
+                _ => Fault::Pass,
+            })
+            .on_custom(|_, _, data| Fault::Answer(Ok(data.to_vec())));
+
+        assert!(link.read_holding_registers(0x30, 5000, 1).await.is_err());
+        assert_eq!(
+            link.read_holding_registers(0x30, 5001, 1).await.unwrap(),
+            [33]
+        );
+        link.write_single_register(0x30, 5200, 38).await.unwrap();
+        link.write_multiple_registers(0x30, 5201, &[35])
+            .await
+            .unwrap();
+        assert_eq!(
+            link.read_holding_registers(0x30, 5200, 2).await.unwrap(),
+            [37, 35]
+        );
+        assert_eq!(
+            link.send_custom(0x30, 0x79, &[0, 0, 0, 1]).await.unwrap(),
+            [0, 0, 0, 1]
+        );
+
+        assert_eq!(link.reads(), 3);
+        assert_eq!(link.writes(), [(5200, vec![38]), (5201, vec![35])]);
+        assert_eq!(
+            link.requests().last(),
+            Some(&Request::SendCustom {
+                slave: 0x30,
+                function_code: 0x79,
+                data: vec![0, 0, 0, 1]
+            })
+        );
+    }
 }
@@ -17,10 +17,16 @@ pub enum RenogyError {
     DeviceControlFailed,
     #[error("invalid register address or range")]
     InvalidRegisterRange,
+    #[error("register data is {actual} bytes, expected {expected}")]
+    InvalidLength { expected: usize, actual: usize },
     #[error("write operation failed")]
     WriteOperationFailed,
     #[error("Bluetooth error: {0}")]
     Bluetooth(String),
+    #[error("timeout waiting for response")]
+    Timeout,
+    #[error("transport task stopped")]
+    TransportClosed,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
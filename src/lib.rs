@@ -1,16 +1,31 @@
 pub mod alarm;
 pub mod any_transport;
+pub mod backup;
 pub mod bt2;
+pub mod btsnoop;
 pub mod collector;
 pub mod device;
+pub mod discovery;
 #[cfg(any(test, feature = "emulator"))]
 pub mod emulator;
 pub mod error;
+pub mod limits;
+#[cfg(any(test, feature = "emulator"))]
+pub mod mock_bluez;
 pub mod pdu;
 pub mod query;
+pub mod recording;
 pub mod registers;
+pub mod retry;
+pub mod rtu;
+pub mod rtu_serial;
+pub mod rtu_tcp;
 pub mod serial;
+pub mod serial_scan;
+pub mod shared_transport;
+pub mod snapshot;
 pub mod system_summary;
+pub mod tcp;
 pub mod transport;
 pub mod tui;
 pub mod util;
@@ -0,0 +1,547 @@
+//! The protection and warning limits (registers 5200-5221) as one typed value, checked
+//! for consistency before any of it is written: a warning set beyond its protection
+//! level, or a charge temperature window wider than the discharge window, is refused.
+
+use crate::device::DeviceCommand;
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::registers::DataType;
+use crate::registers::Register;
+use crate::registers::Value;
+use crate::transport::Transport;
+use std::fmt;
+use std::ops::RangeInclusive;
+use thiserror::Error;
+use uom::si::electric_current::ampere;
+use uom::si::electric_potential::volt;
+use uom::si::f32::ElectricCurrent;
+use uom::si::f32::ElectricPotential;
+use uom::si::f32::ThermodynamicTemperature;
+use uom::si::thermodynamic_temperature::degree_celsius;
+
+/// Each family of tiers, highest first; every tier must be strictly above the next.
+const TIERS: [&[Register]; 6] = [
+    &[
+        Register::CellOverVoltageLimit,
+        Register::CellHighVoltageLimit,
+        Register::CellLowVoltageLimit,
+        Register::CellUnderVoltageLimit,
+    ],
+    &[
+        Register::ChargeOverTemperatureLimit,
+        Register::ChargeHighTemperatureLimit,
+        Register::ChargeLowTemperatureLimit,
+        Register::ChargeUnderTemperatureLimit,
+    ],
+    &[
+        Register::ChargeOver2CurrentLimit,
+        Register::ChargeOver1CurrentLimit,
+        Register::ChargeHighCurrentLimit,
+    ],
+    &[
+        Register::ModuleOverVoltageLimit,
+        Register::ModuleHighVoltageLimit,
+        Register::ModuleLowVoltageLimit,
+        Register::ModuleUnderVoltageLimit,
+    ],
+    &[
+        Register::DischargeOverTemperatureLimit,
+        Register::DischargeHighTemperatureLimit,
+        Register::DischargeLowTemperatureLimit,
+        Register::DischargeUnderTemperatureLimit,
+    ],
+    &[
+        Register::DischargeOver2CurrentLimit,
+        Register::DischargeOver1CurrentLimit,
+        Register::DischargeHighCurrentLimit,
+    ],
+];
+
+/// (higher, lower) pairs that keep each charge temperature inside the discharge
+/// window; they may be equal.
+const WINDOWS: [(Register, Register); 4] = [
+    (
+        Register::DischargeOverTemperatureLimit,
+        Register::ChargeOverTemperatureLimit,
+    ),
+    (
+        Register::DischargeHighTemperatureLimit,
+        Register::ChargeHighTemperatureLimit,
+    ),
+    (
+        Register::ChargeLowTemperatureLimit,
+        Register::DischargeLowTemperatureLimit,
+    ),
+    (
+        Register::ChargeUnderTemperatureLimit,
+        Register::DischargeUnderTemperatureLimit,
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VoltageTiers {
+    pub over: ElectricPotential,
+    pub high: ElectricPotential,
+    pub low: ElectricPotential,
+    pub under: ElectricPotential,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TemperatureTiers {
+    pub over: ThermodynamicTemperature,
+    pub high: ThermodynamicTemperature,
+    pub low: ThermodynamicTemperature,
+    pub under: ThermodynamicTemperature,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurrentTiers {
+    pub over2: ElectricCurrent,
+    pub over1: ElectricCurrent,
+    pub high: ElectricCurrent,
+}
+
+/// Every protection and warning limit of a battery, in register order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BmsLimits {
+    pub cell_voltage: VoltageTiers,
+    pub charge_temperature: TemperatureTiers,
+    pub charge_current: CurrentTiers,
+    pub module_voltage: VoltageTiers,
+    pub discharge_temperature: TemperatureTiers,
+    pub discharge_current: CurrentTiers,
+}
+
+/// A rule a set of limits breaks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Violation {
+    /// Outside the register's documented range, or more than its word can hold.
+    OutOfRange { register: Register, value: f32 },
+    /// `higher` must be above `lower`, or, unless `strict`, equal to it.
+    Order {
+        higher: Register,
+        higher_value: f32,
+        lower: Register,
+        lower_value: f32,
+        strict: bool,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum LimitsError {
+    #[error(transparent)]
+    Renogy(#[from] RenogyError),
+    #[error("inconsistent limits: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Inconsistent(Vec<Violation>),
+    #[error("limits read back {read:04X?} after writing {written:04X?}")]
+    NotApplied { written: Vec<u16>, read: Vec<u16> },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::OutOfRange { register, value } => match register.documented_range() {
+                Some(range) => write!(
+                    f,
+                    "{} = {} is outside {}..={}",
+                    register.name(),
+                    show(register, *value),
+                    show(register, *range.start()),
+                    show(register, *range.end())
+                ),
+                None => write!(
+                    f,
+                    "{} = {} does not fit the register",
+                    register.name(),
+                    show(register, *value)
+                ),
+            },
+            Violation::Order {
+                higher,
+                higher_value,
+                lower,
+                lower_value,
+                strict,
+            } => write!(
+                f,
+                "{} ({}) must be {} {} ({})",
+                higher.name(),
+                show(higher, *higher_value),
+                if *strict { "above" } else { "at least" },
+                lower.name(),
+                show(lower, *lower_value)
+            ),
+        }
+    }
+}
+
+impl BmsLimits {
+    /// The registers holding the limits.
+    pub const ADDRESSES: RangeInclusive<u16> = 5200..=5221;
+
+    pub async fn read<T: Transport>(transport: &mut T, slave: u8) -> Result<Self> {
+        let words = transport
+            .read_holding_registers(slave, *Self::ADDRESSES.start(), Self::words())
+            .await?;
+        Self::from_words(&words)
+    }
+
+    /// Decode the words of `ADDRESSES`.
+    pub fn from_words(words: &[u16]) -> Result<Self> {
+        if words.len() != usize::from(Self::words()) {
+            return Err(RenogyError::InvalidLength {
+                expected: usize::from(Self::words()) * 2,
+                actual: words.len() * 2,
+            });
+        }
+        let value = |register: Register| {
+            let offset = usize::from(register.address() - Self::ADDRESSES.start());
+            register.parse_registers(&words[offset..=offset])
+        };
+        let volts = |register| {
+            value(register)?
+                .as_voltage()
+                .ok_or(RenogyError::InvalidData)
+        };
+        let celsius = |register| {
+            value(register)?
+                .as_temperature()
+                .ok_or(RenogyError::InvalidData)
+        };
+        let amps = |register| {
+            value(register)?
+                .as_current()
+                .ok_or(RenogyError::InvalidData)
+        };
+        Ok(Self {
+            cell_voltage: VoltageTiers {
+                over: volts(Register::CellOverVoltageLimit)?,
+                high: volts(Register::CellHighVoltageLimit)?,
+                low: volts(Register::CellLowVoltageLimit)?,
+                under: volts(Register::CellUnderVoltageLimit)?,
+            },
+            charge_temperature: TemperatureTiers {
+                over: celsius(Register::ChargeOverTemperatureLimit)?,
+                high: celsius(Register::ChargeHighTemperatureLimit)?,
+                low: celsius(Register::ChargeLowTemperatureLimit)?,
+                under: celsius(Register::ChargeUnderTemperatureLimit)?,
+            },
+            charge_current: CurrentTiers {
+                over2: amps(Register::ChargeOver2CurrentLimit)?,
+                over1: amps(Register::ChargeOver1CurrentLimit)?,
+                high: amps(Register::ChargeHighCurrentLimit)?,
+            },
+            module_voltage: VoltageTiers {
+                over: volts(Register::ModuleOverVoltageLimit)?,
+                high: volts(Register::ModuleHighVoltageLimit)?,
+                low: volts(Register::ModuleLowVoltageLimit)?,
+                under: volts(Register::ModuleUnderVoltageLimit)?,
+            },
+            discharge_temperature: TemperatureTiers {
+                over: celsius(Register::DischargeOverTemperatureLimit)?,
+                high: celsius(Register::DischargeHighTemperatureLimit)?,
+                low: celsius(Register::DischargeLowTemperatureLimit)?,
+                under: celsius(Register::DischargeUnderTemperatureLimit)?,
+            },
+            discharge_current: CurrentTiers {
+                over2: amps(Register::DischargeOver2CurrentLimit)?,
+                over1: amps(Register::DischargeOver1CurrentLimit)?,
+                high: amps(Register::DischargeHighCurrentLimit)?,
+            },
+        })
+    }
+
+    /// Every rule the limits break: values the registers cannot hold, tier order within
+    /// each family, and the charge temperature window inside the discharge window.
+    /// Order is checked on the values as written, at the registers' resolution: an
+    /// over-voltage of 3.61 V and a high-voltage of 3.60 V are both written as 3.6 V.
+    #[must_use]
+    pub fn violations(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let mut written = Vec::new();
+        for (register, value) in self.values() {
+            match encode(&register, value).and_then(|word| decode(&register, word)) {
+                Ok(value) => written.push((register, value)),
+                Err(_) => violations.push(Violation::OutOfRange { register, value }),
+            }
+        }
+        let value = |register: &Register| {
+            written
+                .iter()
+                .find(|(r, _)| r == register)
+                .map(|(_, value)| *value)
+        };
+        let pairs = TIERS
+            .iter()
+            .flat_map(|family| family.windows(2).map(|pair| (&pair[0], &pair[1], true)))
+            .chain(WINDOWS.iter().map(|(higher, lower)| (higher, lower, false)));
+        for (higher, lower, strict) in pairs {
+            let (Some(higher_value), Some(lower_value)) = (value(higher), value(lower)) else {
+                continue;
+            };
+            let holds = if strict {
+                higher_value > lower_value
+            } else {
+                higher_value >= lower_value
+            };
+            if !holds {
+                violations.push(Violation::Order {
+                    higher: higher.clone(),
+                    higher_value,
+                    lower: lower.clone(),
+                    lower_value,
+                    strict,
+                });
+            }
+        }
+        violations
+    }
+
+    pub fn validate(&self) -> std::result::Result<(), LimitsError> {
+        let violations = self.violations();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(LimitsError::Inconsistent(violations))
+        }
+    }
+
+    /// The words of `ADDRESSES`, once the limits are known to be consistent.
+    pub fn to_words(&self) -> std::result::Result<Vec<u16>, LimitsError> {
+        self.validate()?;
+        Ok(self
+            .values()
+            .into_iter()
+            .map(|(register, value)| encode(&register, value))
+            .collect::<Result<_>>()?)
+    }
+
+    /// Validate, then write all the limits in one request between an unlock and a
+    /// lock and read them back. Nothing is sent if the limits are inconsistent.
+    pub async fn write<T: Transport>(
+        &self,
+        transport: &mut T,
+        slave: u8,
+    ) -> std::result::Result<(), LimitsError> {
+        let words = self.to_words()?;
+        DeviceCommand::Unlock.send(transport, slave).await?;
+        let result = Self::write_words(transport, slave, &words).await;
+        let locked = DeviceCommand::Lock.send(transport, slave).await;
+        result?;
+        Ok(locked?)
+    }
+
+    async fn write_words<T: Transport>(
+        transport: &mut T,
+        slave: u8,
+        words: &[u16],
+    ) -> std::result::Result<(), LimitsError> {
+        let start = *Self::ADDRESSES.start();
+        transport
+            .write_multiple_registers(slave, start, words)
+            .await?;
+        let read = transport
+            .read_holding_registers(slave, start, Self::words())
+            .await?;
+        if read != words {
+            return Err(LimitsError::NotApplied {
+                written: words.to_vec(),
+                read,
+            });
+        }
+        Ok(())
+    }
+
+    fn words() -> u16 {
+        Self::ADDRESSES.end() - Self::ADDRESSES.start() + 1
+    }
+
+    /// Each limit in register order, in the catalog unit.
+    fn values(&self) -> Vec<(Register, f32)> {
+        let volts = |tiers: &VoltageTiers| {
+            [tiers.over, tiers.high, tiers.low, tiers.under].map(|v| v.get::<volt>())
+        };
+        let celsius = |tiers: &TemperatureTiers| {
+            [tiers.over, tiers.high, tiers.low, tiers.under].map(|t| t.get::<degree_celsius>())
+        };
+        let amps = |tiers: &CurrentTiers| {
+            [tiers.over2, tiers.over1, tiers.high].map(|c| c.get::<ampere>())
+        };
+        let values = volts(&self.cell_voltage)
+            .into_iter()
+            .chain(celsius(&self.charge_temperature))
+            .chain(amps(&self.charge_current))
+            .chain(volts(&self.module_voltage))
+            .chain(celsius(&self.discharge_temperature))
+            .chain(amps(&self.discharge_current));
+        TIERS
+            .iter()
+            .flat_map(|family| family.iter().cloned())
+            .zip(values)
+            .collect()
+    }
+}
+
+/// The word `value` is written as, in the register's catalog unit.
+fn encode(register: &Register, value: f32) -> Result<u16> {
+    let value = match register.info().data_type {
+        DataType::Voltage => Value::ElectricPotential(ElectricPotential::new::<volt>(value)),
+        DataType::Current => Value::ElectricCurrent(ElectricCurrent::new::<ampere>(value)),
+        _ => {
+            Value::ThermodynamicTemperature(ThermodynamicTemperature::new::<degree_celsius>(value))
+        }
+    };
+    let bytes = register.encode_value(&value)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Inverse of `encode`.
+fn decode(register: &Register, word: u16) -> Result<f32> {
+    match register.parse_registers(&[word])? {
+        Value::ElectricPotential(v) => Ok(v.get::<volt>()),
+        Value::ElectricCurrent(c) => Ok(c.get::<ampere>()),
+        Value::ThermodynamicTemperature(t) => Ok(t.get::<degree_celsius>()),
+        _ => Err(RenogyError::InvalidData),
+    }
+}
+
+/// `value` at the register's resolution, with its unit.
+fn show(register: &Register, value: f32) -> String {
+    let info = register.info();
+    let decimals = (-info.scale.log10()).round().max(0.0) as usize;
+    match info.unit {
+        Some(unit) => format!("{value:.decimals$} {unit}"),
+        None => format!("{value:.decimals$}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BmsLimits;
+    use super::LimitsError;
+    use super::Violation;
+    use crate::emulator::EmulatedBattery;
+    use crate::registers::Register;
+    use crate::transport::Transport;
+    use uom::si::electric_potential::volt;
+    use uom::si::f32::ElectricPotential;
+    use uom::si::f32::ThermodynamicTemperature;
+    use uom::si::thermodynamic_temperature::degree_celsius;
+
+    async fn lfp_12v() -> BmsLimits {
+        BmsLimits::read(&mut EmulatedBattery::lfp_12v(0x30), 0x30)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn consistent_limits_validate() {
+        assert_eq!(lfp_12v().await.violations(), []);
+    }
+
+    #[tokio::test]
+    async fn tiers_must_descend() {
+        let mut limits = lfp_12v().await;
+        limits.cell_voltage.high = ElectricPotential::new::<volt>(3.8);
+        limits.discharge_current.high = limits.discharge_current.over1;
+        let violations = limits.violations();
+        assert_eq!(violations.len(), 2, "{violations:?}");
+        assert!(matches!(
+            &violations[0],
+            Violation::Order {
+                higher: Register::CellOverVoltageLimit,
+                lower: Register::CellHighVoltageLimit,
+                strict: true,
+                ..
+            }
+        ));
+        assert_eq!(
+            violations[0].to_string(),
+            "cell_over_voltage_limit (3.7 V) must be above cell_high_voltage_limit (3.8 V)"
+        );
+        assert!(matches!(
+            &violations[1],
+            Violation::Order {
+                higher: Register::DischargeOver1CurrentLimit,
+                lower: Register::DischargeHighCurrentLimit,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn charge_window_must_sit_inside_discharge_window() {
+        let mut limits = lfp_12v().await;
+        limits.charge_temperature.over = ThermodynamicTemperature::new::<degree_celsius>(70.0);
+        limits.charge_temperature.high = limits.discharge_temperature.high;
+        let violations = limits.violations();
+        assert_eq!(violations.len(), 1, "{violations:?}");
+        assert_eq!(
+            violations[0].to_string(),
+            "discharge_over_temperature_limit (65.0 °C) must be at least charge_over_temperature_limit (70.0 °C)"
+        );
+    }
+
+    #[tokio::test]
+    async fn out_of_range_values_are_violations() {
+        let mut limits = lfp_12v().await;
+        limits.cell_voltage.under = ElectricPotential::new::<volt>(-1.0);
+        assert_eq!(
+            limits.violations(),
+            [Violation::OutOfRange {
+                register: Register::CellUnderVoltageLimit,
+                value: -1.0
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn order_is_checked_at_register_resolution() {
+        let mut limits = lfp_12v().await;
+        limits.cell_voltage.over = ElectricPotential::new::<volt>(3.61);
+        limits.cell_voltage.high = ElectricPotential::new::<volt>(3.60);
+        let violations = limits.violations();
+        assert_eq!(violations.len(), 1, "{violations:?}");
+        assert_eq!(
+            violations[0].to_string(),
+            "cell_over_voltage_limit (3.6 V) must be above cell_high_voltage_limit (3.6 V)"
+        );
+        assert!(matches!(
+            limits.to_words(),
+            Err(LimitsError::Inconsistent(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn write_then_read_round_trips() {
+        let mut bms = EmulatedBattery::new(0x30);
+        lfp_12v().await.write(&mut bms, 0x30).await.unwrap();
+        assert_eq!(
+            BmsLimits::read(&mut bms, 0x30).await.unwrap().violations(),
+            []
+        );
+        let words = bms.read_holding_registers(0x30, 5200, 22).await.unwrap();
+        assert_eq!(&words[..4], [37, 36, 28, 25]);
+        assert_eq!(
+            BmsLimits::from_words(&words).unwrap().to_words().unwrap(),
+            words
+        );
+        // Locked again afterwards.
+        assert_eq!(
+            bms.read_holding_registers(0x30, 5224, 1).await.unwrap(),
+            [0x5A5A]
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_limits_are_never_sent() {
+        let mut bms = EmulatedBattery::new(0x30);
+        let mut limits = lfp_12v().await;
+        limits.module_voltage.under = limits.module_voltage.low;
+        let err = limits.write(&mut bms, 0x30).await.unwrap_err();
+        assert!(matches!(err, LimitsError::Inconsistent(_)), "{err}");
+        // Not even the unlock went out.
+        let words = bms.read_holding_registers(0x30, 5200, 25).await.unwrap();
+        assert!(words.iter().all(|&word| word == 0), "{words:?}");
+    }
+}
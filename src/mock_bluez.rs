@@ -0,0 +1,426 @@
+//! A stand-in for BlueZ on a private peer-to-peer D-Bus connection, so `Bt2Transport`
+//! and `Bt2Discovery` can be exercised without a radio or a system bus.
+//!
+//! `MockBluez::start` serves an `ObjectManager` at `/`, an `org.bluez.Adapter1` at
+//! `/org/bluez/hci0` and one BT-2 `org.bluez.Device1` whose ffd1 (write) and fff1
+//! (notify) `GattCharacteristic1`s are backed by `EmulatedBattery`s: every request
+//! written to ffd1 is answered by notifications on fff1, cut into ATT-sized chunks the
+//! way the module does. Tests steer the peripheral through `MockBluez`, e.g. to drop
+//! the link or stop answering.
+
+use crate::bt2::BT2_NOTIFY_CHAR_UUID;
+use crate::bt2::BT2_WRITE_CHAR_UUID;
+use crate::emulator::EmulatedBattery;
+use crate::pdu::Pdu;
+use std::collections::HashMap;
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use zbus::Connection;
+use zbus::Guid;
+use zbus::ObjectServer;
+use zbus::connection::Builder;
+use zbus::fdo;
+use zbus::interface;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::zvariant::OwnedValue;
+
+/// MAC of the BT-2 created by `MockBluez::start`.
+pub const BT2_ADDRESS: &str = "C4:D3:6A:00:00:01";
+
+/// RSSI the BT-2 created by `MockBluez::start` is heard at.
+pub const BT2_RSSI: i16 = -60;
+
+/// Notification payload size of a BT-2 at the default ATT MTU.
+pub const DEFAULT_CHUNK_SIZE: usize = 20;
+
+/// BlueZ object path of a device.
+#[must_use]
+pub fn device_path(adapter: &str, address: &str) -> String {
+    format!("/org/bluez/{adapter}/dev_{}", address.replace(':', "_"))
+}
+
+/// The emulated module behind the characteristics.
+struct Peripheral {
+    batteries: Vec<EmulatedBattery>,
+    chunk_size: usize,
+    silent: bool,
+    notifying: bool,
+}
+
+impl Peripheral {
+    /// Notifications answering the request `frame`; none if nobody on the bus answers.
+    fn answer(&mut self, frame: &[u8]) -> Vec<Vec<u8>> {
+        if self.silent || !self.notifying {
+            return Vec::new();
+        }
+        let Ok(request) = Pdu::deserialize(frame) else {
+            return Vec::new();
+        };
+        let Some(bms) = self
+            .batteries
+            .iter_mut()
+            .find(|bms| bms.slave() == request.address)
+        else {
+            return Vec::new();
+        };
+        match bms.respond(&request) {
+            Ok(response) => response
+                .serialize()
+                .chunks(self.chunk_size)
+                .map(<[u8]>::to_vec)
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+struct MockAdapter {
+    discovering: bool,
+}
+
+#[interface(name = "org.bluez.Adapter1")]
+impl MockAdapter {
+    async fn start_discovery(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        self.discovering = true;
+        self.discovering_changed(&emitter).await?;
+        Ok(())
+    }
+
+    async fn stop_discovery(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        self.discovering = false;
+        self.discovering_changed(&emitter).await?;
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn discovering(&self) -> bool {
+        self.discovering
+    }
+
+    #[zbus(property)]
+    fn powered(&self) -> bool {
+        true
+    }
+}
+
+struct MockDevice {
+    address: String,
+    name: String,
+    rssi: i16,
+    connected: bool,
+    /// The BT-2's module, or `None` for other devices in range.
+    peripheral: Option<Arc<Mutex<Peripheral>>>,
+}
+
+impl MockDevice {
+    async fn set_connected(
+        &mut self,
+        connected: bool,
+        emitter: &SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        self.connected = connected;
+        if !connected && let Some(peripheral) = &self.peripheral {
+            peripheral.lock().unwrap().notifying = false;
+        }
+        self.connected_changed(emitter).await?;
+        self.services_resolved_changed(emitter).await?;
+        Ok(())
+    }
+}
+
+#[interface(name = "org.bluez.Device1")]
+impl MockDevice {
+    async fn connect(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        self.set_connected(true, &emitter).await
+    }
+
+    async fn disconnect(
+        &mut self,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> fdo::Result<()> {
+        self.set_connected(false, &emitter).await
+    }
+
+    #[zbus(property)]
+    fn address(&self) -> String {
+        self.address.clone()
+    }
+
+    #[zbus(property)]
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[zbus(property)]
+    fn alias(&self) -> String {
+        self.name.clone()
+    }
+
+    #[zbus(property, name = "RSSI")]
+    fn rssi(&self) -> i16 {
+        self.rssi
+    }
+
+    #[zbus(property)]
+    fn paired(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn connected(&self) -> bool {
+        self.connected
+    }
+
+    #[zbus(property)]
+    fn services_resolved(&self) -> bool {
+        self.connected
+    }
+}
+
+struct MockCharacteristic {
+    uuid: &'static str,
+    value: Vec<u8>,
+    peripheral: Arc<Mutex<Peripheral>>,
+    /// For the write characteristic, where its answers are notified.
+    notify_path: Option<OwnedObjectPath>,
+}
+
+#[interface(name = "org.bluez.GattCharacteristic1")]
+impl MockCharacteristic {
+    fn read_value(&self, _options: HashMap<String, OwnedValue>) -> Vec<u8> {
+        self.value.clone()
+    }
+
+    async fn write_value(
+        &self,
+        value: Vec<u8>,
+        _options: HashMap<String, OwnedValue>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        let Some(notify_path) = &self.notify_path else {
+            return Err(fdo::Error::NotSupported("write not permitted".into()));
+        };
+        let chunks = self.peripheral.lock().unwrap().answer(&value);
+        let notify = server
+            .interface::<_, MockCharacteristic>(notify_path)
+            .await?;
+        for chunk in chunks {
+            notify.get_mut().await.value = chunk;
+            notify
+                .get()
+                .await
+                .value_changed(notify.signal_emitter())
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn start_notify(&self) {
+        self.peripheral.lock().unwrap().notifying = true;
+    }
+
+    fn stop_notify(&self) {
+        self.peripheral.lock().unwrap().notifying = false;
+    }
+
+    #[zbus(property, name = "UUID")]
+    fn uuid(&self) -> String {
+        self.uuid.to_string()
+    }
+
+    #[zbus(property)]
+    fn value(&self) -> Vec<u8> {
+        self.value.clone()
+    }
+
+    #[zbus(property)]
+    fn notifying(&self) -> bool {
+        self.peripheral.lock().unwrap().notifying
+    }
+}
+
+/// BlueZ with one adapter and one BT-2, on a private connection.
+pub struct MockBluez {
+    server: Connection,
+    client: Connection,
+    peripheral: Arc<Mutex<Peripheral>>,
+}
+
+impl MockBluez {
+    /// Serve a BT-2 at `BT2_ADDRESS` on hci0, disconnected, with `batteries` behind it.
+    pub async fn start(batteries: Vec<EmulatedBattery>) -> zbus::Result<Self> {
+        let (server_socket, client_socket) = UnixStream::pair()?;
+        let server = Builder::unix_stream(server_socket)
+            .server(Guid::generate())?
+            .p2p()
+            .serve_at("/", fdo::ObjectManager)?
+            .build();
+        let client = Builder::unix_stream(client_socket).p2p().build();
+        // The two ends authenticate against each other, so both must make progress.
+        let (server, client) = futures::try_join!(server, client)?;
+
+        let mock = Self {
+            server,
+            client,
+            peripheral: Arc::new(Mutex::new(Peripheral {
+                batteries,
+                chunk_size: DEFAULT_CHUNK_SIZE,
+                silent: false,
+                notifying: false,
+            })),
+        };
+
+        let device = mock
+            .add(
+                "hci0",
+                BT2_ADDRESS,
+                "BT-TH-6A000001",
+                BT2_RSSI,
+                Some(Arc::clone(&mock.peripheral)),
+            )
+            .await?;
+        let notify_path = OwnedObjectPath::try_from(format!("{device}/service000c/char000d"))?;
+        let object_server = mock.server.object_server();
+        object_server
+            .at(
+                &notify_path,
+                MockCharacteristic {
+                    uuid: BT2_NOTIFY_CHAR_UUID,
+                    value: Vec::new(),
+                    peripheral: Arc::clone(&mock.peripheral),
+                    notify_path: None,
+                },
+            )
+            .await?;
+        object_server
+            .at(
+                format!("{device}/service0010/char0011"),
+                MockCharacteristic {
+                    uuid: BT2_WRITE_CHAR_UUID,
+                    value: Vec::new(),
+                    peripheral: Arc::clone(&mock.peripheral),
+                    notify_path: Some(notify_path),
+                },
+            )
+            .await?;
+        Ok(mock)
+    }
+
+    /// The client end, to hand to `Bt2Transport::connect_with` or
+    /// `Bt2Discovery::scan_with`.
+    pub fn connection(&self) -> &Connection {
+        &self.client
+    }
+
+    /// Object path of the BT-2.
+    #[must_use]
+    pub fn bt2_path(&self) -> String {
+        device_path("hci0", BT2_ADDRESS)
+    }
+
+    /// Another device in range, without the BT-2 characteristics. The adapter is
+    /// created if needed. Returns the device's object path.
+    pub async fn add_device(
+        &self,
+        adapter: &str,
+        address: &str,
+        name: &str,
+        rssi: i16,
+    ) -> zbus::Result<String> {
+        self.add(adapter, address, name, rssi, None).await
+    }
+
+    async fn add(
+        &self,
+        adapter: &str,
+        address: &str,
+        name: &str,
+        rssi: i16,
+        peripheral: Option<Arc<Mutex<Peripheral>>>,
+    ) -> zbus::Result<String> {
+        let object_server = self.server.object_server();
+        object_server
+            .at(
+                format!("/org/bluez/{adapter}"),
+                MockAdapter { discovering: false },
+            )
+            .await?;
+        let path = device_path(adapter, address);
+        object_server
+            .at(
+                path.as_str(),
+                MockDevice {
+                    address: address.to_string(),
+                    name: name.to_string(),
+                    rssi,
+                    connected: false,
+                    peripheral,
+                },
+            )
+            .await?;
+        Ok(path)
+    }
+
+    /// Notification payload size; 1 delivers every response a byte at a time.
+    pub fn set_chunk_size(&self, chunk_size: usize) {
+        self.peripheral.lock().unwrap().chunk_size = chunk_size.max(1);
+    }
+
+    /// Accept writes but never answer them.
+    pub fn set_silent(&self, silent: bool) {
+        self.peripheral.lock().unwrap().silent = silent;
+    }
+
+    /// Whether BlueZ currently reports the BT-2 connected.
+    pub async fn bt2_connected(&self) -> zbus::Result<bool> {
+        let device = self
+            .server
+            .object_server()
+            .interface::<_, MockDevice>(self.bt2_path())
+            .await?;
+        Ok(device.get().await.connected)
+    }
+
+    /// Drop the BLE link, as when the module goes out of range: BlueZ reports the
+    /// device disconnected and notifications stop until restarted.
+    pub async fn drop_link(&self) -> zbus::Result<()> {
+        let device = self
+            .server
+            .object_server()
+            .interface::<_, MockDevice>(self.bt2_path())
+            .await?;
+        device
+            .get_mut()
+            .await
+            .set_connected(false, device.signal_emitter())
+            .await?;
+        Ok(())
+    }
+
+    /// Send an unsolicited notification, e.g. a late answer to an earlier request.
+    pub async fn notify(&self, data: &[u8]) -> zbus::Result<()> {
+        let notify = self
+            .server
+            .object_server()
+            .interface::<_, MockCharacteristic>(format!("{}/service000c/char000d", self.bt2_path()))
+            .await?;
+        notify.get_mut().await.value = data.to_vec();
+        notify
+            .get()
+            .await
+            .value_changed(notify.signal_emitter())
+            .await?;
+        Ok(())
+    }
+}
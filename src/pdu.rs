@@ -57,12 +57,57 @@ impl Pdu {
         }
     }
 
+    #[must_use]
+    pub fn read_holding_registers(address: u8, start: u16, quantity: u16) -> Self {
+        let payload = [start.to_be_bytes(), quantity.to_be_bytes()].concat();
+        Self::new(address, FunctionCode::ReadHoldingRegisters, payload)
+    }
+
+    #[must_use]
+    pub fn write_single_register(address: u8, register: u16, value: u16) -> Self {
+        let payload = [register.to_be_bytes(), value.to_be_bytes()].concat();
+        Self::new(address, FunctionCode::WriteSingleRegister, payload)
+    }
+
+    #[must_use]
+    pub fn write_multiple_registers(address: u8, start: u16, values: &[u16]) -> Self {
+        let mut payload = Vec::with_capacity(5 + values.len() * 2);
+        payload.extend_from_slice(&start.to_be_bytes());
+        payload.extend_from_slice(&(values.len() as u16).to_be_bytes());
+        payload.push((values.len() * 2) as u8);
+        for value in values {
+            payload.extend_from_slice(&value.to_be_bytes());
+        }
+        Self::new(address, FunctionCode::WriteMultipleRegisters, payload)
+    }
+
+    /// Decode the register words of a Read Holding Registers response, which must hold
+    /// exactly `quantity` of them: a response to a read of another size is not ours.
+    pub fn register_values(&self, quantity: u16) -> Result<Vec<u16>> {
+        let byte_count = *self.payload.first().ok_or(RenogyError::InvalidData)? as usize;
+        if self.payload.len() < 1 + byte_count || byte_count != quantity as usize * 2 {
+            return Err(RenogyError::InvalidData);
+        }
+
+        Ok(self.payload[1..=byte_count]
+            .chunks_exact(2)
+            .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+            .collect())
+    }
+
+    /// Address, function code and payload, without a CRC (the Modbus TCP ADU body).
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 + self.payload.len());
+        bytes.push(self.address);
+        bytes.push(self.function_code as u8);
+        bytes.extend(&self.payload);
+        bytes
+    }
+
     #[must_use]
     pub fn serialize(&self) -> Vec<u8> {
-        let mut frame = Vec::with_capacity(2 + self.payload.len() + 2);
-        frame.push(self.address);
-        frame.push(self.function_code as u8);
-        frame.extend(&self.payload);
+        let mut frame = self.to_bytes();
         let crc = MODBUS_CRC.checksum(&frame);
         frame.extend(&crc.to_le_bytes());
         frame
@@ -86,6 +131,16 @@ impl Pdu {
             return Err(RenogyError::CrcMismatch);
         }
 
+        Self::from_bytes(data)
+    }
+
+    /// Inverse of `to_bytes`: decode an address/function code/payload sequence that
+    /// carries no CRC. Exception responses are returned as `ModbusException`.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if data.len() < 2 {
+            return Err(RenogyError::InvalidData);
+        }
+
         let address = data[0];
         let function_code_byte = data[1];
 
@@ -139,4 +194,32 @@ mod tests {
             Err(RenogyError::CrcMismatch)
         ));
     }
+
+    #[test]
+    fn register_values_rejects_short_response() {
+        let pdu = Pdu::new(
+            1,
+            FunctionCode::ReadHoldingRegisters,
+            vec![0x02, 0x00, 0x21],
+        );
+        assert!(pdu.register_values(1).is_ok());
+        assert!(matches!(
+            pdu.register_values(2),
+            Err(RenogyError::InvalidData)
+        ));
+    }
+
+    #[test]
+    fn register_values_rejects_long_response() {
+        let pdu = Pdu::new(
+            1,
+            FunctionCode::ReadHoldingRegisters,
+            vec![0x04, 0x00, 0x21, 0x00, 0x22],
+        );
+        assert_eq!(pdu.register_values(2).unwrap(), [0x21, 0x22]);
+        assert!(matches!(
+            pdu.register_values(1),
+            Err(RenogyError::InvalidData)
+        ));
+    }
 }
@@ -5,11 +5,14 @@ use crate::alarm::OtherAlarmInfo;
 use crate::alarm::Status1;
 use crate::alarm::Status2;
 use crate::alarm::Status3;
+use crate::error::RenogyError;
 use crate::registers::Register;
 use crate::registers::Value;
 use crate::transport::Transport;
+use crate::util::clean_string;
 use chrono::DateTime;
 use chrono::Utc;
+use std::ops::RangeInclusive;
 use uom::si::electric_current::ampere;
 use uom::si::electric_potential::volt;
 use uom::si::thermodynamic_temperature::degree_celsius;
@@ -27,6 +30,9 @@ const STATUS2_NON_ALARM_FLAGS: Status2 = Status2::EFFECTIVE_CHARGE_CURRENT
 pub struct BatteryInfo {
     pub timestamp: DateTime<Utc>,
     pub serial: String,
+    /// Name of the collector link the sample was polled over; empty unless the
+    /// collector runs several links.
+    pub link: String,
     pub model: String,
     pub software_version: String,
     pub manufacturer: String,
@@ -101,39 +107,155 @@ impl BatteryInfo {
     }
 }
 
+/// Register blocks `query_battery` reads, one request each. The BT-2 phone app never
+/// reads more than 34 registers at once, so the 5000 block is split to match.
+const BLOCKS: [RangeInclusive<u16>; 4] = [5000..=5033, 5034..=5052, 5100..=5109, 5110..=5141];
+
+enum Block {
+    Loaded(Vec<u16>),
+    /// The BMS rejected the block read with a Modbus exception; read its registers one
+    /// at a time instead.
+    PerRegister,
+    Failed,
+}
+
+/// Reads registers out of `BLOCKS`, fetching each block on first use.
+struct BlockReader<'a, T: Transport> {
+    transport: &'a mut T,
+    addr: u8,
+    blocks: Vec<(RangeInclusive<u16>, Option<Block>)>,
+}
+
+impl<'a, T: Transport> BlockReader<'a, T> {
+    fn new(transport: &'a mut T, addr: u8) -> Self {
+        Self {
+            transport,
+            addr,
+            blocks: BLOCKS.iter().map(|range| (range.clone(), None)).collect(),
+        }
+    }
+
+    async fn read(&mut self, register: Register) -> Option<Value> {
+        let start = register.address();
+        let end = start + register.quantity() - 1;
+        let Some(index) = self
+            .blocks
+            .iter()
+            .position(|(range, _)| range.contains(&start) && range.contains(&end))
+        else {
+            return self.read_single(&register).await;
+        };
+
+        if self.blocks[index].1.is_none() {
+            let block = self.load(self.blocks[index].0.clone()).await;
+            self.blocks[index].1 = Some(block);
+        }
+
+        let (range, block) = &self.blocks[index];
+        match block {
+            Some(Block::Loaded(words)) => {
+                let offset = (start - range.start()) as usize;
+                let words = words.get(offset..=(end - range.start()) as usize)?;
+                register.parse_registers(words).ok()
+            }
+            Some(Block::PerRegister) => self.read_single(&register).await,
+            Some(Block::Failed) | None => None,
+        }
+    }
+
+    async fn load(&mut self, range: RangeInclusive<u16>) -> Block {
+        let quantity = range.end() - range.start() + 1;
+        match self
+            .transport
+            .read_holding_registers(self.addr, *range.start(), quantity)
+            .await
+        {
+            Ok(words) => Block::Loaded(words),
+            Err(RenogyError::ModbusException(e)) => {
+                tracing::debug!(
+                    "Block read {:?} at 0x{:02X} rejected ({}), reading registers individually",
+                    range,
+                    self.addr,
+                    e
+                );
+                Block::PerRegister
+            }
+            Err(_) => Block::Failed,
+        }
+    }
+
+    async fn read_single(&mut self, register: &Register) -> Option<Value> {
+        let regs = self
+            .transport
+            .read_holding_registers(self.addr, register.address(), register.quantity())
+            .await
+            .ok()?;
+        register.parse_registers(&regs).ok()
+    }
+
+    async fn string(&mut self, register: Register) -> Option<String> {
+        self.read(register).await?.as_string().map(clean_string)
+    }
+
+    async fn integer(&mut self, register: Register) -> Option<u32> {
+        self.read(register).await?.as_integer()
+    }
+
+    async fn voltage(&mut self, register: Register) -> Option<f32> {
+        self.read(register)
+            .await?
+            .as_voltage()
+            .map(|v| v.get::<volt>())
+    }
+
+    async fn current(&mut self, register: Register) -> Option<f32> {
+        self.read(register)
+            .await?
+            .as_current()
+            .map(|c| c.get::<ampere>())
+    }
+
+    async fn temperature(&mut self, register: Register) -> Option<f32> {
+        self.read(register)
+            .await?
+            .as_temperature()
+            .map(|t| t.get::<degree_celsius>())
+    }
+}
+
 pub async fn query_battery<T: Transport>(transport: &mut T, addr: u8) -> Option<BatteryInfo> {
-    let serial = read_string(transport, addr, Register::SnNumber).await?;
-    let model = read_string(transport, addr, Register::BatteryName)
+    let mut reader = BlockReader::new(transport, addr);
+
+    let serial = reader.string(Register::SnNumber).await?;
+    let model = reader
+        .string(Register::BatteryName)
         .await
         .unwrap_or_default();
-    let software_version = read_string(transport, addr, Register::SoftwareVersion)
+    let software_version = reader
+        .string(Register::SoftwareVersion)
         .await
         .unwrap_or_default();
-    let manufacturer = read_string(transport, addr, Register::ManufacturerName)
+    let manufacturer = reader
+        .string(Register::ManufacturerName)
         .await
         .unwrap_or_default();
 
-    let cell_count = read_integer(transport, addr, Register::CellCount).await?;
+    let cell_count = reader.integer(Register::CellCount).await?;
 
     let mut cell_voltages = Vec::with_capacity(cell_count.min(16) as usize);
     for i in 1..=cell_count.min(16) {
-        if let Some(v) = read_voltage(transport, addr, Register::CellVoltage(i as u8)).await {
+        if let Some(v) = reader.voltage(Register::CellVoltage(i as u8)).await {
             cell_voltages.push(v);
         }
     }
 
-    let module_voltage = read_voltage(transport, addr, Register::ModuleVoltage)
-        .await
-        .unwrap_or(0.0);
-    let current = read_current(transport, addr, Register::Current)
-        .await
-        .unwrap_or(0.0);
-    let remaining_capacity = read_current(transport, addr, Register::RemainingCapacity)
-        .await
-        .unwrap_or(0.0);
-    let total_capacity = read_current(transport, addr, Register::TotalCapacity)
+    let module_voltage = reader.voltage(Register::ModuleVoltage).await.unwrap_or(0.0);
+    let current = reader.current(Register::Current).await.unwrap_or(0.0);
+    let remaining_capacity = reader
+        .current(Register::RemainingCapacity)
         .await
         .unwrap_or(0.0);
+    let total_capacity = reader.current(Register::TotalCapacity).await.unwrap_or(0.0);
 
     let soc_percent = if total_capacity > 0.0 {
         (remaining_capacity / total_capacity) * 100.0
@@ -141,66 +263,88 @@ pub async fn query_battery<T: Transport>(transport: &mut T, addr: u8) -> Option<
         0.0
     };
 
-    let cycle_count = read_integer(transport, addr, Register::CycleNumber)
-        .await
-        .unwrap_or(0);
+    let cycle_count = reader.integer(Register::CycleNumber).await.unwrap_or(0);
 
-    let cell_temp_count = read_integer(transport, addr, Register::CellTemperatureCount)
+    let cell_temp_count = reader
+        .integer(Register::CellTemperatureCount)
         .await
         .unwrap_or(0);
 
     let mut cell_temperatures = Vec::with_capacity(cell_temp_count.min(16) as usize);
     for i in 1..=cell_temp_count.min(16) {
-        if let Some(t) = read_temperature(transport, addr, Register::CellTemperature(i as u8)).await
-        {
+        if let Some(t) = reader.temperature(Register::CellTemperature(i as u8)).await {
             cell_temperatures.push(t);
         }
     }
 
-    let bms_temperature = read_temperature(transport, addr, Register::BmsTemperature).await;
+    let bms_temperature = reader.temperature(Register::BmsTemperature).await;
 
-    let env_temp_count = read_integer(transport, addr, Register::EnvironmentTemperatureCount)
+    let env_temp_count = reader
+        .integer(Register::EnvironmentTemperatureCount)
         .await
         .unwrap_or(0);
     let mut environment_temperatures = Vec::with_capacity(env_temp_count.min(2) as usize);
     for i in 1..=env_temp_count.min(2) {
-        if let Some(t) =
-            read_temperature(transport, addr, Register::EnvironmentTemperature(i as u8)).await
+        if let Some(t) = reader
+            .temperature(Register::EnvironmentTemperature(i as u8))
+            .await
         {
             environment_temperatures.push(t);
         }
     }
 
-    let heater_temp_count = read_integer(transport, addr, Register::HeaterTemperatureCount)
+    let heater_temp_count = reader
+        .integer(Register::HeaterTemperatureCount)
         .await
         .unwrap_or(0);
     let mut heater_temperatures = Vec::with_capacity(heater_temp_count.min(2) as usize);
     for i in 1..=heater_temp_count.min(2) {
-        if let Some(t) =
-            read_temperature(transport, addr, Register::HeaterTemperature(i as u8)).await
+        if let Some(t) = reader
+            .temperature(Register::HeaterTemperature(i as u8))
+            .await
         {
             heater_temperatures.push(t);
         }
     }
 
-    let charge_voltage_limit = read_voltage(transport, addr, Register::ChargeVoltageLimit).await;
-    let discharge_voltage_limit =
-        read_voltage(transport, addr, Register::DischargeVoltageLimit).await;
-    let charge_current_limit = read_current(transport, addr, Register::ChargeCurrentLimit).await;
-    let discharge_current_limit =
-        read_current(transport, addr, Register::DischargeCurrentLimit).await;
-
-    let status1 = read_status1(transport, addr).await;
-    let status2 = read_status2(transport, addr).await;
-    let status3 = read_status3(transport, addr).await;
-    let other_alarm_info = read_other_alarm_info(transport, addr).await;
-    let cell_voltage_alarms = read_cell_voltage_alarms(transport, addr).await;
-    let cell_temperature_alarms = read_cell_temperature_alarms(transport, addr).await;
-    let charge_discharge_status = read_charge_discharge_status(transport, addr).await;
+    let charge_voltage_limit = reader.voltage(Register::ChargeVoltageLimit).await;
+    let discharge_voltage_limit = reader.voltage(Register::DischargeVoltageLimit).await;
+    let charge_current_limit = reader.current(Register::ChargeCurrentLimit).await;
+    let discharge_current_limit = reader.current(Register::DischargeCurrentLimit).await;
+
+    let status1 = reader
+        .read(Register::Status1)
+        .await
+        .and_then(|v| v.as_status1());
+    let status2 = reader
+        .read(Register::Status2)
+        .await
+        .and_then(|v| v.as_status2());
+    let status3 = reader
+        .read(Register::Status3)
+        .await
+        .and_then(|v| v.as_status3());
+    let other_alarm_info = reader
+        .read(Register::OtherAlarmInfo)
+        .await
+        .and_then(|v| v.as_other_alarm_info());
+    let cell_voltage_alarms = reader
+        .read(Register::CellVoltageAlarmInfo)
+        .await
+        .and_then(|v| v.as_cell_voltage_alarms());
+    let cell_temperature_alarms = reader
+        .read(Register::CellTemperatureAlarmInfo)
+        .await
+        .and_then(|v| v.as_cell_temperature_alarms());
+    let charge_discharge_status = reader
+        .read(Register::ChargeDischargeStatus)
+        .await
+        .and_then(|v| v.as_charge_discharge_status());
 
     Some(BatteryInfo {
         timestamp: Utc::now(),
         serial,
+        link: String::new(),
         model,
         software_version,
         manufacturer,
@@ -230,120 +374,59 @@ pub async fn query_battery<T: Transport>(transport: &mut T, addr: u8) -> Option<
     })
 }
 
-async fn read_register<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-    register: Register,
-) -> Option<Value> {
-    let regs = transport
-        .read_holding_registers(addr, register.address(), register.quantity())
-        .await
-        .ok()?;
-    Some(register.parse_registers(&regs))
-}
-
-async fn read_string<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-    register: Register,
-) -> Option<String> {
-    read_register(transport, addr, register)
-        .await?
-        .as_string()
-        .map(|s| s.trim_matches('\0').to_string())
-}
-
-async fn read_integer<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-    register: Register,
-) -> Option<u32> {
-    read_register(transport, addr, register).await?.as_integer()
-}
-
-async fn read_voltage<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-    register: Register,
-) -> Option<f32> {
-    read_register(transport, addr, register)
-        .await?
-        .as_voltage()
-        .map(|v| v.get::<volt>())
-}
-
-async fn read_current<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-    register: Register,
-) -> Option<f32> {
-    read_register(transport, addr, register)
-        .await?
-        .as_current()
-        .map(|c| c.get::<ampere>())
-}
-
-async fn read_temperature<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-    register: Register,
-) -> Option<f32> {
-    read_register(transport, addr, register)
-        .await?
-        .as_temperature()
-        .map(|t| t.get::<degree_celsius>())
-}
-
-async fn read_status1<T: Transport>(transport: &mut T, addr: u8) -> Option<Status1> {
-    read_register(transport, addr, Register::Status1)
-        .await?
-        .as_status1()
-}
-
-async fn read_status2<T: Transport>(transport: &mut T, addr: u8) -> Option<Status2> {
-    read_register(transport, addr, Register::Status2)
-        .await?
-        .as_status2()
-}
-
-async fn read_status3<T: Transport>(transport: &mut T, addr: u8) -> Option<Status3> {
-    read_register(transport, addr, Register::Status3)
-        .await?
-        .as_status3()
-}
+#[cfg(test)]
+mod tests {
+    use super::query_battery;
+    use crate::emulator::EmulatedBattery;
+    use crate::emulator::Fault;
+    use crate::emulator::FaultyTransport;
+    use crate::error::ModbusExceptionCode;
+    use crate::error::RenogyError;
+
+    /// Rejects reads of more than `max_quantity` registers, the way a BMS with a
+    /// short receive buffer does.
+    fn short_buffer(max_quantity: u16) -> FaultyTransport<EmulatedBattery> {
+        FaultyTransport::new(EmulatedBattery::lfp_12v(0x30)).on_read(move |_, _, quantity| {
+            if quantity > max_quantity {
+                Fault::Answer(Err(RenogyError::ModbusException(
+                    ModbusExceptionCode::IllegalDataAddress,
+                )))
+            } else {
+                Fault::Pass
+            }
+        })
+    }
 
-async fn read_other_alarm_info<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-) -> Option<OtherAlarmInfo> {
-    read_register(transport, addr, Register::OtherAlarmInfo)
-        .await?
-        .as_other_alarm_info()
-}
+    #[tokio::test]
+    async fn reads_in_blocks() {
+        let mut transport = FaultyTransport::new(EmulatedBattery::lfp_12v(0x30));
+        let info = query_battery(&mut transport, 0x30).await.expect("info");
+
+        assert_eq!(transport.reads(), super::BLOCKS.len());
+        assert_eq!(info.serial, "SN0030");
+        assert_eq!(info.manufacturer, "RENOGY");
+        assert_eq!(info.cell_voltages.len(), 4);
+        assert!((info.cell_temperatures[1] - 21.5).abs() < 1e-3);
+        assert!((info.current + 5.0).abs() < 1e-3);
+        assert_eq!(info.discharge_current_limit, Some(100.0));
+    }
 
-async fn read_cell_voltage_alarms<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-) -> Option<CellVoltageAlarms> {
-    read_register(transport, addr, Register::CellVoltageAlarmInfo)
-        .await?
-        .as_cell_voltage_alarms()
-}
+    #[tokio::test]
+    async fn falls_back_to_single_reads_on_exception() {
+        let mut transport = short_buffer(10);
+        let info = query_battery(&mut transport, 0x30).await.expect("info");
 
-async fn read_cell_temperature_alarms<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-) -> Option<CellTemperatureAlarms> {
-    read_register(transport, addr, Register::CellTemperatureAlarmInfo)
-        .await?
-        .as_cell_temperature_alarms()
-}
+        assert!(transport.reads() > super::BLOCKS.len());
+        assert_eq!(info.serial, "SN0030");
+        assert_eq!(info.manufacturer, "RENOGY");
+        assert_eq!(info.cell_voltages.len(), 4);
+        assert!((info.current + 5.0).abs() < 1e-3);
+    }
 
-async fn read_charge_discharge_status<T: Transport>(
-    transport: &mut T,
-    addr: u8,
-) -> Option<ChargeDischargeStatus> {
-    read_register(transport, addr, Register::ChargeDischargeStatus)
-        .await?
-        .as_charge_discharge_status()
+    #[tokio::test]
+    async fn unanswered_serial_block_short_circuits() {
+        let mut transport = FaultyTransport::new(EmulatedBattery::lfp_12v(0x30));
+        assert!(query_battery(&mut transport, 0x31).await.is_none());
+        assert_eq!(transport.reads(), 1);
+    }
 }
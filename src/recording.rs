@@ -0,0 +1,478 @@
+//! Capture and replay of transport sessions.
+//!
+//! `RecordingTransport` wraps a live transport and appends every exchange to a JSON
+//! Lines file: one object per request with its timestamp, the request and the response
+//! or error. `ReplayTransport` serves such a file back through `Transport`, so
+//! `query_battery`, discovery and the collector can run against a field capture.
+
+use crate::error::ModbusExceptionCode;
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::transport::Transport;
+use crate::transport::TransportType;
+use async_trait::async_trait;
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "function", rename_all = "snake_case")]
+pub enum Request {
+    ReadHoldingRegisters {
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    },
+    WriteSingleRegister {
+        slave: u8,
+        addr: u16,
+        value: u16,
+    },
+    WriteMultipleRegisters {
+        slave: u8,
+        addr: u16,
+        values: Vec<u16>,
+    },
+    SendCustom {
+        slave: u8,
+        function_code: u8,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Registers(Vec<u16>),
+    Written,
+    Payload(Vec<u8>),
+    Error(RecordedError),
+}
+
+/// The parts of a `RenogyError` that survive a round trip through the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordedError {
+    ModbusException(u8),
+    Timeout,
+    CrcMismatch,
+    InvalidData,
+    Other(String),
+}
+
+impl From<&RenogyError> for RecordedError {
+    fn from(err: &RenogyError) -> Self {
+        match err {
+            RenogyError::ModbusException(code) => RecordedError::ModbusException(*code as u8),
+            RenogyError::Timeout => RecordedError::Timeout,
+            RenogyError::CrcMismatch => RecordedError::CrcMismatch,
+            RenogyError::InvalidData => RecordedError::InvalidData,
+            other => RecordedError::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<RecordedError> for RenogyError {
+    fn from(err: RecordedError) -> Self {
+        match err {
+            RecordedError::ModbusException(code) => ModbusExceptionCode::from_u8(code)
+                .map_or(RenogyError::InvalidData, RenogyError::ModbusException),
+            RecordedError::Timeout => RenogyError::Timeout,
+            RecordedError::CrcMismatch => RenogyError::CrcMismatch,
+            RecordedError::InvalidData => RenogyError::InvalidData,
+            RecordedError::Other(message) => RenogyError::Io(IoError::other(message)),
+        }
+    }
+}
+
+/// One line of a recording.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Exchange {
+    pub timestamp: DateTime<Utc>,
+    pub transport: TransportType,
+    pub request: Request,
+    pub response: Response,
+}
+
+/// Logs every exchange with `inner` to `writer` as JSON Lines.
+///
+/// Failures to write the log are reported but never fail the request itself.
+pub struct RecordingTransport<T, W = BufWriter<File>> {
+    inner: T,
+    writer: W,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    /// Record to `path`, appending if it exists.
+    pub fn create(inner: T, path: impl AsRef<Path>) -> Result<Self> {
+        let file = File::options().create(true).append(true).open(path)?;
+        Ok(Self::new(inner, BufWriter::new(file)))
+    }
+}
+
+impl<T: Transport, W: Write> RecordingTransport<T, W> {
+    pub fn new(inner: T, writer: W) -> Self {
+        Self { inner, writer }
+    }
+
+    pub fn into_inner(self) -> (T, W) {
+        (self.inner, self.writer)
+    }
+
+    fn record(&mut self, request: Request, response: Response) {
+        let exchange = Exchange {
+            timestamp: Utc::now(),
+            transport: self.inner.transport_type(),
+            request,
+            response,
+        };
+        let written = serde_json::to_writer(&mut self.writer, &exchange)
+            .map_err(IoError::from)
+            .and_then(|()| self.writer.write_all(b"\n"))
+            .and_then(|()| self.writer.flush());
+        if let Err(e) = written {
+            tracing::warn!("Failed to record exchange: {}", e);
+        }
+    }
+}
+
+#[async_trait]
+impl<T: Transport + Send, W: Write + Send> Transport for RecordingTransport<T, W> {
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        let result = self
+            .inner
+            .read_holding_registers(slave, addr, quantity)
+            .await;
+        let response = match &result {
+            Ok(words) => Response::Registers(words.clone()),
+            Err(e) => Response::Error(e.into()),
+        };
+        self.record(
+            Request::ReadHoldingRegisters {
+                slave,
+                addr,
+                quantity,
+            },
+            response,
+        );
+        result
+    }
+
+    async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
+        let result = self.inner.write_single_register(slave, addr, value).await;
+        let response = match &result {
+            Ok(()) => Response::Written,
+            Err(e) => Response::Error(e.into()),
+        };
+        self.record(
+            Request::WriteSingleRegister { slave, addr, value },
+            response,
+        );
+        result
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        let result = self
+            .inner
+            .write_multiple_registers(slave, addr, values)
+            .await;
+        let response = match &result {
+            Ok(()) => Response::Written,
+            Err(e) => Response::Error(e.into()),
+        };
+        let values = values.to_vec();
+        self.record(
+            Request::WriteMultipleRegisters {
+                slave,
+                addr,
+                values,
+            },
+            response,
+        );
+        result
+    }
+
+    async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let result = self.inner.send_custom(slave, function_code, data).await;
+        let response = match &result {
+            Ok(payload) => Response::Payload(payload.clone()),
+            Err(e) => Response::Error(e.into()),
+        };
+        let data = data.to_vec();
+        self.record(
+            Request::SendCustom {
+                slave,
+                function_code,
+                data,
+            },
+            response,
+        );
+        result
+    }
+
+    fn transport_type(&self) -> TransportType {
+        self.inner.transport_type()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplayMode {
+    /// Requests must arrive exactly in recorded order.
+    #[default]
+    Strict,
+    /// Each request is answered by the recorded exchanges for the same request, in
+    /// order; the last one keeps being served once they run out. Unrecorded requests
+    /// time out, as an absent slave would.
+    Keyed,
+}
+
+/// Serves a recording back through `Transport`.
+pub struct ReplayTransport {
+    mode: ReplayMode,
+    transport_type: TransportType,
+    sequence: VecDeque<Exchange>,
+    keyed: HashMap<Request, VecDeque<Response>>,
+}
+
+impl ReplayTransport {
+    pub fn open(path: impl AsRef<Path>, mode: ReplayMode) -> Result<Self> {
+        Self::from_reader(BufReader::new(File::open(path)?), mode)
+    }
+
+    /// Parse JSON Lines from `reader`; blank lines are ignored.
+    pub fn from_reader(reader: impl BufRead, mode: ReplayMode) -> Result<Self> {
+        let mut exchanges = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            exchanges.push(serde_json::from_str(&line).map_err(IoError::from)?);
+        }
+        Ok(Self::new(exchanges, mode))
+    }
+
+    #[must_use]
+    pub fn new(exchanges: Vec<Exchange>, mode: ReplayMode) -> Self {
+        let transport_type = exchanges
+            .first()
+            .map_or(TransportType::Serial, |exchange| exchange.transport);
+
+        let mut keyed: HashMap<Request, VecDeque<Response>> = HashMap::new();
+        if mode == ReplayMode::Keyed {
+            for exchange in &exchanges {
+                keyed
+                    .entry(exchange.request.clone())
+                    .or_default()
+                    .push_back(exchange.response.clone());
+            }
+        }
+
+        Self {
+            mode,
+            transport_type,
+            sequence: exchanges.into(),
+            keyed,
+        }
+    }
+
+    /// Exchanges not yet served in strict mode.
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.sequence.len()
+    }
+
+    fn replay(&mut self, request: Request) -> Result<Response> {
+        match self.mode {
+            ReplayMode::Strict => {
+                let Some(exchange) = self.sequence.pop_front() else {
+                    return Err(RenogyError::Io(IoError::new(
+                        ErrorKind::UnexpectedEof,
+                        "recording exhausted",
+                    )));
+                };
+                if exchange.request != request {
+                    return Err(RenogyError::Io(IoError::new(
+                        ErrorKind::InvalidInput,
+                        format!("replay expected {:?}, got {:?}", exchange.request, request),
+                    )));
+                }
+                Ok(exchange.response)
+            }
+            ReplayMode::Keyed => {
+                let responses = self.keyed.get_mut(&request).ok_or(RenogyError::Timeout)?;
+                let response = if responses.len() > 1 {
+                    responses.pop_front()
+                } else {
+                    responses.front().cloned()
+                };
+                response.ok_or(RenogyError::Timeout)
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for ReplayTransport {
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        match self.replay(Request::ReadHoldingRegisters {
+            slave,
+            addr,
+            quantity,
+        })? {
+            Response::Registers(words) => Ok(words),
+            Response::Error(e) => Err(e.into()),
+            _ => Err(RenogyError::InvalidData),
+        }
+    }
+
+    async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
+        match self.replay(Request::WriteSingleRegister { slave, addr, value })? {
+            Response::Written => Ok(()),
+            Response::Error(e) => Err(e.into()),
+            _ => Err(RenogyError::InvalidData),
+        }
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        match self.replay(Request::WriteMultipleRegisters {
+            slave,
+            addr,
+            values: values.to_vec(),
+        })? {
+            Response::Written => Ok(()),
+            Response::Error(e) => Err(e.into()),
+            _ => Err(RenogyError::InvalidData),
+        }
+    }
+
+    async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
+        match self.replay(Request::SendCustom {
+            slave,
+            function_code,
+            data: data.to_vec(),
+        })? {
+            Response::Payload(payload) => Ok(payload),
+            Response::Error(e) => Err(e.into()),
+            _ => Err(RenogyError::InvalidData),
+        }
+    }
+
+    fn transport_type(&self) -> TransportType {
+        self.transport_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RecordingTransport;
+    use super::ReplayMode;
+    use super::ReplayTransport;
+    use crate::discovery::DiscoveryOptions;
+    use crate::discovery::discover;
+    use crate::emulator::EmulatedBattery;
+    use crate::emulator::EmulatedBus;
+    use crate::error::RenogyError;
+    use crate::query::query_battery;
+    use crate::transport::Transport;
+
+    const ADDR: u8 = 0x30;
+
+    /// Record a `query_battery` session against an emulated battery.
+    async fn capture() -> Vec<u8> {
+        let mut recorder = RecordingTransport::new(EmulatedBattery::lfp_12v(ADDR), Vec::new());
+        query_battery(&mut recorder, ADDR).await.expect("info");
+        recorder
+            .read_holding_registers(ADDR + 1, 5000, 1)
+            .await
+            .unwrap_err();
+        recorder.into_inner().1
+    }
+
+    #[tokio::test]
+    async fn strict_replay_reproduces_session() {
+        let log = capture().await;
+        let mut replay = ReplayTransport::from_reader(log.as_slice(), ReplayMode::Strict).unwrap();
+
+        let info = query_battery(&mut replay, ADDR).await.expect("info");
+        assert_eq!(info.serial, "SN0030");
+        assert!((info.module_voltage - 13.2).abs() < 1e-2);
+
+        assert!(matches!(
+            replay.read_holding_registers(ADDR + 1, 5000, 1).await,
+            Err(RenogyError::InvalidData)
+        ));
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn strict_replay_rejects_out_of_order_requests() {
+        let log = capture().await;
+        let mut replay = ReplayTransport::from_reader(log.as_slice(), ReplayMode::Strict).unwrap();
+        assert!(replay.read_holding_registers(ADDR, 5200, 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn keyed_replay_serves_repeated_polls() {
+        let log = capture().await;
+        let mut replay = ReplayTransport::from_reader(log.as_slice(), ReplayMode::Keyed).unwrap();
+
+        for _ in 0..3 {
+            let info = query_battery(&mut replay, ADDR).await.expect("info");
+            assert_eq!(info.cell_voltages.len(), 4);
+        }
+        assert!(matches!(
+            replay.read_holding_registers(0x3F, 5000, 1).await,
+            Err(RenogyError::Timeout)
+        ));
+    }
+
+    #[tokio::test]
+    async fn keyed_replay_of_a_recorded_discovery_finds_the_batteries() {
+        let bus = EmulatedBus::new(vec![EmulatedBattery::lfp_12v(0x31)]);
+        let options = DiscoveryOptions::default();
+        let mut recorder = RecordingTransport::new(bus, Vec::new());
+        let recorded = discover(&mut recorder, 0x30..=0x33, &options).await;
+        query_battery(&mut recorder, 0x31).await.expect("info");
+        let log = recorder.into_inner().1;
+
+        let mut replay = ReplayTransport::from_reader(log.as_slice(), ReplayMode::Keyed).unwrap();
+        let replayed = discover(&mut replay, 0x30..=0x33, &options).await;
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].address, recorded[0].address);
+        assert_eq!(replayed[0].serial, "SN0031");
+        assert_eq!(replayed[0].model, "RBT100LFP12S-G1");
+        assert!(query_battery(&mut replay, 0x31).await.is_some());
+    }
+}
@@ -10,6 +10,9 @@ use crate::error::RenogyError;
 use crate::error::Result;
 use byteorder::BigEndian;
 use byteorder::ByteOrder;
+use serde::Serialize;
+use std::fmt;
+use std::ops::RangeInclusive;
 use uom::si::electric_current::ampere;
 use uom::si::electric_potential::volt;
 use uom::si::f32::ElectricCurrent;
@@ -55,6 +58,26 @@ macro_rules! impl_as_variant {
     };
 }
 
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::ElectricPotential(v) => write!(f, "{:.1} V", v.get::<volt>()),
+            Value::ElectricCurrent(i) => write!(f, "{:.2} A", i.get::<ampere>()),
+            Value::ThermodynamicTemperature(t) => write!(f, "{:.1} °C", t.get::<degree_celsius>()),
+            Value::Integer(n) => write!(f, "{n}"),
+            Value::String(s) => write!(f, "{:?}", s.trim_end_matches(['\0', ' '])),
+            Value::CellVoltageAlarms(v) => write!(f, "{v:?}"),
+            Value::CellTemperatureAlarms(v) => write!(f, "{v:?}"),
+            Value::OtherAlarmInfo(v) => write!(f, "{v:?}"),
+            Value::Status1(v) => write!(f, "{v:?}"),
+            Value::Status2(v) => write!(f, "{v:?}"),
+            Value::Status3(v) => write!(f, "{v:?}"),
+            Value::CellVoltageErrors(v) => write!(f, "{v:?}"),
+            Value::ChargeDischargeStatus(v) => write!(f, "{v:?}"),
+        }
+    }
+}
+
 impl Value {
     impl_as_variant!(as_string, String, ref str);
     impl_as_variant!(as_integer, Integer, u32);
@@ -150,262 +173,899 @@ pub enum Register {
     AcpShake,
 }
 
-impl Register {
-    #[must_use]
-    pub const fn address(&self) -> u16 {
-        match self {
-            Register::CellCount => 5000,
-            Register::CellVoltage(n) => 5000 + *n as u16,
-            Register::CellTemperatureCount => 5017,
-            Register::CellTemperature(n) => 5017 + *n as u16,
-            Register::BmsTemperature => 5035,
-            Register::EnvironmentTemperatureCount => 5036,
-            Register::EnvironmentTemperature(n) => 5036 + *n as u16,
-            Register::HeaterTemperatureCount => 5039,
-            Register::HeaterTemperature(n) => 5039 + *n as u16,
-            Register::Current => 5042,
-            Register::ModuleVoltage => 5043,
-            Register::RemainingCapacity => 5044,
-            Register::TotalCapacity => 5046,
-            Register::CycleNumber => 5048,
-            Register::ChargeVoltageLimit => 5049,
-            Register::DischargeVoltageLimit => 5050,
-            Register::ChargeCurrentLimit => 5051,
-            Register::DischargeCurrentLimit => 5052,
-            Register::CellVoltageAlarmInfo => 5100,
-            Register::CellTemperatureAlarmInfo => 5102,
-            Register::OtherAlarmInfo => 5104,
-            Register::Status1 => 5106,
-            Register::Status2 => 5107,
-            Register::Status3 => 5108,
-            Register::ChargeDischargeStatus => 5109,
-            Register::SnNumber => 5110,
-            Register::ManufactureVersion => 5118,
-            Register::MainlineVersion => 5119,
-            Register::CommunicationProtocolVersion => 5121,
-            Register::BatteryName => 5122,
-            Register::SoftwareVersion => 5130,
-            Register::ManufacturerName => 5132,
-            Register::CellOverVoltageLimit => 5200,
-            Register::CellHighVoltageLimit => 5201,
-            Register::CellLowVoltageLimit => 5202,
-            Register::CellUnderVoltageLimit => 5203,
-            Register::ChargeOverTemperatureLimit => 5204,
-            Register::ChargeHighTemperatureLimit => 5205,
-            Register::ChargeLowTemperatureLimit => 5206,
-            Register::ChargeUnderTemperatureLimit => 5207,
-            Register::ChargeOver2CurrentLimit => 5208,
-            Register::ChargeOver1CurrentLimit => 5209,
-            Register::ChargeHighCurrentLimit => 5210,
-            Register::ModuleOverVoltageLimit => 5211,
-            Register::ModuleHighVoltageLimit => 5212,
-            Register::ModuleLowVoltageLimit => 5213,
-            Register::ModuleUnderVoltageLimit => 5214,
-            Register::DischargeOverTemperatureLimit => 5215,
-            Register::DischargeHighTemperatureLimit => 5216,
-            Register::DischargeLowTemperatureLimit => 5217,
-            Register::DischargeUnderTemperatureLimit => 5218,
-            Register::DischargeOver2CurrentLimit => 5219,
-            Register::DischargeOver1CurrentLimit => 5220,
-            Register::DischargeHighCurrentLimit => 5221,
-            Register::ShutdownCommand => 5222,
-            Register::DeviceId => 5223,
-            Register::LockControl => 5224,
-            Register::TestReady => 5225,
-            Register::UniqueIdentificationCode => 5226,
-            Register::ChargePowerSetting => 5228,
-            Register::DischargePowerSetting => 5229,
-            Register::AcpBroadcast => 61440,
-            Register::AcpConfigure => 61441,
-            Register::AcpShake => 61442,
+/// Which `Value` variant a register decodes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataType {
+    Integer,
+    Voltage,
+    Current,
+    /// Charge in Ah, returned as `Value::ElectricCurrent`.
+    Capacity,
+    Temperature,
+    String,
+    CellVoltageAlarms,
+    CellTemperatureAlarms,
+    OtherAlarmInfo,
+    Status1,
+    Status2,
+    Status3,
+    ChargeDischargeStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Access {
+    ReadOnly,
+    ReadWrite,
+}
+
+/// Everything known about one register, or one block of per-cell or per-sensor
+/// registers. `Register`'s methods are all driven by these entries.
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisterInfo {
+    /// The register, or the first of the block.
+    #[serde(skip)]
+    pub register: Register,
+    pub name: &'static str,
+    /// Address of the first word.
+    pub address: u16,
+    /// Registers in the block, at consecutive addresses; 1 unless indexed by cell or
+    /// sensor number.
+    pub count: u8,
+    /// Words per register.
+    pub words: u16,
+    pub data_type: DataType,
+    /// Physical value of one step of the raw word.
+    pub scale: f64,
+    /// Whether the raw word is two's complement.
+    pub signed: bool,
+    pub unit: Option<&'static str>,
+    pub access: Access,
+    /// Lowest value a write may set, in `unit`. See `Register::documented_range`.
+    pub min: Option<f64>,
+    /// Highest value a write may set, in `unit`.
+    pub max: Option<f64>,
+    pub description: &'static str,
+}
+
+impl RegisterInfo {
+    /// Every register of this entry: the one, or one per cell or sensor.
+    pub fn registers(&self) -> impl Iterator<Item = Register> + '_ {
+        (1..=self.count).map(|n| self.register.with_index(n))
+    }
+
+    const fn new(
+        register: Register,
+        name: &'static str,
+        address: u16,
+        words: u16,
+        data_type: DataType,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            register,
+            name,
+            address,
+            count: 1,
+            words,
+            data_type,
+            scale: 1.0,
+            signed: false,
+            unit: None,
+            access: Access::ReadOnly,
+            min: None,
+            max: None,
+            description,
         }
     }
 
+    const fn scaled(self, scale: f64, unit: &'static str) -> Self {
+        Self {
+            scale,
+            unit: Some(unit),
+            ..self
+        }
+    }
+
+    const fn signed(self) -> Self {
+        Self {
+            signed: true,
+            ..self
+        }
+    }
+
+    const fn count(self, count: u8) -> Self {
+        Self { count, ..self }
+    }
+
+    const fn writable(self) -> Self {
+        Self {
+            access: Access::ReadWrite,
+            ..self
+        }
+    }
+
+    const fn range(self, min: f64, max: f64) -> Self {
+        Self {
+            min: Some(min),
+            max: Some(max),
+            ..self
+        }
+    }
+}
+
+const fn integer(
+    register: Register,
+    name: &'static str,
+    address: u16,
+    description: &'static str,
+) -> RegisterInfo {
+    RegisterInfo::new(register, name, address, 1, DataType::Integer, description)
+}
+
+/// 0.1 V per step.
+const fn voltage(
+    register: Register,
+    name: &'static str,
+    address: u16,
+    description: &'static str,
+) -> RegisterInfo {
+    RegisterInfo::new(register, name, address, 1, DataType::Voltage, description).scaled(0.1, "V")
+}
+
+/// 0.01 A per step.
+const fn current(
+    register: Register,
+    name: &'static str,
+    address: u16,
+    description: &'static str,
+) -> RegisterInfo {
+    RegisterInfo::new(register, name, address, 1, DataType::Current, description).scaled(0.01, "A")
+}
+
+/// 0.001 Ah per step, over two words.
+const fn capacity(
+    register: Register,
+    name: &'static str,
+    address: u16,
+    description: &'static str,
+) -> RegisterInfo {
+    RegisterInfo::new(register, name, address, 2, DataType::Capacity, description)
+        .scaled(0.001, "Ah")
+}
+
+/// 0.1 °C per step, two's complement. Sub-zero readings come back as e.g. 0xFF9C
+/// (-10.0 °C) on every protocol version seen so far; read as unsigned they would be
+/// 6553.x °C.
+const fn temperature(
+    register: Register,
+    name: &'static str,
+    address: u16,
+    description: &'static str,
+) -> RegisterInfo {
+    RegisterInfo::new(
+        register,
+        name,
+        address,
+        1,
+        DataType::Temperature,
+        description,
+    )
+    .scaled(0.1, "°C")
+    .signed()
+}
+
+/// ASCII, padded with NULs or spaces.
+const fn string(
+    register: Register,
+    name: &'static str,
+    address: u16,
+    words: u16,
+    description: &'static str,
+) -> RegisterInfo {
+    RegisterInfo::new(
+        register,
+        name,
+        address,
+        words,
+        DataType::String,
+        description,
+    )
+}
+
+// The V1.7 protocol document gives the unit and resolution of each limit, but not a
+// settable range. These bounds are ours, not the document's: those of a LiFePO4 pack of
+// 4 to 16 cells (12 V to 48 V), with cell thresholds between the 2.0 V floor and 4.5 V,
+// module thresholds up to 16 × 4.5 V, temperatures from -40 °C to 100 °C, and currents
+// up to the 300 A the largest packs are rated for. They keep a 0 V or mistyped limit
+// from reaching the BMS, including through single-register writes that bypass
+// `BmsLimits::validate`.
+const CELL_VOLTS: (f64, f64) = (2.0, 4.5);
+const MODULE_VOLTS: (f64, f64) = (8.0, 72.0);
+const LIMIT_CELSIUS: (f64, f64) = (-40.0, 100.0);
+const LIMIT_AMPS: (f64, f64) = (0.0, 300.0);
+
+/// Every register, in address order.
+static CATALOG: [RegisterInfo; 64] = [
+    integer(
+        Register::CellCount,
+        "cell_count",
+        5000,
+        "Number of cells in series",
+    ),
+    voltage(
+        Register::CellVoltage(1),
+        "cell_voltage",
+        5001,
+        "Voltage of cell N",
+    )
+    .count(16),
+    integer(
+        Register::CellTemperatureCount,
+        "cell_temperature_count",
+        5017,
+        "Number of cell temperature sensors",
+    ),
+    temperature(
+        Register::CellTemperature(1),
+        "cell_temperature",
+        5018,
+        "Temperature at cell sensor N",
+    )
+    .count(16),
+    temperature(
+        Register::BmsTemperature,
+        "bms_temperature",
+        5035,
+        "BMS board temperature",
+    ),
+    integer(
+        Register::EnvironmentTemperatureCount,
+        "environment_temperature_count",
+        5036,
+        "Number of environment temperature sensors",
+    ),
+    temperature(
+        Register::EnvironmentTemperature(1),
+        "environment_temperature",
+        5037,
+        "Temperature at environment sensor N",
+    )
+    .count(2),
+    integer(
+        Register::HeaterTemperatureCount,
+        "heater_temperature_count",
+        5039,
+        "Number of heater temperature sensors",
+    ),
+    temperature(
+        Register::HeaterTemperature(1),
+        "heater_temperature",
+        5040,
+        "Temperature at heater sensor N",
+    )
+    .count(2),
+    current(
+        Register::Current,
+        "current",
+        5042,
+        "Pack current; negative when discharging",
+    )
+    .signed(),
+    voltage(
+        Register::ModuleVoltage,
+        "module_voltage",
+        5043,
+        "Pack voltage",
+    ),
+    capacity(
+        Register::RemainingCapacity,
+        "remaining_capacity",
+        5044,
+        "Remaining charge",
+    ),
+    capacity(
+        Register::TotalCapacity,
+        "total_capacity",
+        5046,
+        "Full charge capacity",
+    ),
+    integer(
+        Register::CycleNumber,
+        "cycle_number",
+        5048,
+        "Charge cycles completed",
+    ),
+    voltage(
+        Register::ChargeVoltageLimit,
+        "charge_voltage_limit",
+        5049,
+        "Charge voltage requested from the charger",
+    )
+    .writable()
+    .range(MODULE_VOLTS.0, MODULE_VOLTS.1),
+    voltage(
+        Register::DischargeVoltageLimit,
+        "discharge_voltage_limit",
+        5050,
+        "Lowest pack voltage to discharge to",
+    )
+    .writable()
+    .range(MODULE_VOLTS.0, MODULE_VOLTS.1),
+    current(
+        Register::ChargeCurrentLimit,
+        "charge_current_limit",
+        5051,
+        "Charge current requested from the charger",
+    )
+    .writable()
+    .range(LIMIT_AMPS.0, LIMIT_AMPS.1),
+    // Reported negative by some firmware, like the discharge current itself.
+    current(
+        Register::DischargeCurrentLimit,
+        "discharge_current_limit",
+        5052,
+        "Highest discharge current allowed",
+    )
+    .signed()
+    .writable()
+    .range(-LIMIT_AMPS.1, LIMIT_AMPS.1),
+    RegisterInfo::new(
+        Register::CellVoltageAlarmInfo,
+        "cell_voltage_alarm_info",
+        5100,
+        2,
+        DataType::CellVoltageAlarms,
+        "Over/under-voltage alarm per cell",
+    ),
+    RegisterInfo::new(
+        Register::CellTemperatureAlarmInfo,
+        "cell_temperature_alarm_info",
+        5102,
+        2,
+        DataType::CellTemperatureAlarms,
+        "Over/under-temperature alarm per cell sensor",
+    ),
+    RegisterInfo::new(
+        Register::OtherAlarmInfo,
+        "other_alarm_info",
+        5104,
+        2,
+        DataType::OtherAlarmInfo,
+        "BMS, environment, heater, current and voltage alarms",
+    ),
+    RegisterInfo::new(
+        Register::Status1,
+        "status1",
+        5106,
+        1,
+        DataType::Status1,
+        "Protection and MOSFET state",
+    ),
+    RegisterInfo::new(
+        Register::Status2,
+        "status2",
+        5107,
+        1,
+        DataType::Status2,
+        "Warnings, heater and charge state",
+    ),
+    RegisterInfo::new(
+        Register::Status3,
+        "status3",
+        5108,
+        1,
+        DataType::Status3,
+        "Per-cell voltage errors",
+    ),
+    RegisterInfo::new(
+        Register::ChargeDischargeStatus,
+        "charge_discharge_status",
+        5109,
+        1,
+        DataType::ChargeDischargeStatus,
+        "Charge and discharge requests to the inverter",
+    ),
+    string(Register::SnNumber, "sn_number", 5110, 8, "Serial number"),
+    string(
+        Register::ManufactureVersion,
+        "manufacture_version",
+        5118,
+        1,
+        "Manufacture version",
+    ),
+    string(
+        Register::MainlineVersion,
+        "mainline_version",
+        5119,
+        2,
+        "Mainline firmware version",
+    ),
+    string(
+        Register::CommunicationProtocolVersion,
+        "communication_protocol_version",
+        5121,
+        1,
+        "Modbus protocol version",
+    ),
+    string(Register::BatteryName, "battery_name", 5122, 8, "Model name"),
+    string(
+        Register::SoftwareVersion,
+        "software_version",
+        5130,
+        2,
+        "Software version",
+    ),
+    string(
+        Register::ManufacturerName,
+        "manufacturer_name",
+        5132,
+        10,
+        "Manufacturer name",
+    ),
+    voltage(
+        Register::CellOverVoltageLimit,
+        "cell_over_voltage_limit",
+        5200,
+        "Cell voltage that trips over-voltage protection",
+    )
+    .writable()
+    .range(CELL_VOLTS.0, CELL_VOLTS.1),
+    voltage(
+        Register::CellHighVoltageLimit,
+        "cell_high_voltage_limit",
+        5201,
+        "Cell voltage that raises a high-voltage warning",
+    )
+    .writable()
+    .range(CELL_VOLTS.0, CELL_VOLTS.1),
+    voltage(
+        Register::CellLowVoltageLimit,
+        "cell_low_voltage_limit",
+        5202,
+        "Cell voltage that raises a low-voltage warning",
+    )
+    .writable()
+    .range(CELL_VOLTS.0, CELL_VOLTS.1),
+    voltage(
+        Register::CellUnderVoltageLimit,
+        "cell_under_voltage_limit",
+        5203,
+        "Cell voltage that trips under-voltage protection",
+    )
+    .writable()
+    .range(CELL_VOLTS.0, CELL_VOLTS.1),
+    temperature(
+        Register::ChargeOverTemperatureLimit,
+        "charge_over_temperature_limit",
+        5204,
+        "Temperature that stops charging",
+    )
+    .writable()
+    .range(LIMIT_CELSIUS.0, LIMIT_CELSIUS.1),
+    temperature(
+        Register::ChargeHighTemperatureLimit,
+        "charge_high_temperature_limit",
+        5205,
+        "Temperature that raises a charge high-temperature warning",
+    )
+    .writable()
+    .range(LIMIT_CELSIUS.0, LIMIT_CELSIUS.1),
+    temperature(
+        Register::ChargeLowTemperatureLimit,
+        "charge_low_temperature_limit",
+        5206,
+        "Temperature that raises a charge low-temperature warning",
+    )
+    .writable()
+    .range(LIMIT_CELSIUS.0, LIMIT_CELSIUS.1),
+    temperature(
+        Register::ChargeUnderTemperatureLimit,
+        "charge_under_temperature_limit",
+        5207,
+        "Temperature below which charging stops",
+    )
+    .writable()
+    .range(LIMIT_CELSIUS.0, LIMIT_CELSIUS.1),
+    current(
+        Register::ChargeOver2CurrentLimit,
+        "charge_over2_current_limit",
+        5208,
+        "Charge current that trips level-2 over-current protection",
+    )
+    .writable()
+    .range(LIMIT_AMPS.0, LIMIT_AMPS.1),
+    current(
+        Register::ChargeOver1CurrentLimit,
+        "charge_over1_current_limit",
+        5209,
+        "Charge current that trips level-1 over-current protection",
+    )
+    .writable()
+    .range(LIMIT_AMPS.0, LIMIT_AMPS.1),
+    current(
+        Register::ChargeHighCurrentLimit,
+        "charge_high_current_limit",
+        5210,
+        "Charge current that raises a high-current warning",
+    )
+    .writable()
+    .range(LIMIT_AMPS.0, LIMIT_AMPS.1),
+    voltage(
+        Register::ModuleOverVoltageLimit,
+        "module_over_voltage_limit",
+        5211,
+        "Pack voltage that trips over-voltage protection",
+    )
+    .writable()
+    .range(MODULE_VOLTS.0, MODULE_VOLTS.1),
+    voltage(
+        Register::ModuleHighVoltageLimit,
+        "module_high_voltage_limit",
+        5212,
+        "Pack voltage that raises a high-voltage warning",
+    )
+    .writable()
+    .range(MODULE_VOLTS.0, MODULE_VOLTS.1),
+    voltage(
+        Register::ModuleLowVoltageLimit,
+        "module_low_voltage_limit",
+        5213,
+        "Pack voltage that raises a low-voltage warning",
+    )
+    .writable()
+    .range(MODULE_VOLTS.0, MODULE_VOLTS.1),
+    voltage(
+        Register::ModuleUnderVoltageLimit,
+        "module_under_voltage_limit",
+        5214,
+        "Pack voltage that trips under-voltage protection",
+    )
+    .writable()
+    .range(MODULE_VOLTS.0, MODULE_VOLTS.1),
+    temperature(
+        Register::DischargeOverTemperatureLimit,
+        "discharge_over_temperature_limit",
+        5215,
+        "Temperature that stops discharging",
+    )
+    .writable()
+    .range(LIMIT_CELSIUS.0, LIMIT_CELSIUS.1),
+    temperature(
+        Register::DischargeHighTemperatureLimit,
+        "discharge_high_temperature_limit",
+        5216,
+        "Temperature that raises a discharge high-temperature warning",
+    )
+    .writable()
+    .range(LIMIT_CELSIUS.0, LIMIT_CELSIUS.1),
+    temperature(
+        Register::DischargeLowTemperatureLimit,
+        "discharge_low_temperature_limit",
+        5217,
+        "Temperature that raises a discharge low-temperature warning",
+    )
+    .writable()
+    .range(LIMIT_CELSIUS.0, LIMIT_CELSIUS.1),
+    temperature(
+        Register::DischargeUnderTemperatureLimit,
+        "discharge_under_temperature_limit",
+        5218,
+        "Temperature below which discharging stops",
+    )
+    .writable()
+    .range(LIMIT_CELSIUS.0, LIMIT_CELSIUS.1),
+    current(
+        Register::DischargeOver2CurrentLimit,
+        "discharge_over2_current_limit",
+        5219,
+        "Discharge current that trips level-2 over-current protection",
+    )
+    .writable()
+    .range(LIMIT_AMPS.0, LIMIT_AMPS.1),
+    current(
+        Register::DischargeOver1CurrentLimit,
+        "discharge_over1_current_limit",
+        5220,
+        "Discharge current that trips level-1 over-current protection",
+    )
+    .writable()
+    .range(LIMIT_AMPS.0, LIMIT_AMPS.1),
+    current(
+        Register::DischargeHighCurrentLimit,
+        "discharge_high_current_limit",
+        5221,
+        "Discharge current that raises a high-current warning",
+    )
+    .writable()
+    .range(LIMIT_AMPS.0, LIMIT_AMPS.1),
+    integer(
+        Register::ShutdownCommand,
+        "shutdown_command",
+        5222,
+        "Write to shut the BMS down",
+    )
+    .writable(),
+    integer(
+        Register::DeviceId,
+        "device_id",
+        5223,
+        "Modbus slave address",
+    )
+    .writable()
+    .range(1.0, 247.0),
+    integer(
+        Register::LockControl,
+        "lock_control",
+        5224,
+        "Unlocks the limit registers for writing",
+    )
+    .writable(),
+    integer(Register::TestReady, "test_ready", 5225, "Factory test mode").writable(),
+    RegisterInfo::new(
+        Register::UniqueIdentificationCode,
+        "unique_identification_code",
+        5226,
+        2,
+        DataType::Integer,
+        "Unique identification code",
+    )
+    .writable(),
+    integer(
+        Register::ChargePowerSetting,
+        "charge_power_setting",
+        5228,
+        "Charge power, in percent",
+    )
+    .writable()
+    .range(0.0, 100.0),
+    integer(
+        Register::DischargePowerSetting,
+        "discharge_power_setting",
+        5229,
+        "Discharge power, in percent",
+    )
+    .writable()
+    .range(0.0, 100.0),
+    integer(
+        Register::AcpBroadcast,
+        "acp_broadcast",
+        61440,
+        "ACP broadcast",
+    )
+    .writable()
+    .range(1.0, 254.0),
+    integer(
+        Register::AcpConfigure,
+        "acp_configure",
+        61441,
+        "ACP configure",
+    )
+    .writable()
+    .range(1.0, 254.0),
+    integer(Register::AcpShake, "acp_shake", 61442, "ACP handshake")
+        .writable()
+        .range(1.0, 254.0),
+];
+
+/// Every register, in address order.
+#[must_use]
+pub fn catalog() -> &'static [RegisterInfo] {
+    &CATALOG
+}
+
+/// The catalog as a JSON array, for UIs and tools that list registers generically.
+#[must_use]
+pub fn catalog_json() -> String {
+    serde_json::to_string_pretty(catalog()).expect("catalog serializes")
+}
+
+impl Register {
+    /// The catalog entry for this register, or for its block if indexed.
     #[must_use]
-    pub const fn quantity(&self) -> u16 {
+    pub const fn info(&self) -> &'static RegisterInfo {
+        &CATALOG[self.catalog_index()]
+    }
+
+    /// Position of this register's entry in `CATALOG`.
+    const fn catalog_index(&self) -> usize {
         match self {
-            Register::RemainingCapacity
-            | Register::TotalCapacity
-            | Register::CellVoltageAlarmInfo
-            | Register::CellTemperatureAlarmInfo
-            | Register::OtherAlarmInfo
-            | Register::MainlineVersion
-            | Register::SoftwareVersion
-            | Register::UniqueIdentificationCode => 2,
-            Register::SnNumber | Register::BatteryName => 8,
-            Register::ManufacturerName => 10,
-            _ => 1,
+            Register::CellCount => 0,
+            Register::CellVoltage(_) => 1,
+            Register::CellTemperatureCount => 2,
+            Register::CellTemperature(_) => 3,
+            Register::BmsTemperature => 4,
+            Register::EnvironmentTemperatureCount => 5,
+            Register::EnvironmentTemperature(_) => 6,
+            Register::HeaterTemperatureCount => 7,
+            Register::HeaterTemperature(_) => 8,
+            Register::Current => 9,
+            Register::ModuleVoltage => 10,
+            Register::RemainingCapacity => 11,
+            Register::TotalCapacity => 12,
+            Register::CycleNumber => 13,
+            Register::ChargeVoltageLimit => 14,
+            Register::DischargeVoltageLimit => 15,
+            Register::ChargeCurrentLimit => 16,
+            Register::DischargeCurrentLimit => 17,
+            Register::CellVoltageAlarmInfo => 18,
+            Register::CellTemperatureAlarmInfo => 19,
+            Register::OtherAlarmInfo => 20,
+            Register::Status1 => 21,
+            Register::Status2 => 22,
+            Register::Status3 => 23,
+            Register::ChargeDischargeStatus => 24,
+            Register::SnNumber => 25,
+            Register::ManufactureVersion => 26,
+            Register::MainlineVersion => 27,
+            Register::CommunicationProtocolVersion => 28,
+            Register::BatteryName => 29,
+            Register::SoftwareVersion => 30,
+            Register::ManufacturerName => 31,
+            Register::CellOverVoltageLimit => 32,
+            Register::CellHighVoltageLimit => 33,
+            Register::CellLowVoltageLimit => 34,
+            Register::CellUnderVoltageLimit => 35,
+            Register::ChargeOverTemperatureLimit => 36,
+            Register::ChargeHighTemperatureLimit => 37,
+            Register::ChargeLowTemperatureLimit => 38,
+            Register::ChargeUnderTemperatureLimit => 39,
+            Register::ChargeOver2CurrentLimit => 40,
+            Register::ChargeOver1CurrentLimit => 41,
+            Register::ChargeHighCurrentLimit => 42,
+            Register::ModuleOverVoltageLimit => 43,
+            Register::ModuleHighVoltageLimit => 44,
+            Register::ModuleLowVoltageLimit => 45,
+            Register::ModuleUnderVoltageLimit => 46,
+            Register::DischargeOverTemperatureLimit => 47,
+            Register::DischargeHighTemperatureLimit => 48,
+            Register::DischargeLowTemperatureLimit => 49,
+            Register::DischargeUnderTemperatureLimit => 50,
+            Register::DischargeOver2CurrentLimit => 51,
+            Register::DischargeOver1CurrentLimit => 52,
+            Register::DischargeHighCurrentLimit => 53,
+            Register::ShutdownCommand => 54,
+            Register::DeviceId => 55,
+            Register::LockControl => 56,
+            Register::TestReady => 57,
+            Register::UniqueIdentificationCode => 58,
+            Register::ChargePowerSetting => 59,
+            Register::DischargePowerSetting => 60,
+            Register::AcpBroadcast => 61,
+            Register::AcpConfigure => 62,
+            Register::AcpShake => 63,
         }
     }
 
-    /// Parse a value from register data (u16 slice from `Transport::read_holding_registers`).
+    /// Catalog name, suffixed with the cell or sensor number if indexed
+    /// (`cell_voltage_3`).
     #[must_use]
-    pub fn parse_registers(&self, registers: &[u16]) -> Value {
-        let mut data = vec![0u8; registers.len() * 2];
-        byteorder::BigEndian::write_u16_into(registers, &mut data);
-        self.parse_value(&data)
+    pub fn name(&self) -> String {
+        match self.index() {
+            Some(n) => format!("{}_{n}", self.info().name),
+            None => self.info().name.to_string(),
+        }
     }
 
-    #[must_use]
-    #[allow(clippy::too_many_lines)]
-    pub fn parse_value(&self, data: &[u8]) -> Value {
+    /// 1-based cell or sensor number of an indexed register.
+    const fn index(&self) -> Option<u8> {
         match self {
-            // Integer values (u16 -> u32)
-            Register::CellCount
-            | Register::CellTemperatureCount
-            | Register::EnvironmentTemperatureCount
-            | Register::HeaterTemperatureCount
-            | Register::CycleNumber
-            | Register::ShutdownCommand
-            | Register::DeviceId
-            | Register::LockControl
-            | Register::TestReady
-            | Register::ChargePowerSetting
-            | Register::DischargePowerSetting
-            | Register::AcpBroadcast
-            | Register::AcpConfigure
-            | Register::AcpShake => Value::Integer(BigEndian::read_u16(data) as u32),
-
-            // Voltage (0.1V resolution)
-            Register::CellVoltage(_)
-            | Register::ModuleVoltage
-            | Register::ChargeVoltageLimit
-            | Register::DischargeVoltageLimit
-            | Register::CellOverVoltageLimit
-            | Register::CellHighVoltageLimit
-            | Register::CellLowVoltageLimit
-            | Register::CellUnderVoltageLimit
-            | Register::ModuleOverVoltageLimit
-            | Register::ModuleHighVoltageLimit
-            | Register::ModuleLowVoltageLimit
-            | Register::ModuleUnderVoltageLimit => Value::ElectricPotential(
-                ElectricPotential::new::<volt>(BigEndian::read_u16(data) as f32 * 0.1),
-            ),
+            Register::CellVoltage(n)
+            | Register::CellTemperature(n)
+            | Register::EnvironmentTemperature(n)
+            | Register::HeaterTemperature(n) => Some(*n),
+            _ => None,
+        }
+    }
 
-            // Temperature (0.1 C resolution, unsigned)
-            Register::CellTemperature(_)
-            | Register::BmsTemperature
-            | Register::EnvironmentTemperature(_)
-            | Register::HeaterTemperature(_) => {
-                Value::ThermodynamicTemperature(ThermodynamicTemperature::new::<degree_celsius>(
-                    BigEndian::read_u16(data) as f32 * 0.1,
-                ))
-            }
+    /// This kind of register at cell or sensor `n`; unchanged if not indexed.
+    fn with_index(&self, n: u8) -> Self {
+        match self {
+            Register::CellVoltage(_) => Register::CellVoltage(n),
+            Register::CellTemperature(_) => Register::CellTemperature(n),
+            Register::EnvironmentTemperature(_) => Register::EnvironmentTemperature(n),
+            Register::HeaterTemperature(_) => Register::HeaterTemperature(n),
+            other => other.clone(),
+        }
+    }
 
-            // Temperature limits (0.1 C resolution, signed)
-            Register::ChargeOverTemperatureLimit
-            | Register::ChargeHighTemperatureLimit
-            | Register::ChargeLowTemperatureLimit
-            | Register::ChargeUnderTemperatureLimit
-            | Register::DischargeOverTemperatureLimit
-            | Register::DischargeHighTemperatureLimit
-            | Register::DischargeLowTemperatureLimit
-            | Register::DischargeUnderTemperatureLimit => {
-                Value::ThermodynamicTemperature(ThermodynamicTemperature::new::<degree_celsius>(
-                    BigEndian::read_i16(data) as f32 * 0.1,
-                ))
+    /// The register whose first word is at `address`, if any.
+    #[must_use]
+    pub fn from_address(address: u16) -> Option<Self> {
+        CATALOG.iter().find_map(|info| {
+            let offset = address.checked_sub(info.address)?;
+            if offset % info.words != 0 || offset / info.words >= u16::from(info.count) {
+                return None;
             }
+            Some(info.register.with_index((offset / info.words) as u8 + 1))
+        })
+    }
 
-            // Current (0.01A resolution, signed)
-            Register::Current | Register::DischargeCurrentLimit => Value::ElectricCurrent(
-                ElectricCurrent::new::<ampere>(BigEndian::read_i16(data) as f32 * 0.01),
-            ),
+    #[must_use]
+    pub const fn address(&self) -> u16 {
+        let info = self.info();
+        match self.index() {
+            Some(n) => (info.address - info.words) + n as u16 * info.words,
+            None => info.address,
+        }
+    }
 
-            // Current (0.01A resolution, unsigned)
-            Register::ChargeCurrentLimit
-            | Register::ChargeOver2CurrentLimit
-            | Register::ChargeOver1CurrentLimit
-            | Register::ChargeHighCurrentLimit
-            | Register::DischargeOver2CurrentLimit
-            | Register::DischargeOver1CurrentLimit
-            | Register::DischargeHighCurrentLimit => Value::ElectricCurrent(
-                ElectricCurrent::new::<ampere>(BigEndian::read_u16(data) as f32 * 0.01),
-            ),
+    #[must_use]
+    pub const fn quantity(&self) -> u16 {
+        self.info().words
+    }
 
-            // Capacity (0.001Ah resolution, u32)
-            Register::RemainingCapacity | Register::TotalCapacity => Value::ElectricCurrent(
-                ElectricCurrent::new::<ampere>(BigEndian::read_u32(data) as f32 * 0.001),
-            ),
+    /// Parse a value from register data (u16 slice from `Transport::read_holding_registers`).
+    pub fn parse_registers(&self, registers: &[u16]) -> Result<Value> {
+        let mut data = vec![0u8; registers.len() * 2];
+        byteorder::BigEndian::write_u16_into(registers, &mut data);
+        self.parse_value(&data)
+    }
 
-            // String values
-            Register::SnNumber
-            | Register::ManufactureVersion
-            | Register::MainlineVersion
-            | Register::CommunicationProtocolVersion
-            | Register::BatteryName
-            | Register::SoftwareVersion
-            | Register::ManufacturerName => {
-                Value::String(String::from_utf8_lossy(data).to_string())
-            }
+    /// Parse a value from raw register bytes, which must be exactly `quantity()` words:
+    /// a truncated response is an `InvalidLength` error rather than a misread.
+    pub fn parse_value(&self, data: &[u8]) -> Result<Value> {
+        let info = self.info();
+        let expected = info.words as usize * 2;
+        if data.len() != expected {
+            return Err(RenogyError::InvalidLength {
+                expected,
+                actual: data.len(),
+            });
+        }
+        let word = || BigEndian::read_u16(data);
+        let long = || BigEndian::read_u32(data);
+        let scaled = || {
+            let raw = match (info.words, info.signed) {
+                (2, false) => f64::from(long()),
+                (2, true) => f64::from(BigEndian::read_i32(data)),
+                (_, false) => f64::from(word()),
+                (_, true) => f64::from(BigEndian::read_i16(data)),
+            };
+            (raw * info.scale) as f32
+        };
 
-            // Alarm/status registers
-            Register::CellVoltageAlarmInfo => {
-                Value::CellVoltageAlarms(CellVoltageAlarms::from_bits(BigEndian::read_u32(data)))
+        Ok(match info.data_type {
+            DataType::Integer if info.words == 2 => Value::Integer(long()),
+            DataType::Integer => Value::Integer(u32::from(word())),
+            DataType::Voltage => Value::ElectricPotential(ElectricPotential::new::<volt>(scaled())),
+            DataType::Current | DataType::Capacity => {
+                Value::ElectricCurrent(ElectricCurrent::new::<ampere>(scaled()))
             }
-            Register::CellTemperatureAlarmInfo => Value::CellTemperatureAlarms(
-                CellTemperatureAlarms::from_bits(BigEndian::read_u32(data)),
+            DataType::Temperature => Value::ThermodynamicTemperature(
+                ThermodynamicTemperature::new::<degree_celsius>(scaled()),
             ),
-            Register::OtherAlarmInfo => Value::OtherAlarmInfo(OtherAlarmInfo::from_bits_truncate(
-                BigEndian::read_u32(data),
-            )),
-            Register::Status1 => {
-                Value::Status1(Status1::from_bits_truncate(BigEndian::read_u16(data)))
+            DataType::String => Value::String(String::from_utf8_lossy(data).to_string()),
+            DataType::CellVoltageAlarms => {
+                Value::CellVoltageAlarms(CellVoltageAlarms::from_bits(long()))
             }
-            Register::Status2 => {
-                Value::Status2(Status2::from_bits_truncate(BigEndian::read_u16(data)))
+            DataType::CellTemperatureAlarms => {
+                Value::CellTemperatureAlarms(CellTemperatureAlarms::from_bits(long()))
             }
-            Register::Status3 => {
-                Value::Status3(Status3::from_bits_truncate(BigEndian::read_u16(data)))
+            DataType::OtherAlarmInfo => {
+                Value::OtherAlarmInfo(OtherAlarmInfo::from_bits_truncate(long()))
             }
-            Register::ChargeDischargeStatus => Value::ChargeDischargeStatus(
-                ChargeDischargeStatus::from_bits_truncate(BigEndian::read_u16(data)),
-            ),
-
-            // Unique ID (u32)
-            Register::UniqueIdentificationCode => Value::Integer(BigEndian::read_u32(data)),
-        }
+            DataType::Status1 => Value::Status1(Status1::from_bits_truncate(word())),
+            DataType::Status2 => Value::Status2(Status2::from_bits_truncate(word())),
+            DataType::Status3 => Value::Status3(Status3::from_bits_truncate(word())),
+            DataType::ChargeDischargeStatus => {
+                Value::ChargeDischargeStatus(ChargeDischargeStatus::from_bits_truncate(word()))
+            }
+        })
     }
 
     pub fn is_writable(&self) -> bool {
-        matches!(
-            self,
-            Register::ChargeVoltageLimit
-                | Register::DischargeVoltageLimit
-                | Register::ChargeCurrentLimit
-                | Register::DischargeCurrentLimit
-                | Register::CellOverVoltageLimit
-                | Register::CellHighVoltageLimit
-                | Register::CellLowVoltageLimit
-                | Register::CellUnderVoltageLimit
-                | Register::ChargeOverTemperatureLimit
-                | Register::ChargeHighTemperatureLimit
-                | Register::ChargeLowTemperatureLimit
-                | Register::ChargeUnderTemperatureLimit
-                | Register::ChargeOver2CurrentLimit
-                | Register::ChargeOver1CurrentLimit
-                | Register::ChargeHighCurrentLimit
-                | Register::ModuleOverVoltageLimit
-                | Register::ModuleHighVoltageLimit
-                | Register::ModuleLowVoltageLimit
-                | Register::ModuleUnderVoltageLimit
-                | Register::DischargeOverTemperatureLimit
-                | Register::DischargeHighTemperatureLimit
-                | Register::DischargeLowTemperatureLimit
-                | Register::DischargeUnderTemperatureLimit
-                | Register::DischargeOver2CurrentLimit
-                | Register::DischargeOver1CurrentLimit
-                | Register::DischargeHighCurrentLimit
-                | Register::ShutdownCommand
-                | Register::DeviceId
-                | Register::LockControl
-                | Register::TestReady
-                | Register::UniqueIdentificationCode
-                | Register::ChargePowerSetting
-                | Register::DischargePowerSetting
-                | Register::AcpBroadcast
-                | Register::AcpConfigure
-                | Register::AcpShake
-        )
+        self.info().access == Access::ReadWrite
+    }
+
+    /// Values a writable register accepts, in the unit `parse_value` returns (V, A, °C,
+    /// or the raw integer). `None` for read-only registers and for writable ones whose
+    /// whole word is meaningful.
+    #[must_use]
+    pub fn documented_range(&self) -> Option<RangeInclusive<f32>> {
+        let info = self.info();
+        Some(info.min? as f32..=info.max? as f32)
     }
 
     /// Inverse of `parse_value`: encode a `Value` into this register's raw bytes.
@@ -414,134 +1074,102 @@ impl Register {
     /// read-only monitoring ones), so it round-trips with `parse_value` and is the
     /// single source of truth for serialization (an emulator can produce a coherent
     /// response for any register).
+    ///
+    /// Scaled values are rounded to the nearest step of the register's resolution
+    /// (3.45 V is 35, not 34). A value the register cannot hold -- out of the word's
+    /// range, outside `documented_range`, not finite, or a string longer than the
+    /// register -- is `InvalidRegisterRange` rather than being truncated.
     pub fn encode_value(&self, value: &Value) -> Result<Vec<u8>> {
-        let mut data = vec![0u8; (self.quantity() * 2) as usize];
+        let info = self.info();
+        let mut data = vec![0u8; info.words as usize * 2];
 
-        match (self, value) {
-            (Register::UniqueIdentificationCode, Value::Integer(v)) => {
-                BigEndian::write_u32(&mut data, *v);
+        match (info.data_type, value) {
+            (DataType::Integer, Value::Integer(v)) => {
+                self.write_scaled(&mut data, f64::from(*v))?
             }
-            (
-                Register::CellCount
-                | Register::CellTemperatureCount
-                | Register::EnvironmentTemperatureCount
-                | Register::HeaterTemperatureCount
-                | Register::CycleNumber
-                | Register::ShutdownCommand
-                | Register::DeviceId
-                | Register::LockControl
-                | Register::TestReady
-                | Register::ChargePowerSetting
-                | Register::DischargePowerSetting
-                | Register::AcpBroadcast
-                | Register::AcpConfigure
-                | Register::AcpShake,
-                Value::Integer(v),
-            ) => BigEndian::write_u16(&mut data, *v as u16),
-
-            (
-                Register::CellVoltage(_)
-                | Register::ModuleVoltage
-                | Register::ChargeVoltageLimit
-                | Register::DischargeVoltageLimit
-                | Register::CellOverVoltageLimit
-                | Register::CellHighVoltageLimit
-                | Register::CellLowVoltageLimit
-                | Register::CellUnderVoltageLimit
-                | Register::ModuleOverVoltageLimit
-                | Register::ModuleHighVoltageLimit
-                | Register::ModuleLowVoltageLimit
-                | Register::ModuleUnderVoltageLimit,
-                Value::ElectricPotential(v),
-            ) => BigEndian::write_u16(&mut data, (v.get::<volt>() * 10.0) as u16),
-
-            (
-                Register::CellTemperature(_)
-                | Register::BmsTemperature
-                | Register::EnvironmentTemperature(_)
-                | Register::HeaterTemperature(_),
-                Value::ThermodynamicTemperature(t),
-            ) => BigEndian::write_u16(&mut data, (t.get::<degree_celsius>() * 10.0) as u16),
-
-            (
-                Register::ChargeOverTemperatureLimit
-                | Register::ChargeHighTemperatureLimit
-                | Register::ChargeLowTemperatureLimit
-                | Register::ChargeUnderTemperatureLimit
-                | Register::DischargeOverTemperatureLimit
-                | Register::DischargeHighTemperatureLimit
-                | Register::DischargeLowTemperatureLimit
-                | Register::DischargeUnderTemperatureLimit,
-                Value::ThermodynamicTemperature(t),
-            ) => BigEndian::write_i16(&mut data, (t.get::<degree_celsius>() * 10.0) as i16),
-
-            (Register::Current | Register::DischargeCurrentLimit, Value::ElectricCurrent(c)) => {
-                BigEndian::write_i16(&mut data, (c.get::<ampere>() * 100.0) as i16)
+            (DataType::Voltage, Value::ElectricPotential(v)) => {
+                self.write_scaled(&mut data, f64::from(v.get::<volt>()))?;
             }
-
-            (
-                Register::ChargeCurrentLimit
-                | Register::ChargeOver2CurrentLimit
-                | Register::ChargeOver1CurrentLimit
-                | Register::ChargeHighCurrentLimit
-                | Register::DischargeOver2CurrentLimit
-                | Register::DischargeOver1CurrentLimit
-                | Register::DischargeHighCurrentLimit,
-                Value::ElectricCurrent(c),
-            ) => BigEndian::write_u16(&mut data, (c.get::<ampere>() * 100.0) as u16),
-
-            (Register::RemainingCapacity | Register::TotalCapacity, Value::ElectricCurrent(c)) => {
-                BigEndian::write_u32(&mut data, (c.get::<ampere>() * 1000.0) as u32)
+            (DataType::Current | DataType::Capacity, Value::ElectricCurrent(c)) => {
+                self.write_scaled(&mut data, f64::from(c.get::<ampere>()))?;
             }
-
-            (
-                Register::SnNumber
-                | Register::ManufactureVersion
-                | Register::MainlineVersion
-                | Register::CommunicationProtocolVersion
-                | Register::BatteryName
-                | Register::SoftwareVersion
-                | Register::ManufacturerName,
-                Value::String(s),
-            ) => {
+            (DataType::Temperature, Value::ThermodynamicTemperature(t)) => {
+                self.write_scaled(&mut data, f64::from(t.get::<degree_celsius>()))?;
+            }
+            (DataType::String, Value::String(s)) => {
                 let bytes = s.as_bytes();
-                let n = bytes.len().min(data.len());
-                data[..n].copy_from_slice(&bytes[..n]);
+                if bytes.len() > data.len() {
+                    return Err(RenogyError::InvalidRegisterRange);
+                }
+                data[..bytes.len()].copy_from_slice(bytes);
             }
-
-            (Register::CellVoltageAlarmInfo, Value::CellVoltageAlarms(a)) => {
+            (DataType::CellVoltageAlarms, Value::CellVoltageAlarms(a)) => {
                 BigEndian::write_u32(&mut data, a.to_bits());
             }
-            (Register::CellTemperatureAlarmInfo, Value::CellTemperatureAlarms(a)) => {
+            (DataType::CellTemperatureAlarms, Value::CellTemperatureAlarms(a)) => {
                 BigEndian::write_u32(&mut data, a.to_bits());
             }
-            (Register::OtherAlarmInfo, Value::OtherAlarmInfo(a)) => {
+            (DataType::OtherAlarmInfo, Value::OtherAlarmInfo(a)) => {
                 BigEndian::write_u32(&mut data, a.bits());
             }
-            (Register::Status1, Value::Status1(s)) => BigEndian::write_u16(&mut data, s.bits()),
-            (Register::Status2, Value::Status2(s)) => BigEndian::write_u16(&mut data, s.bits()),
-            (Register::Status3, Value::Status3(s)) => BigEndian::write_u16(&mut data, s.bits()),
-            (Register::ChargeDischargeStatus, Value::ChargeDischargeStatus(s)) => {
-                BigEndian::write_u16(&mut data, s.bits())
+            (DataType::Status1, Value::Status1(s)) => BigEndian::write_u16(&mut data, s.bits()),
+            (DataType::Status2, Value::Status2(s)) => BigEndian::write_u16(&mut data, s.bits()),
+            (DataType::Status3, Value::Status3(s)) => BigEndian::write_u16(&mut data, s.bits()),
+            (DataType::ChargeDischargeStatus, Value::ChargeDischargeStatus(s)) => {
+                BigEndian::write_u16(&mut data, s.bits());
             }
-
             _ => return Err(RenogyError::UnsupportedOperation),
         }
 
         Ok(data)
     }
+
+    /// Write `value` as steps of the register's scale, rounded to nearest and checked
+    /// against both `documented_range` and the width and signedness of the word.
+    fn write_scaled(&self, data: &mut [u8], value: f64) -> Result<()> {
+        let info = self.info();
+        let steps = (value / info.scale).round();
+        if !steps.is_finite() {
+            return Err(RenogyError::InvalidRegisterRange);
+        }
+        // Saturates for huge values, which the conversions below then reject.
+        let steps = steps as i64;
+        if let (Some(min), Some(max)) = (info.min, info.max) {
+            let min = (min / info.scale).round() as i64;
+            let max = (max / info.scale).round() as i64;
+            if !(min..=max).contains(&steps) {
+                return Err(RenogyError::InvalidRegisterRange);
+            }
+        }
+        let out_of_range = |_| RenogyError::InvalidRegisterRange;
+        match (info.words, info.signed) {
+            (2, false) => BigEndian::write_u32(data, u32::try_from(steps).map_err(out_of_range)?),
+            (2, true) => BigEndian::write_i32(data, i32::try_from(steps).map_err(out_of_range)?),
+            (_, false) => BigEndian::write_u16(data, u16::try_from(steps).map_err(out_of_range)?),
+            (_, true) => BigEndian::write_i16(data, i16::try_from(steps).map_err(out_of_range)?),
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use super::Access;
+    use super::DataType;
     use super::Register;
     use super::Value;
+    use super::catalog;
+    use super::catalog_json;
     use crate::alarm::CellTemperatureAlarm;
     use crate::alarm::CellTemperatureAlarms;
     use crate::alarm::CellVoltageAlarm;
     use crate::alarm::CellVoltageAlarms;
     use crate::alarm::Status1;
     use crate::alarm::Status2;
+    use crate::error::RenogyError;
+    use proptest::prelude::prop_assert;
+    use proptest::prelude::prop_assert_eq;
+    use proptest::prelude::proptest;
     use uom::si::electric_current::ampere;
     use uom::si::electric_potential::volt;
     use uom::si::f32::ElectricCurrent;
@@ -551,9 +1179,19 @@ mod tests {
 
     const TOLERANCE: f32 = 1e-3;
 
+    /// Registers that are not indexed by cell or sensor number.
+    fn fixed_registers() -> impl Iterator<Item = Register> {
+        catalog()
+            .iter()
+            .filter(|info| info.count == 1)
+            .map(|info| info.register.clone())
+    }
+
     #[test]
     fn parse_cell_voltage() {
-        let value = Register::CellVoltage(1).parse_value(&33u16.to_be_bytes());
+        let value = Register::CellVoltage(1)
+            .parse_value(&33u16.to_be_bytes())
+            .unwrap();
         assert_eq!(
             value,
             Value::ElectricPotential(ElectricPotential::new::<volt>(3.3))
@@ -562,13 +1200,17 @@ mod tests {
 
     #[test]
     fn parse_integer() {
-        let value = Register::CellCount.parse_value(&16u16.to_be_bytes());
+        let value = Register::CellCount
+            .parse_value(&16u16.to_be_bytes())
+            .unwrap();
         assert_eq!(value, Value::Integer(16));
     }
 
     #[test]
     fn parse_multiword_current() {
-        let value = Register::RemainingCapacity.parse_value(&50000u32.to_be_bytes());
+        let value = Register::RemainingCapacity
+            .parse_value(&50000u32.to_be_bytes())
+            .unwrap();
         let Value::ElectricCurrent(current) = value else {
             panic!("wrong type: {value:?}");
         };
@@ -577,14 +1219,14 @@ mod tests {
 
     #[test]
     fn parse_string() {
-        let value = Register::SnNumber.parse_value(b"12345678");
-        assert_eq!(value, Value::String("12345678".to_string()));
+        let value = Register::SnNumber.parse_value(b"1234567890ABCDEF").unwrap();
+        assert_eq!(value, Value::String("1234567890ABCDEF".to_string()));
     }
 
     #[test]
     fn parse_cell_voltage_alarms() {
         let data = 0b0000_0000_0000_0001_0000_0000_0000_0001u32.to_be_bytes();
-        let value = Register::CellVoltageAlarmInfo.parse_value(&data);
+        let value = Register::CellVoltageAlarmInfo.parse_value(&data).unwrap();
         let expected = CellVoltageAlarms {
             alarms: [
                 CellVoltageAlarm::OverVoltage,
@@ -610,7 +1252,9 @@ mod tests {
 
     #[test]
     fn parse_status1() {
-        let value = Register::Status1.parse_value(&0b1000_0000_0000_0101u16.to_be_bytes());
+        let value = Register::Status1
+            .parse_value(&0b1000_0000_0000_0101u16.to_be_bytes())
+            .unwrap();
         let expected =
             Status1::MODULE_UNDER_VOLTAGE | Status1::DISCHARGE_MOSFET | Status1::SHORT_CIRCUIT;
         assert_eq!(value, Value::Status1(expected));
@@ -624,7 +1268,7 @@ mod tests {
                 4.2,
             )))
             .unwrap();
-        let Value::ElectricPotential(parsed) = register.parse_value(&bytes) else {
+        let Value::ElectricPotential(parsed) = register.parse_value(&bytes).unwrap() else {
             panic!("wrong type");
         };
         assert!((parsed.get::<volt>() - 4.2).abs() < TOLERANCE);
@@ -638,7 +1282,7 @@ mod tests {
                 100.0,
             )))
             .unwrap();
-        let Value::ElectricCurrent(parsed) = register.parse_value(&bytes) else {
+        let Value::ElectricCurrent(parsed) = register.parse_value(&bytes).unwrap() else {
             panic!("wrong type");
         };
         assert!((parsed.get::<ampere>() - 100.0).abs() < TOLERANCE);
@@ -687,7 +1331,7 @@ mod tests {
     fn encode_value_roundtrips_integer() {
         let reg = Register::CellCount;
         let bytes = reg.encode_value(&Value::Integer(16)).unwrap();
-        assert_eq!(reg.parse_value(&bytes), Value::Integer(16));
+        assert_eq!(reg.parse_value(&bytes).unwrap(), Value::Integer(16));
     }
 
     #[test]
@@ -695,7 +1339,7 @@ mod tests {
         let reg = Register::Status1;
         let status = Status1::DISCHARGE_MOSFET | Status1::SHORT_CIRCUIT;
         let bytes = reg.encode_value(&Value::Status1(status)).unwrap();
-        assert_eq!(reg.parse_value(&bytes), Value::Status1(status));
+        assert_eq!(reg.parse_value(&bytes).unwrap(), Value::Status1(status));
     }
 
     #[test]
@@ -706,7 +1350,7 @@ mod tests {
                 3.3,
             )))
             .unwrap();
-        let Value::ElectricPotential(parsed) = reg.parse_value(&bytes) else {
+        let Value::ElectricPotential(parsed) = reg.parse_value(&bytes).unwrap() else {
             panic!("wrong type");
         };
         assert!((parsed.get::<volt>() - 3.3).abs() < TOLERANCE);
@@ -722,7 +1366,10 @@ mod tests {
         let bytes = reg
             .encode_value(&Value::CellVoltageAlarms(original))
             .unwrap();
-        assert_eq!(reg.parse_value(&bytes), Value::CellVoltageAlarms(original));
+        assert_eq!(
+            reg.parse_value(&bytes).unwrap(),
+            Value::CellVoltageAlarms(original)
+        );
     }
 
     #[test]
@@ -733,24 +1380,47 @@ mod tests {
                 ThermodynamicTemperature::new::<degree_celsius>(-12.5),
             ))
             .unwrap();
-        let Value::ThermodynamicTemperature(t) = reg.parse_value(&bytes) else {
+        let Value::ThermodynamicTemperature(t) = reg.parse_value(&bytes).unwrap() else {
             panic!("wrong type");
         };
         assert!((t.get::<degree_celsius>() + 12.5).abs() < TOLERANCE);
     }
 
     #[test]
-    fn encode_value_roundtrips_unsigned_temperature() {
-        let reg = Register::CellTemperature(1);
-        let bytes = reg
-            .encode_value(&Value::ThermodynamicTemperature(
-                ThermodynamicTemperature::new::<degree_celsius>(25.0),
-            ))
+    fn encode_value_roundtrips_sensor_temperature() {
+        for register in [
+            Register::CellTemperature(1),
+            Register::BmsTemperature,
+            Register::EnvironmentTemperature(2),
+            Register::HeaterTemperature(1),
+        ] {
+            for celsius in [25.0, 0.0, -0.1, -18.3] {
+                let bytes = register
+                    .encode_value(&Value::ThermodynamicTemperature(
+                        ThermodynamicTemperature::new::<degree_celsius>(celsius),
+                    ))
+                    .unwrap();
+                let Value::ThermodynamicTemperature(t) = register.parse_value(&bytes).unwrap()
+                else {
+                    panic!("wrong type");
+                };
+                assert!(
+                    (t.get::<degree_celsius>() - celsius).abs() < 0.1 + TOLERANCE,
+                    "{register:?} {celsius}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parse_sub_zero_cell_temperature() {
+        let value = Register::CellTemperature(1)
+            .parse_value(&0xFF9Cu16.to_be_bytes())
             .unwrap();
-        let Value::ThermodynamicTemperature(t) = reg.parse_value(&bytes) else {
+        let Value::ThermodynamicTemperature(t) = value else {
             panic!("wrong type");
         };
-        assert!((t.get::<degree_celsius>() - 25.0).abs() < TOLERANCE);
+        assert!((t.get::<degree_celsius>() + 10.0).abs() < TOLERANCE);
     }
 
     #[test]
@@ -761,7 +1431,7 @@ mod tests {
                 -5.0,
             )))
             .unwrap();
-        let Value::ElectricCurrent(c) = reg.parse_value(&bytes) else {
+        let Value::ElectricCurrent(c) = reg.parse_value(&bytes).unwrap() else {
             panic!("wrong type");
         };
         assert!((c.get::<ampere>() + 5.0).abs() < TOLERANCE);
@@ -775,7 +1445,7 @@ mod tests {
                 50.0,
             )))
             .unwrap();
-        let Value::ElectricCurrent(c) = reg.parse_value(&bytes) else {
+        let Value::ElectricCurrent(c) = reg.parse_value(&bytes).unwrap() else {
             panic!("wrong type");
         };
         assert!((c.get::<ampere>() - 50.0).abs() < TOLERANCE);
@@ -787,7 +1457,7 @@ mod tests {
         let bytes = reg
             .encode_value(&Value::String("ABCD".to_string()))
             .unwrap();
-        let Value::String(s) = reg.parse_value(&bytes) else {
+        let Value::String(s) = reg.parse_value(&bytes).unwrap() else {
             panic!("wrong type");
         };
         assert_eq!(s.trim_matches('\0'), "ABCD");
@@ -798,14 +1468,17 @@ mod tests {
         let reg = Register::Status2;
         let status = Status2::HEATER_ON | Status2::FULLY_CHARGED;
         let bytes = reg.encode_value(&Value::Status2(status)).unwrap();
-        assert_eq!(reg.parse_value(&bytes), Value::Status2(status));
+        assert_eq!(reg.parse_value(&bytes).unwrap(), Value::Status2(status));
     }
 
     #[test]
     fn encode_value_roundtrips_unique_id() {
         let reg = Register::UniqueIdentificationCode;
         let bytes = reg.encode_value(&Value::Integer(0xDEAD_BEEF)).unwrap();
-        assert_eq!(reg.parse_value(&bytes), Value::Integer(0xDEAD_BEEF));
+        assert_eq!(
+            reg.parse_value(&bytes).unwrap(),
+            Value::Integer(0xDEAD_BEEF)
+        );
     }
 
     #[test]
@@ -819,8 +1492,390 @@ mod tests {
             .encode_value(&Value::CellTemperatureAlarms(original))
             .unwrap();
         assert_eq!(
-            reg.parse_value(&bytes),
+            reg.parse_value(&bytes).unwrap(),
             Value::CellTemperatureAlarms(original)
         );
     }
+
+    #[test]
+    fn from_address_inverts_address() {
+        let registers = fixed_registers().chain([
+            Register::CellVoltage(1),
+            Register::CellVoltage(16),
+            Register::CellTemperature(4),
+            Register::EnvironmentTemperature(2),
+            Register::HeaterTemperature(1),
+        ]);
+        for register in registers {
+            assert_eq!(Register::from_address(register.address()), Some(register));
+        }
+        assert_eq!(Register::from_address(5045), None);
+    }
+
+    #[test]
+    fn parse_value_rejects_wrong_length() {
+        assert!(matches!(
+            Register::RemainingCapacity.parse_value(&[0x00, 0x01]),
+            Err(RenogyError::InvalidLength {
+                expected: 4,
+                actual: 2
+            })
+        ));
+        assert!(Register::SnNumber.parse_value(b"1234567").is_err());
+        assert!(Register::CellVoltage(1).parse_value(&[]).is_err());
+        assert!(Register::CellVoltage(1).parse_registers(&[33, 0]).is_err());
+    }
+
+    #[test]
+    fn parse_value_never_panics() {
+        let registers = fixed_registers().chain([
+            Register::CellVoltage(1),
+            Register::CellTemperature(1),
+            Register::EnvironmentTemperature(1),
+            Register::HeaterTemperature(1),
+        ]);
+        for register in registers {
+            for len in 0..=24 {
+                for fill in [0x00, 0x7F, 0x80, 0xFF] {
+                    let data = vec![fill; len];
+                    let parsed = register.parse_value(&data);
+                    assert_eq!(parsed.is_ok(), len == register.quantity() as usize * 2);
+                }
+            }
+        }
+    }
+
+    fn voltage(volts: f32) -> Value {
+        Value::ElectricPotential(ElectricPotential::new::<volt>(volts))
+    }
+
+    fn current(amps: f32) -> Value {
+        Value::ElectricCurrent(ElectricCurrent::new::<ampere>(amps))
+    }
+
+    fn temperature(celsius: f32) -> Value {
+        Value::ThermodynamicTemperature(ThermodynamicTemperature::new::<degree_celsius>(celsius))
+    }
+
+    #[test]
+    fn encode_value_rounds_to_nearest() {
+        assert_eq!(
+            Register::CellOverVoltageLimit
+                .encode_value(&voltage(3.45))
+                .unwrap(),
+            35u16.to_be_bytes()
+        );
+        assert_eq!(
+            Register::ModuleOverVoltageLimit
+                .encode_value(&voltage(14.59))
+                .unwrap(),
+            146u16.to_be_bytes()
+        );
+        assert_eq!(
+            Register::ChargeLowTemperatureLimit
+                .encode_value(&temperature(-0.06))
+                .unwrap(),
+            (-1i16).to_be_bytes()
+        );
+        assert_eq!(
+            Register::ChargeHighCurrentLimit
+                .encode_value(&current(99.996))
+                .unwrap(),
+            10_000u16.to_be_bytes()
+        );
+    }
+
+    #[test]
+    fn encode_value_rejects_unrepresentable() {
+        let rejected = |register: Register, value: Value| {
+            assert!(
+                matches!(
+                    register.encode_value(&value),
+                    Err(RenogyError::InvalidRegisterRange)
+                ),
+                "{register:?} {value:?}"
+            );
+        };
+        // Outside the word.
+        rejected(Register::CellVoltage(1), voltage(-0.1));
+        rejected(Register::CellVoltage(1), voltage(6553.6));
+        rejected(Register::Current, current(327.68));
+        rejected(Register::BmsTemperature, temperature(-3276.9));
+        rejected(Register::CellCount, Value::Integer(65_536));
+        rejected(Register::TotalCapacity, current(-1.0));
+        rejected(Register::ModuleVoltage, voltage(f32::NAN));
+        rejected(Register::ModuleVoltage, voltage(f32::INFINITY));
+        rejected(
+            Register::SnNumber,
+            Value::String("1234567890ABCDEFG".into()),
+        );
+        rejected(Register::DischargeCurrentLimit, current(-327.69));
+        // Outside the documented range.
+        rejected(Register::CellOverVoltageLimit, voltage(0.0));
+        rejected(Register::CellOverVoltageLimit, voltage(4.6));
+        rejected(Register::CellUnderVoltageLimit, voltage(1.9));
+        rejected(Register::ModuleHighVoltageLimit, voltage(72.1));
+        rejected(Register::ChargeUnderTemperatureLimit, temperature(-40.1));
+        rejected(Register::DischargeOver1CurrentLimit, current(300.01));
+        rejected(Register::DeviceId, Value::Integer(0));
+        rejected(Register::DeviceId, Value::Integer(248));
+        rejected(Register::ChargePowerSetting, Value::Integer(101));
+        rejected(Register::AcpShake, Value::Integer(0));
+        rejected(Register::AcpBroadcast, Value::Integer(255));
+    }
+
+    #[test]
+    fn documented_range_is_inclusive() {
+        assert!(
+            Register::CellOverVoltageLimit
+                .encode_value(&voltage(4.5))
+                .is_ok()
+        );
+        assert!(
+            Register::CellUnderVoltageLimit
+                .encode_value(&voltage(2.0))
+                .is_ok()
+        );
+        assert!(
+            Register::DischargeUnderTemperatureLimit
+                .encode_value(&temperature(-40.0))
+                .is_ok()
+        );
+        // A 48 V pack's module limits fit.
+        assert!(
+            Register::ModuleOverVoltageLimit
+                .encode_value(&voltage(60.0))
+                .is_ok()
+        );
+        assert!(
+            Register::DeviceId
+                .encode_value(&Value::Integer(247))
+                .is_ok()
+        );
+        for register in fixed_registers() {
+            if register.documented_range().is_some() {
+                assert!(register.is_writable(), "{register:?}");
+            }
+        }
+    }
+
+    /// The value `parse_value` gives back for a voltage, current or temperature.
+    fn parsed_scalar(value: &Value) -> f32 {
+        match value {
+            Value::ElectricPotential(v) => v.get::<volt>(),
+            Value::ElectricCurrent(c) => c.get::<ampere>(),
+            Value::ThermodynamicTemperature(t) => t.get::<degree_celsius>(),
+            _ => panic!("not a scalar: {value:?}"),
+        }
+    }
+
+    /// Encode `value` and parse it back: either it is rejected, or it comes back within
+    /// half a step of `resolution`. Values inside `range` must not be rejected.
+    fn roundtrip(
+        register: &Register,
+        value: &Value,
+        resolution: f32,
+        range: &std::ops::RangeInclusive<f32>,
+    ) -> Result<(), proptest::test_runner::TestCaseError> {
+        let original = parsed_scalar(value);
+        match register.encode_value(value) {
+            Ok(bytes) => {
+                prop_assert_eq!(bytes.len(), register.quantity() as usize * 2);
+                let parsed = parsed_scalar(&register.parse_value(&bytes).unwrap());
+                prop_assert!(
+                    (parsed - original).abs() <= resolution / 2.0 + original.abs() * 1e-6,
+                    "{:?}: {} came back as {}",
+                    register,
+                    original,
+                    parsed
+                );
+            }
+            Err(err) => {
+                prop_assert!(matches!(err, RenogyError::InvalidRegisterRange));
+                // Half a step inside the bounds always rounds to a representable word.
+                prop_assert!(
+                    original < range.start() + resolution / 2.0
+                        || original > range.end() - resolution / 2.0,
+                    "{:?}: {} rejected",
+                    register,
+                    original
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn catalog_is_in_address_order_without_overlaps() {
+        for pair in catalog().windows(2) {
+            let end = pair[0].address + u16::from(pair[0].count) * pair[0].words;
+            assert!(
+                end <= pair[1].address,
+                "{} overlaps {}",
+                pair[0].name,
+                pair[1].name
+            );
+        }
+        for info in catalog() {
+            assert!(std::ptr::eq(info.register.info(), info), "{}", info.name);
+            assert_eq!(info.register.address(), info.address, "{}", info.name);
+            for register in info.registers() {
+                assert_eq!(Register::from_address(register.address()), Some(register));
+            }
+            assert_eq!(
+                info.unit.is_some(),
+                matches!(
+                    info.data_type,
+                    DataType::Voltage
+                        | DataType::Current
+                        | DataType::Capacity
+                        | DataType::Temperature
+                ),
+                "{}",
+                info.name
+            );
+            if info.min.is_some() {
+                assert_eq!(info.access, Access::ReadWrite, "{}", info.name);
+            }
+        }
+    }
+
+    #[test]
+    fn indexed_registers_follow_their_block() {
+        assert_eq!(Register::CellVoltage(16).address(), 5016);
+        assert_eq!(Register::CellTemperature(16).address(), 5033);
+        assert_eq!(Register::HeaterTemperature(2).address(), 5041);
+        assert_eq!(
+            Register::from_address(5017),
+            Some(Register::CellTemperatureCount)
+        );
+        assert_eq!(Register::from_address(5034), None);
+        assert_eq!(Register::from_address(5045), None);
+        assert_eq!(Register::CellTemperature(3).info().name, "cell_temperature");
+        assert_eq!(Register::CellTemperature(3).name(), "cell_temperature_3");
+        assert_eq!(Register::BmsTemperature.name(), "bms_temperature");
+    }
+
+    #[test]
+    fn catalog_exports_as_json() {
+        let json: serde_json::Value = serde_json::from_str(&catalog_json()).unwrap();
+        let entries = json.as_array().unwrap();
+        assert_eq!(entries.len(), catalog().len());
+        let cell_over_voltage = entries
+            .iter()
+            .find(|entry| entry["name"] == "cell_over_voltage_limit")
+            .unwrap();
+        assert_eq!(
+            *cell_over_voltage,
+            serde_json::json!({
+                "name": "cell_over_voltage_limit",
+                "address": 5200,
+                "count": 1,
+                "words": 1,
+                "data_type": "voltage",
+                "scale": 0.1,
+                "signed": false,
+                "unit": "V",
+                "access": "read_write",
+                "min": 2.0,
+                "max": 4.5,
+                "description": "Cell voltage that trips over-voltage protection",
+            })
+        );
+        let cell_voltage = entries
+            .iter()
+            .find(|entry| entry["name"] == "cell_voltage")
+            .unwrap();
+        assert_eq!(cell_voltage["count"], 16);
+        assert_eq!(cell_voltage["access"], "read_only");
+    }
+
+    proptest! {
+        #[test]
+        fn voltage_roundtrips(volts in -10.0f32..7000.0) {
+            for register in fixed_registers()
+                .chain([Register::CellVoltage(1)])
+                .filter(|r| matches!(r.parse_value(&[0, 0]), Ok(Value::ElectricPotential(_))))
+            {
+                let range = register.documented_range().unwrap_or(0.0..=6553.5);
+                roundtrip(&register, &voltage(volts), 0.1, &range)?;
+            }
+        }
+
+        #[test]
+        fn temperature_roundtrips(celsius in -3300.0f32..3300.0) {
+            for register in fixed_registers()
+                .chain([Register::CellTemperature(1), Register::HeaterTemperature(1)])
+                .filter(|r| {
+                    matches!(r.parse_value(&[0, 0]), Ok(Value::ThermodynamicTemperature(_)))
+                })
+            {
+                let range = register.documented_range().unwrap_or(-3276.8..=3276.7);
+                roundtrip(&register, &temperature(celsius), 0.1, &range)?;
+            }
+        }
+
+        #[test]
+        fn current_roundtrips(amps in -700.0f32..700.0) {
+            for register in fixed_registers()
+                .filter(|r| matches!(r.parse_value(&[0, 0]), Ok(Value::ElectricCurrent(_))))
+            {
+                let range = register.documented_range().unwrap_or(if register.info().signed {
+                    -327.68..=327.67
+                } else {
+                    0.0..=655.35
+                });
+                roundtrip(&register, &current(amps), 0.01, &range)?;
+            }
+        }
+
+        #[test]
+        fn capacity_roundtrips(amp_hours in -10.0f32..5000.0) {
+            for register in [Register::RemainingCapacity, Register::TotalCapacity] {
+                roundtrip(&register, &current(amp_hours), 0.001, &(0.0..=4_294_967.5))?;
+            }
+        }
+
+        #[test]
+        fn integer_roundtrips(n in 0u32..70_000) {
+            for register in fixed_registers()
+                .filter(|r| r.quantity() == 1)
+                .filter(|r| matches!(r.parse_value(&[0, 0]), Ok(Value::Integer(_))))
+            {
+                let range = register.documented_range().unwrap_or(0.0..=65_535.0);
+                match register.encode_value(&Value::Integer(n)) {
+                    Ok(bytes) => prop_assert_eq!(register.parse_value(&bytes).unwrap(), Value::Integer(n)),
+                    Err(err) => {
+                        prop_assert!(matches!(err, RenogyError::InvalidRegisterRange));
+                        prop_assert!(!range.contains(&(n as f32)), "{:?}: {} rejected", register, n);
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn raw_words_roundtrip(word in 0u16..=u16::MAX) {
+            // Every word a numeric register can report encodes back to itself, unless
+            // it is outside the documented range of a writable limit.
+            for register in fixed_registers().filter(|r| r.quantity() == 1) {
+                let value = register.parse_value(&word.to_be_bytes()).unwrap();
+                if !matches!(
+                    value,
+                    Value::Integer(_)
+                        | Value::ElectricPotential(_)
+                        | Value::ElectricCurrent(_)
+                        | Value::ThermodynamicTemperature(_)
+                ) {
+                    continue;
+                }
+                match register.encode_value(&value) {
+                    Ok(bytes) => prop_assert_eq!(bytes, word.to_be_bytes().to_vec(), "{:?}", register),
+                    Err(err) => {
+                        prop_assert!(matches!(err, RenogyError::InvalidRegisterRange));
+                        prop_assert!(register.documented_range().is_some(), "{:?}", register);
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,367 @@
+//! Retrying `Transport` decorator for flaky links.
+//!
+//! Reads are idempotent, so a read that fails with a CRC mismatch, a timeout or a
+//! "slave device busy" exception is retried with jittered exponential backoff. Writes
+//! and custom function codes are never retried: a write whose response was lost may
+//! already have taken effect.
+
+use crate::error::ModbusExceptionCode;
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::pdu::FunctionCode;
+use crate::transport::Transport;
+use crate::transport::TransportType;
+use async_trait::async_trait;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::registry::Registry;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; 0 disables retrying.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether a request with `function_code` that failed with `err` may be retried.
+    #[must_use]
+    pub fn should_retry(&self, function_code: FunctionCode, err: &RenogyError) -> bool {
+        !function_code.is_write_operation() && is_transient(err)
+    }
+
+    /// Delay before retry number `retry` (1-based): doubles each time up to
+    /// `max_backoff`, then jittered down by up to half so that several pollers sharing
+    /// a bus do not retry in lockstep.
+    #[must_use]
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exp = self
+            .initial_backoff
+            .saturating_mul(1 << retry.saturating_sub(1).min(16))
+            .min(self.max_backoff);
+        let permille = (RandomState::new().build_hasher().finish() % 1000) as u32;
+        exp / 2 + exp / 2 * permille / 1000
+    }
+}
+
+/// Errors worth retrying: corrupted frames, lost responses and a busy slave.
+#[must_use]
+pub fn is_transient(err: &RenogyError) -> bool {
+    matches!(
+        err,
+        RenogyError::CrcMismatch
+            | RenogyError::Timeout
+            | RenogyError::ModbusException(ModbusExceptionCode::SlaveDeviceBusy)
+    )
+}
+
+/// Retry counters, shareable with a Prometheus registry.
+#[derive(Clone, Debug, Default)]
+pub struct RetryStats {
+    /// Retries issued.
+    pub retries: Counter,
+    /// Requests that succeeded after at least one retry.
+    pub recovered: Counter,
+    /// Requests that still failed with a transient error after the last retry.
+    pub exhausted: Counter,
+}
+
+impl RetryStats {
+    pub fn register(&self, registry: &mut Registry) {
+        registry.register(
+            "renogy_transport_retries",
+            "Read requests retried after a transient transport error",
+            self.retries.clone(),
+        );
+        registry.register(
+            "renogy_transport_retry_recovered",
+            "Read requests that succeeded after retrying",
+            self.recovered.clone(),
+        );
+        registry.register(
+            "renogy_transport_retry_exhausted",
+            "Read requests that failed after exhausting retries",
+            self.exhausted.clone(),
+        );
+    }
+}
+
+/// Wraps any `Transport` (including `AnyTransport`) and retries transient read failures.
+pub struct RetryingTransport<T> {
+    inner: T,
+    policy: RetryPolicy,
+    stats: RetryStats,
+}
+
+impl<T: Transport> RetryingTransport<T> {
+    pub fn new(inner: T) -> Self {
+        Self::with_policy(inner, RetryPolicy::default())
+    }
+
+    pub fn with_policy(inner: T, policy: RetryPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            stats: RetryStats::default(),
+        }
+    }
+
+    /// Counters shared with this transport; clones observe the same values.
+    #[must_use]
+    pub fn stats(&self) -> RetryStats {
+        self.stats.clone()
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+#[async_trait]
+impl<T: Transport + Send> Transport for RetryingTransport<T> {
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        let mut retry = 0;
+        loop {
+            match self
+                .inner
+                .read_holding_registers(slave, addr, quantity)
+                .await
+            {
+                Ok(words) => {
+                    if retry > 0 {
+                        self.stats.recovered.inc();
+                    }
+                    return Ok(words);
+                }
+                Err(e)
+                    if self
+                        .policy
+                        .should_retry(FunctionCode::ReadHoldingRegisters, &e) =>
+                {
+                    if retry >= self.policy.max_retries {
+                        self.stats.exhausted.inc();
+                        return Err(e);
+                    }
+                    retry += 1;
+                    self.stats.retries.inc();
+                    let backoff = self.policy.backoff(retry);
+                    tracing::debug!(
+                        "Read {}+{} at 0x{:02X} failed ({}), retry {} in {:?}",
+                        addr,
+                        quantity,
+                        slave,
+                        e,
+                        retry,
+                        backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
+        self.inner.write_single_register(slave, addr, value).await
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        self.inner
+            .write_multiple_registers(slave, addr, values)
+            .await
+    }
+
+    async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
+        self.inner.send_custom(slave, function_code, data).await
+    }
+
+    fn transport_type(&self) -> TransportType {
+        self.inner.transport_type()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use super::RetryingTransport;
+    use crate::any_transport::AnyTransport;
+    use crate::emulator::EmulatedBattery;
+    use crate::emulator::Fault;
+    use crate::emulator::FaultyTransport;
+    use crate::error::ModbusExceptionCode;
+    use crate::error::RenogyError;
+    use crate::pdu::FunctionCode;
+    use crate::transport::Transport;
+    use crate::transport::TransportType;
+    use std::time::Duration;
+
+    /// A hook failing the first `failures` requests it sees with `error()`.
+    fn fail_first<T>(
+        mut failures: u32,
+        error: fn() -> RenogyError,
+    ) -> impl FnMut() -> Fault<T> + Send + 'static {
+        move || {
+            if failures == 0 {
+                return Fault::Pass;
+            }
+            failures -= 1;
+            Fault::Answer(Err(error()))
+        }
+    }
+
+    fn flaky_reads(failures: u32, error: fn() -> RenogyError) -> FaultyTransport<EmulatedBattery> {
+        let mut fail = fail_first(failures, error);
+        FaultyTransport::new(EmulatedBattery::lfp_12v(0x30)).on_read(move |_, _, _| fail())
+    }
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_transient_read_errors() {
+        let mut transport = RetryingTransport::with_policy(
+            flaky_reads(2, || RenogyError::CrcMismatch),
+            fast_policy(2),
+        );
+        let stats = transport.stats();
+
+        assert!(
+            transport
+                .read_holding_registers(0x30, 5000, 1)
+                .await
+                .is_ok()
+        );
+        assert_eq!(stats.retries.get(), 2);
+        assert_eq!(stats.recovered.get(), 1);
+        assert_eq!(stats.exhausted.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let mut transport =
+            RetryingTransport::with_policy(flaky_reads(5, || RenogyError::Timeout), fast_policy(2));
+        let stats = transport.stats();
+
+        assert!(matches!(
+            transport.read_holding_registers(0x30, 5000, 1).await,
+            Err(RenogyError::Timeout)
+        ));
+        assert_eq!(transport.inner_mut().requests().len(), 3);
+        assert_eq!(stats.exhausted.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_busy_slave_but_not_other_exceptions() {
+        let busy = || RenogyError::ModbusException(ModbusExceptionCode::SlaveDeviceBusy);
+        let mut transport = RetryingTransport::with_policy(flaky_reads(1, busy), fast_policy(2));
+        assert!(
+            transport
+                .read_holding_registers(0x30, 5000, 1)
+                .await
+                .is_ok()
+        );
+
+        let illegal = || RenogyError::ModbusException(ModbusExceptionCode::IllegalDataAddress);
+        let mut transport = RetryingTransport::with_policy(flaky_reads(1, illegal), fast_policy(2));
+        assert!(
+            transport
+                .read_holding_registers(0x30, 5000, 1)
+                .await
+                .is_err()
+        );
+        assert_eq!(transport.inner_mut().requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn never_retries_writes_or_custom_functions() {
+        let mut fail = fail_first(1, || RenogyError::Timeout);
+        let inner =
+            FaultyTransport::new(EmulatedBattery::lfp_12v(0x30)).on_write(move |_, _, _| fail());
+        let mut transport = RetryingTransport::with_policy(inner, fast_policy(2));
+        assert!(
+            transport
+                .write_single_register(0x30, 5223, 1)
+                .await
+                .is_err()
+        );
+        assert!(
+            transport
+                .write_multiple_registers(0x30, 5223, &[1])
+                .await
+                .is_ok()
+        );
+
+        let mut fail = fail_first(1, || RenogyError::Timeout);
+        let inner =
+            FaultyTransport::new(EmulatedBattery::lfp_12v(0x30)).on_custom(move |_, _, _| fail());
+        let mut transport = RetryingTransport::with_policy(inner, fast_policy(2));
+        assert!(
+            transport
+                .send_custom(0x30, FunctionCode::ClearHistory as u8, &[0, 0, 0, 1])
+                .await
+                .is_err()
+        );
+        assert_eq!(transport.inner_mut().requests().len(), 1);
+        assert_eq!(transport.stats().retries.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn wraps_any_transport() {
+        let inner = AnyTransport::new(flaky_reads(1, || RenogyError::CrcMismatch));
+        let mut transport = RetryingTransport::with_policy(inner, fast_policy(1));
+        assert!(
+            transport
+                .read_holding_registers(0x30, 5000, 1)
+                .await
+                .is_ok()
+        );
+        assert_eq!(transport.transport_type(), TransportType::Serial);
+    }
+
+    #[test]
+    fn backoff_is_capped_and_jittered() {
+        let policy = RetryPolicy {
+            max_retries: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(400),
+        };
+        for retry in 1..=10 {
+            let backoff = policy.backoff(retry);
+            let cap = Duration::from_millis(100 << (retry - 1).min(2));
+            assert!(backoff <= cap && backoff >= cap / 2, "{retry}: {backoff:?}");
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Modbus RTU framing for byte streams whose chunk boundaries mean nothing (TCP
+//! tunnels, serial ports, BLE notifications).
+//!
+//! RTU frames carry no length header, so the frame length is derived from the function
+//! code (and the byte count for reads). CRC checking is left to `Pdu::deserialize`.
+
+use crate::pdu::FunctionCode;
+use crate::pdu::Pdu;
+
+/// Address + function code + CRC.
+const FRAME_OVERHEAD: usize = 4;
+/// Largest RTU frame the Modbus spec allows.
+const MAX_FRAME_LEN: usize = 256;
+
+/// Length of the response frame at the front of `buf`, or `None` until enough of it has
+/// arrived to tell. `Some(0)` marks a function code no BMS response can carry.
+#[must_use]
+pub fn response_len(buf: &[u8]) -> Option<usize> {
+    let fc = *buf.get(1)?;
+    if fc & 0x80 != 0 {
+        return Some(FRAME_OVERHEAD + 1);
+    }
+    match FunctionCode::from_u8(fc) {
+        Some(FunctionCode::ReadHoldingRegisters) => {
+            let byte_count = *buf.get(2)? as usize;
+            Some(FRAME_OVERHEAD + 1 + byte_count)
+        }
+        Some(
+            FunctionCode::WriteSingleRegister
+            | FunctionCode::WriteMultipleRegisters
+            | FunctionCode::RestoreFactoryDefault
+            | FunctionCode::ClearHistory,
+        ) => Some(FRAME_OVERHEAD + 4),
+        None => Some(0),
+    }
+}
+
+/// Length of the request frame at the front of `buf`, for the server side of a link.
+#[must_use]
+pub fn request_len(buf: &[u8]) -> Option<usize> {
+    match FunctionCode::from_u8(*buf.get(1)?) {
+        Some(FunctionCode::WriteMultipleRegisters) => {
+            let byte_count = *buf.get(6)? as usize;
+            Some(FRAME_OVERHEAD + 5 + byte_count)
+        }
+        Some(_) => Some(FRAME_OVERHEAD + 4),
+        None => Some(0),
+    }
+}
+
+/// True if `frame` (a normal or exception response) comes from the slave `request` was
+/// sent to and answers it: same function code, a read's byte count matching the
+/// quantity asked for, a write echoing its address and value (or quantity). A late
+/// answer to an earlier request to the same slave is rejected unless it is identical
+/// in those fields.
+#[must_use]
+pub fn is_response_to(frame: &[u8], request: &Pdu) -> bool {
+    if frame.len() < 2
+        || frame[0] != request.address
+        || frame[1] & 0x7F != request.function_code as u8
+    {
+        return false;
+    }
+    if frame[1] & 0x80 != 0 {
+        return true;
+    }
+    match request.function_code {
+        FunctionCode::ReadHoldingRegisters => match (frame.get(2), request.payload.get(2..4)) {
+            (Some(&byte_count), Some(quantity)) => {
+                usize::from(byte_count)
+                    == 2 * usize::from(u16::from_be_bytes([quantity[0], quantity[1]]))
+            }
+            _ => false,
+        },
+        FunctionCode::WriteSingleRegister | FunctionCode::WriteMultipleRegisters => {
+            frame.get(2..6) == request.payload.get(..4)
+        }
+        FunctionCode::RestoreFactoryDefault | FunctionCode::ClearHistory => true,
+    }
+}
+
+/// Reassembles RTU frames from arbitrarily split chunks.
+#[derive(Debug)]
+pub struct FrameAssembler {
+    buf: Vec<u8>,
+    frame_len: fn(&[u8]) -> Option<usize>,
+}
+
+impl Default for FrameAssembler {
+    fn default() -> Self {
+        Self::responses()
+    }
+}
+
+impl FrameAssembler {
+    /// Assembler for the client side: frames are slave responses.
+    #[must_use]
+    pub fn responses() -> Self {
+        Self {
+            buf: Vec::new(),
+            frame_len: response_len,
+        }
+    }
+
+    /// Assembler for the server side: frames are master requests.
+    #[must_use]
+    pub fn requests() -> Self {
+        Self {
+            buf: Vec::new(),
+            frame_len: request_len,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Drop any partial frame, e.g. before sending a new request.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Split the next complete frame (CRC included) off the buffer.
+    ///
+    /// Bytes that cannot start a frame are skipped one at a time so the stream can
+    /// resynchronise after line noise.
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let len = (self.frame_len)(&self.buf)?;
+            if !(FRAME_OVERHEAD + 1..=MAX_FRAME_LEN).contains(&len) {
+                self.buf.remove(0);
+                continue;
+            }
+            if self.buf.len() < len {
+                return None;
+            }
+            return Some(self.buf.drain(..len).collect());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FrameAssembler;
+    use super::is_response_to;
+    use super::response_len;
+    use crate::pdu::FunctionCode;
+    use crate::pdu::Pdu;
+
+    fn read_response() -> Vec<u8> {
+        Pdu::new(
+            0x30,
+            FunctionCode::ReadHoldingRegisters,
+            vec![0x04, 0x00, 0x21, 0x00, 0x22],
+        )
+        .serialize()
+    }
+
+    #[test]
+    fn response_len_uses_byte_count() {
+        let frame = read_response();
+        assert_eq!(response_len(&frame[..2]), None);
+        assert_eq!(response_len(&frame), Some(frame.len()));
+        assert_eq!(response_len(&[0x30, 0x83, 0x02]), Some(5));
+    }
+
+    #[test]
+    fn assembler_joins_fragments() {
+        let frame = read_response();
+        let mut assembler = FrameAssembler::responses();
+        assembler.push(&frame[..3]);
+        assert_eq!(assembler.next_frame(), None);
+        assembler.push(&frame[3..]);
+        assert_eq!(assembler.next_frame(), Some(frame));
+        assert!(assembler.is_empty());
+    }
+
+    #[test]
+    fn assembler_splits_back_to_back_frames() {
+        let frame = read_response();
+        let mut assembler = FrameAssembler::responses();
+        assembler.push(&[frame.clone(), frame.clone()].concat());
+        assert_eq!(assembler.next_frame(), Some(frame.clone()));
+        assert_eq!(assembler.next_frame(), Some(frame));
+        assert_eq!(assembler.next_frame(), None);
+    }
+
+    #[test]
+    fn assembler_skips_garbage() {
+        let frame = read_response();
+        let mut assembler = FrameAssembler::responses();
+        assembler.push(&[0x00, 0x42]);
+        assembler.push(&frame);
+        assert_eq!(assembler.next_frame(), Some(frame));
+    }
+
+    #[test]
+    fn matches_request_address_and_function() {
+        let request = Pdu::read_holding_registers(0x30, 5000, 2);
+        assert!(is_response_to(&read_response(), &request));
+        assert!(is_response_to(&[0x30, 0x83, 0x02], &request));
+        assert!(!is_response_to(
+            &read_response(),
+            &Pdu::read_holding_registers(0x31, 5000, 2)
+        ));
+    }
+
+    #[test]
+    fn rejects_late_response_to_another_read() {
+        // A 2-register answer cannot satisfy a later 1- or 3-register read.
+        for quantity in [1, 3] {
+            let request = Pdu::read_holding_registers(0x30, 5000, quantity);
+            assert!(!is_response_to(&read_response(), &request), "{quantity}");
+        }
+    }
+
+    #[test]
+    fn write_responses_must_echo_the_request() {
+        let request = Pdu::write_single_register(0x30, 5224, 0xA5A5);
+        assert!(is_response_to(&request.serialize(), &request));
+        let earlier = Pdu::write_single_register(0x30, 5224, 0x5A5A);
+        assert!(!is_response_to(&earlier.serialize(), &request));
+
+        let request = Pdu::write_multiple_registers(0x30, 5200, &[37, 36]);
+        let echo = Pdu::new(
+            0x30,
+            FunctionCode::WriteMultipleRegisters,
+            vec![0x14, 0x50, 0x00, 0x02],
+        );
+        assert!(is_response_to(&echo.serialize(), &request));
+        let other = Pdu::new(
+            0x30,
+            FunctionCode::WriteMultipleRegisters,
+            vec![0x14, 0x50, 0x00, 0x03],
+        );
+        assert!(!is_response_to(&other.serialize(), &request));
+    }
+}
@@ -0,0 +1,442 @@
+//! Native Modbus RTU over a serial port.
+//!
+//! An alternative to the `tokio-modbus` based `SerialTransport` that speaks RTU
+//! directly: requests go out as `Pdu::serialize()` frames and responses are reassembled
+//! by `rtu::FrameAssembler` and CRC-checked by `Pdu::deserialize`, the same codec the
+//! BT-2 and RTU-over-TCP transports use. This keeps custom function codes (0x78/0x79)
+//! on the same path as register reads.
+//!
+//! Framing follows the RTU silent interval: the line must be quiet for 3.5 character
+//! times before a request is sent, and a partial response followed by that much silence
+//! is dropped as a broken frame.
+//!
+//! Unlike `SerialTransport`, this framer can drive an RS-485 transceiver's direction
+//! from RTS (`SerialConfig::rts_direction`), since it knows when each frame ends.
+
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::pdu::FunctionCode;
+use crate::pdu::Pdu;
+use crate::rtu::FrameAssembler;
+use crate::rtu::is_response_to;
+use crate::serial::SerialConfig;
+use crate::transport::Transport;
+use crate::transport::TransportType;
+use async_trait::async_trait;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::time::Instant;
+use tokio::time::timeout;
+use tokio_serial::SerialPort;
+use tokio_serial::SerialStream;
+
+/// Bits on the wire per RTU character: start, 8 data, parity (or second stop), stop.
+const BITS_PER_CHAR: u32 = 11;
+
+/// Fixed inter-frame delay the Modbus spec prescribes above 19200 baud.
+const HIGH_SPEED_SILENT_INTERVAL: Duration = Duration::from_micros(1750);
+
+/// Shortest gap treated as the end of a partial frame. USB serial adapters deliver
+/// bytes in bursts (FTDI's latency timer defaults to 16 ms), so a strict 3.5-character
+/// gap would split healthy responses at 9600 baud.
+const MIN_FRAME_GAP: Duration = Duration::from_millis(20);
+
+/// Time for one character at `baud_rate`.
+#[must_use]
+pub fn char_time(baud_rate: u32) -> Duration {
+    Duration::from_secs_f64(f64::from(BITS_PER_CHAR) / f64::from(baud_rate.max(1)))
+}
+
+/// The 3.5-character silent interval separating RTU frames at `baud_rate`.
+#[must_use]
+pub fn silent_interval(baud_rate: u32) -> Duration {
+    if baud_rate > 19_200 {
+        HIGH_SPEED_SILENT_INTERVAL
+    } else {
+        char_time(baud_rate).mul_f64(3.5)
+    }
+}
+
+/// Modbus RTU spoken directly over a serial port (or any byte stream, for tests).
+///
+/// # Example
+///
+/// ```ignore
+/// use renogy::rtu_serial::RtuSerialTransport;
+/// use renogy::serial::SerialConfig;
+/// use renogy::transport::Transport;
+///
+/// let mut transport = RtuSerialTransport::open("/dev/ttyUSB0", &SerialConfig::default())?;
+/// let echo = transport.send_custom(0x30, 0x79, &[0x00, 0x00, 0x00, 0x00]).await?;
+/// ```
+pub struct RtuSerialTransport<S = SerialStream> {
+    port: S,
+    assembler: FrameAssembler,
+    char_time: Duration,
+    silent_interval: Duration,
+    timeout: Duration,
+    inter_request_delay: Duration,
+    /// When the line last carried a byte in either direction.
+    last_activity: Instant,
+    /// Sets RTS to switch an RS-485 transceiver into (true) or out of transmit.
+    rts: Option<fn(&mut S, bool) -> std::io::Result<()>>,
+}
+
+impl<S> std::fmt::Debug for RtuSerialTransport<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RtuSerialTransport")
+            .field("silent_interval", &self.silent_interval)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RtuSerialTransport<SerialStream> {
+    /// Open `path` (e.g. "/dev/ttyUSB0") with the given line settings.
+    pub fn open(path: &str, config: &SerialConfig) -> Result<Self> {
+        let port = config.open(path)?;
+        let mut transport = Self::new(port, config);
+        if config.rts_direction {
+            let set_rts: fn(&mut SerialStream, bool) -> std::io::Result<()> = |port, level| {
+                port.write_request_to_send(level)
+                    .map_err(|e| IoError::other(e.to_string()))
+            };
+            set_rts(&mut transport.port, false)?;
+            transport.rts = Some(set_rts);
+        }
+        Ok(transport)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> RtuSerialTransport<S> {
+    /// Speak RTU over an already opened port. Only the timing fields of `config` are
+    /// used; line settings are the caller's business.
+    pub fn new(port: S, config: &SerialConfig) -> Self {
+        Self {
+            port,
+            assembler: FrameAssembler::responses(),
+            char_time: char_time(config.baud_rate),
+            silent_interval: silent_interval(config.baud_rate),
+            timeout: config.timeout,
+            inter_request_delay: config.inter_request_delay,
+            last_activity: Instant::now(),
+            rts: None,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    async fn send_pdu(&mut self, pdu: &Pdu) -> Result<Pdu> {
+        let quiet = self.silent_interval.max(self.inter_request_delay);
+        tokio::time::sleep_until(self.last_activity + quiet).await;
+
+        // Anything still buffered belongs to an earlier exchange.
+        self.assembler.clear();
+        let frame = pdu.serialize();
+        if let Some(set_rts) = self.rts {
+            set_rts(&mut self.port, true)?;
+        }
+        self.port.write_all(&frame).await?;
+        self.port.flush().await?;
+        // The frame is still shifting out of the UART when write returns.
+        self.last_activity = Instant::now() + self.char_time * frame.len() as u32;
+        if let Some(set_rts) = self.rts {
+            // The slave waits a silent interval before answering, so releasing the
+            // bus a timer tick late is harmless; releasing it early truncates the frame.
+            tokio::time::sleep_until(self.last_activity).await;
+            set_rts(&mut self.port, false)?;
+        }
+
+        let frame = timeout(self.timeout, self.receive(pdu))
+            .await
+            .map_err(|_| RenogyError::Timeout)??;
+        Pdu::deserialize(&frame)
+    }
+
+    /// Read until a frame answering `request` arrives. Frames for other requests are
+    /// discarded, as is a partial frame followed by a silent interval.
+    async fn receive(&mut self, request: &Pdu) -> Result<Vec<u8>> {
+        let frame_gap = self.silent_interval.max(MIN_FRAME_GAP);
+        loop {
+            while let Some(frame) = self.assembler.next_frame() {
+                if is_response_to(&frame, request) {
+                    return Ok(frame);
+                }
+                tracing::debug!("Discarding unexpected RTU frame {:02X?}", frame);
+            }
+
+            let mut chunk = [0u8; 256];
+            let n = if self.assembler.is_empty() {
+                self.port.read(&mut chunk).await?
+            } else {
+                match timeout(frame_gap, self.port.read(&mut chunk)).await {
+                    Ok(read) => read?,
+                    Err(_) => {
+                        tracing::debug!("Discarding RTU frame cut short by a silent interval");
+                        self.assembler.clear();
+                        continue;
+                    }
+                }
+            };
+            if n == 0 {
+                return Err(RenogyError::Io(IoError::new(
+                    ErrorKind::UnexpectedEof,
+                    "serial port closed",
+                )));
+            }
+            self.last_activity = Instant::now();
+            self.assembler.push(&chunk[..n]);
+        }
+    }
+}
+
+#[async_trait]
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Transport for RtuSerialTransport<S> {
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        self.send_pdu(&Pdu::read_holding_registers(slave, addr, quantity))
+            .await?
+            .register_values(quantity)
+    }
+
+    async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
+        self.send_pdu(&Pdu::write_single_register(slave, addr, value))
+            .await?;
+        Ok(())
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        self.send_pdu(&Pdu::write_multiple_registers(slave, addr, values))
+            .await?;
+        Ok(())
+    }
+
+    async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let fc = FunctionCode::from_u8(function_code).ok_or(RenogyError::InvalidData)?;
+        Ok(self
+            .send_pdu(&Pdu::new(slave, fc, data.to_vec()))
+            .await?
+            .payload)
+    }
+
+    fn transport_type(&self) -> TransportType {
+        TransportType::Serial
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RtuSerialTransport;
+    use super::silent_interval;
+    use crate::emulator::EmulatedBattery;
+    use crate::error::ModbusExceptionCode;
+    use crate::error::RenogyError;
+    use crate::pdu::FunctionCode;
+    use crate::pdu::Pdu;
+    use crate::query::query_battery;
+    use crate::registers::Register;
+    use crate::rtu::FrameAssembler;
+    use crate::serial::SerialConfig;
+    use crate::transport::Transport;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::io::DuplexStream;
+
+    /// What the test bus answers a factory reset (0x78) with, whatever was sent.
+    const FACTORY_RESET_ACK: [u8; 4] = [0x00, 0x00, 0x00, 0x01];
+
+    /// An exception response frame, CRC included.
+    fn exception_frame(address: u8, function_code: u8, code: u8) -> Vec<u8> {
+        let mut frame = vec![address, function_code | 0x80, code];
+        let crc = crc::Crc::<u16>::new(&crc::CRC_16_MODBUS).checksum(&frame);
+        frame.extend_from_slice(&crc.to_le_bytes());
+        frame
+    }
+
+    /// The bus side of a serial link with an `EmulatedBattery` on it. A factory reset
+    /// gets the fixed `FACTORY_RESET_ACK` rather than an echo, and a history clear an
+    /// Illegal Function exception, so custom replies are framed by their own length;
+    /// `noise` goes out ahead of every response followed by a silent interval, like a
+    /// frame cut off mid-way.
+    fn spawn_bus(bms: EmulatedBattery, noise: Vec<u8>) -> RtuSerialTransport<DuplexStream> {
+        spawn_bus_with(bms, noise, &SerialConfig::default())
+    }
+
+    fn spawn_bus_with(
+        mut bms: EmulatedBattery,
+        noise: Vec<u8>,
+        config: &SerialConfig,
+    ) -> RtuSerialTransport<DuplexStream> {
+        let (client, mut bus) = tokio::io::duplex(512);
+        tokio::spawn(async move {
+            let mut requests = FrameAssembler::requests();
+            loop {
+                let mut chunk = [0u8; 256];
+                let n = bus.read(&mut chunk).await.unwrap();
+                if n == 0 {
+                    return;
+                }
+                requests.push(&chunk[..n]);
+                while let Some(frame) = requests.next_frame() {
+                    let request = Pdu::deserialize(&frame).unwrap();
+                    let response = match request.function_code {
+                        FunctionCode::RestoreFactoryDefault => Pdu::new(
+                            request.address,
+                            request.function_code,
+                            FACTORY_RESET_ACK.to_vec(),
+                        )
+                        .serialize(),
+                        FunctionCode::ClearHistory => {
+                            exception_frame(request.address, request.function_code as u8, 0x01)
+                        }
+                        _ => match bms.respond(&request) {
+                            Ok(response) => response.serialize(),
+                            Err(_) => continue,
+                        },
+                    };
+                    if !noise.is_empty() {
+                        bus.write_all(&noise).await.unwrap();
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                    }
+                    // Yield rather than sleep between pieces: on a loaded machine a
+                    // timer can overshoot the frame gap and split the response.
+                    for piece in response.chunks(3) {
+                        bus.write_all(piece).await.unwrap();
+                        tokio::task::yield_now().await;
+                    }
+                }
+            }
+        });
+        RtuSerialTransport::new(client, config)
+    }
+
+    #[test]
+    fn silent_interval_scales_with_baud_rate() {
+        assert_eq!(silent_interval(9600).as_micros(), 4010);
+        assert_eq!(silent_interval(115_200), Duration::from_micros(1750));
+    }
+
+    #[tokio::test]
+    async fn query_battery_over_native_rtu() {
+        let mut transport = spawn_bus(EmulatedBattery::lfp_12v(0x01), vec![]);
+        let info = query_battery(&mut transport, 0x01).await.expect("info");
+
+        assert_eq!(info.serial, "SN0001");
+        assert_eq!(info.cell_voltages.len(), 4);
+        assert!((info.module_voltage - 13.2).abs() < 1e-2);
+    }
+
+    #[tokio::test]
+    async fn custom_function_codes_share_the_codec() {
+        let mut transport = spawn_bus(EmulatedBattery::lfp_12v(0x01), vec![]);
+        let ack = transport
+            .send_custom(0x01, 0x78, &[0x00, 0x00, 0x00, 0x00])
+            .await
+            .unwrap();
+        assert_eq!(ack, FACTORY_RESET_ACK);
+
+        let err = transport
+            .send_custom(0x01, 0x79, &[0x00, 0x00, 0x00, 0x01])
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RenogyError::ModbusException(ModbusExceptionCode::IllegalFunction)
+        ));
+
+        // Both replies were consumed whole: the next request is still in step.
+        let regs = transport
+            .read_holding_registers(0x01, Register::CellCount.address(), 1)
+            .await
+            .unwrap();
+        assert_eq!(regs, [4]);
+    }
+
+    #[test]
+    fn framer_splits_back_to_back_custom_replies() {
+        let ack = Pdu::new(
+            0x01,
+            FunctionCode::RestoreFactoryDefault,
+            FACTORY_RESET_ACK.to_vec(),
+        )
+        .serialize();
+        let exception = exception_frame(0x01, 0x79, 0x01);
+        let read = Pdu::new(
+            0x01,
+            FunctionCode::ReadHoldingRegisters,
+            vec![0x02, 0x00, 0x04],
+        )
+        .serialize();
+        assert_eq!(ack.len(), 8);
+
+        let mut assembler = FrameAssembler::responses();
+        assembler.push(&[ack.clone(), exception.clone(), read.clone()].concat());
+        assert_eq!(assembler.next_frame(), Some(ack));
+        assert_eq!(assembler.next_frame(), Some(exception));
+        assert_eq!(assembler.next_frame(), Some(read));
+        assert_eq!(assembler.next_frame(), None);
+    }
+
+    #[tokio::test]
+    async fn partial_frame_before_silence_is_dropped() {
+        // The first half of a read response, as if the line dropped out mid-frame.
+        let partial = Pdu::new(
+            0x01,
+            FunctionCode::ReadHoldingRegisters,
+            vec![0x04, 0x00, 0x21, 0x00, 0x22],
+        )
+        .serialize()[..4]
+            .to_vec();
+        let mut transport = spawn_bus(EmulatedBattery::lfp_12v(0x01), partial);
+        let regs = transport
+            .read_holding_registers(0x01, Register::CellCount.address(), 1)
+            .await
+            .unwrap();
+        assert_eq!(regs, [4]);
+    }
+
+    #[tokio::test]
+    async fn inter_request_delay_spaces_requests() {
+        let config = SerialConfig {
+            inter_request_delay: Duration::from_millis(100),
+            ..SerialConfig::default()
+        };
+        let mut transport = spawn_bus_with(EmulatedBattery::lfp_12v(0x01), vec![], &config);
+        let start = tokio::time::Instant::now();
+        for _ in 0..3 {
+            transport
+                .read_holding_registers(0x01, Register::CellCount.address(), 1)
+                .await
+                .unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn silent_slave_times_out() {
+        let mut transport = spawn_bus(EmulatedBattery::lfp_12v(0x01), vec![]);
+        transport.set_timeout(Duration::from_millis(100));
+        let err = transport
+            .read_holding_registers(0x02, 5000, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RenogyError::Timeout));
+    }
+}
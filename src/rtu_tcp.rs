@@ -0,0 +1,234 @@
+//! RTU-over-TCP transport for transparent serial servers.
+//!
+//! Cheap RS-485-to-Ethernet bridges (USR-TCP232 style) tunnel raw bytes between a TCP
+//! socket and the bus. Requests go out as `Pdu::serialize()` frames, exactly as over
+//! BT-2, and responses are reassembled from the stream and CRC-checked by
+//! `Pdu::deserialize`.
+
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::pdu::FunctionCode;
+use crate::pdu::Pdu;
+use crate::rtu::FrameAssembler;
+use crate::rtu::is_response_to;
+use crate::transport::Transport;
+use crate::transport::TransportType;
+use async_trait::async_trait;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::net::ToSocketAddrs;
+use tokio::time::timeout;
+
+/// Common default listening port of transparent serial servers.
+pub const DEFAULT_RTU_TCP_PORT: u16 = 8899;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Modbus RTU tunnelled over a plain TCP stream.
+pub struct RtuOverTcpTransport {
+    stream: TcpStream,
+    assembler: FrameAssembler,
+    timeout: Duration,
+}
+
+impl std::fmt::Debug for RtuOverTcpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RtuOverTcpTransport")
+            .field("peer", &self.stream.peer_addr().ok())
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RtuOverTcpTransport {
+    /// Connect to a transparent serial server (e.g. "192.168.1.60:8899").
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = timeout(DEFAULT_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| RenogyError::Timeout)??;
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream,
+            assembler: FrameAssembler::responses(),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    async fn send_pdu(&mut self, pdu: &Pdu) -> Result<Pdu> {
+        self.assembler.clear();
+        self.stream.write_all(&pdu.serialize()).await?;
+
+        let frame = timeout(self.timeout, self.receive(pdu))
+            .await
+            .map_err(|_| RenogyError::Timeout)??;
+        Pdu::deserialize(&frame)
+    }
+
+    /// Read until a frame answering `request` arrives, discarding late responses to
+    /// earlier requests. Cancel-safe: partial frames stay in the assembler.
+    async fn receive(&mut self, request: &Pdu) -> Result<Vec<u8>> {
+        loop {
+            while let Some(frame) = self.assembler.next_frame() {
+                if is_response_to(&frame, request) {
+                    return Ok(frame);
+                }
+                tracing::debug!("Discarding unexpected RTU frame {:02X?}", frame);
+            }
+
+            let mut chunk = [0u8; 256];
+            let n = self.stream.read(&mut chunk).await?;
+            if n == 0 {
+                return Err(RenogyError::Io(IoError::new(
+                    ErrorKind::UnexpectedEof,
+                    "serial server closed the connection",
+                )));
+            }
+            self.assembler.push(&chunk[..n]);
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for RtuOverTcpTransport {
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        self.send_pdu(&Pdu::read_holding_registers(slave, addr, quantity))
+            .await?
+            .register_values(quantity)
+    }
+
+    async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
+        self.send_pdu(&Pdu::write_single_register(slave, addr, value))
+            .await?;
+        Ok(())
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        self.send_pdu(&Pdu::write_multiple_registers(slave, addr, values))
+            .await?;
+        Ok(())
+    }
+
+    async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let fc = FunctionCode::from_u8(function_code).ok_or(RenogyError::InvalidData)?;
+        Ok(self
+            .send_pdu(&Pdu::new(slave, fc, data.to_vec()))
+            .await?
+            .payload)
+    }
+
+    fn transport_type(&self) -> TransportType {
+        TransportType::RtuOverTcp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RtuOverTcpTransport;
+    use crate::emulator::EmulatedBattery;
+    use crate::error::RenogyError;
+    use crate::pdu::Pdu;
+    use crate::query::query_battery;
+    use crate::registers::Register;
+    use crate::rtu::FrameAssembler;
+    use crate::transport::Transport;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    /// Transparent serial server with an `EmulatedBattery` on the far end. Responses
+    /// are written a few bytes at a time to exercise reassembly; `noise` is sent ahead
+    /// of every response to mimic a late frame from an earlier request.
+    async fn spawn_server(mut bms: EmulatedBattery, noise: Vec<u8>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            socket.set_nodelay(true).unwrap();
+            let mut requests = FrameAssembler::requests();
+            loop {
+                let mut chunk = [0u8; 256];
+                let n = socket.read(&mut chunk).await.unwrap();
+                if n == 0 {
+                    return;
+                }
+                requests.push(&chunk[..n]);
+                while let Some(frame) = requests.next_frame() {
+                    let request = Pdu::deserialize(&frame).unwrap();
+                    let Ok(response) = bms.respond(&request) else {
+                        continue;
+                    };
+                    socket.write_all(&noise).await.unwrap();
+                    for piece in response.serialize().chunks(3) {
+                        socket.write_all(piece).await.unwrap();
+                        socket.flush().await.unwrap();
+                    }
+                }
+            }
+        });
+        local
+    }
+
+    #[tokio::test]
+    async fn query_battery_over_rtu_tcp() {
+        let mut transport = RtuOverTcpTransport::connect(
+            spawn_server(EmulatedBattery::lfp_12v(0x01), vec![]).await,
+        )
+        .await
+        .unwrap();
+        let info = query_battery(&mut transport, 0x01).await.expect("info");
+
+        assert_eq!(info.serial, "SN0001");
+        assert_eq!(info.cell_voltages.len(), 4);
+        assert!((info.module_voltage - 13.2).abs() < 1e-2);
+    }
+
+    #[tokio::test]
+    async fn stale_frames_from_other_slaves_are_skipped() {
+        let stale = Pdu::write_single_register(0x02, 5223, 1).serialize();
+        let mut transport =
+            RtuOverTcpTransport::connect(spawn_server(EmulatedBattery::lfp_12v(0x01), stale).await)
+                .await
+                .unwrap();
+        let regs = transport
+            .read_holding_registers(0x01, Register::CellCount.address(), 1)
+            .await
+            .unwrap();
+        assert_eq!(regs, [4]);
+    }
+
+    #[tokio::test]
+    async fn silent_slave_times_out() {
+        let mut transport = RtuOverTcpTransport::connect(
+            spawn_server(EmulatedBattery::lfp_12v(0x01), vec![]).await,
+        )
+        .await
+        .unwrap();
+        transport.set_timeout(Duration::from_millis(100));
+        let err = transport
+            .read_holding_registers(0x02, 5000, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, RenogyError::Timeout));
+    }
+}
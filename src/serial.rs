@@ -1,7 +1,8 @@
 //! Serial/RS-485 transport for Modbus RTU communication.
 //!
 //! This module provides a serial transport that implements the `Transport` trait,
-//! using `tokio-modbus` for the underlying Modbus RTU communication.
+//! using `tokio-modbus` for the underlying Modbus RTU communication, and the
+//! `SerialConfig` line settings shared with the native `rtu_serial` framer.
 
 use crate::error::RenogyError;
 use crate::error::Result;
@@ -10,17 +11,115 @@ use crate::transport::TransportType;
 use async_trait::async_trait;
 use std::io::Error as IoError;
 use std::io::ErrorKind;
+use std::time::Duration;
+use tokio::time::Instant;
+use tokio::time::error::Elapsed;
+use tokio::time::timeout;
 use tokio_modbus::client::Client;
 use tokio_modbus::client::Context;
 use tokio_modbus::client::Reader;
 use tokio_modbus::client::Writer;
 use tokio_modbus::slave::Slave;
 use tokio_modbus::slave::SlaveContext;
+use tokio_serial::DataBits;
+use tokio_serial::Parity;
 use tokio_serial::SerialPortBuilderExt;
+use tokio_serial::SerialStream;
+use tokio_serial::StopBits;
 
 /// Default baud rate for Renogy BMS communication
 const DEFAULT_BAUD_RATE: u32 = 9600;
 
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Serial line settings. The default is 9600 8N1, as Renogy batteries ship.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialConfig {
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    /// How long to wait for a response before giving up with `RenogyError::Timeout`.
+    pub timeout: Duration,
+    /// Minimum quiet time between the end of one exchange and the next request, for
+    /// slaves that need to settle before they listen again.
+    pub inter_request_delay: Duration,
+    /// Let the kernel UART driver switch the RS-485 transceiver (Linux `TIOCSRS485`).
+    pub rs485: bool,
+    /// Switch the transceiver from user space by raising RTS while transmitting, for
+    /// adapters that wire RTS to DE/RE without driver support. Only the native RTU
+    /// framer controls RTS.
+    pub rts_direction: bool,
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate: DEFAULT_BAUD_RATE,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            timeout: DEFAULT_TIMEOUT,
+            inter_request_delay: Duration::ZERO,
+            rs485: false,
+            rts_direction: false,
+        }
+    }
+}
+
+impl SerialConfig {
+    /// Open `path` with these line settings, switching on kernel RS-485 mode if asked.
+    pub fn open(&self, path: &str) -> Result<SerialStream> {
+        let port = tokio_serial::new(path, self.baud_rate)
+            .data_bits(self.data_bits)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .open_native_async()
+            .map_err(|e| RenogyError::Io(IoError::other(e.to_string())))?;
+        if self.rs485 {
+            enable_rs485(&port)?;
+        }
+        Ok(port)
+    }
+}
+
+/// Put the UART into RS-485 mode so the driver asserts RTS (DE) while sending.
+#[cfg(target_os = "linux")]
+fn enable_rs485(port: &SerialStream) -> Result<()> {
+    use std::os::fd::AsRawFd;
+
+    const SER_RS485_ENABLED: u32 = 1 << 0;
+    const SER_RS485_RTS_ON_SEND: u32 = 1 << 1;
+
+    /// `struct serial_rs485` from `<linux/serial.h>`.
+    #[repr(C)]
+    struct SerialRs485 {
+        flags: u32,
+        delay_rts_before_send: u32,
+        delay_rts_after_send: u32,
+        padding: [u32; 5],
+    }
+
+    let config = SerialRs485 {
+        flags: SER_RS485_ENABLED | SER_RS485_RTS_ON_SEND,
+        delay_rts_before_send: 0,
+        delay_rts_after_send: 0,
+        padding: [0; 5],
+    };
+    // SAFETY: the fd is open for the lifetime of `port`, and TIOCSRS485 only reads a
+    // `struct serial_rs485` through the pointer during the call.
+    let rc = unsafe { libc::ioctl(port.as_raw_fd(), libc::TIOCSRS485, &config) };
+    if rc < 0 {
+        return Err(RenogyError::Io(IoError::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enable_rs485(_port: &SerialStream) -> Result<()> {
+    Err(RenogyError::UnsupportedOperation)
+}
+
 /// Serial transport for Modbus RTU communication.
 ///
 /// Wraps `tokio-modbus` to implement our `Transport` trait.
@@ -36,17 +135,22 @@ const DEFAULT_BAUD_RATE: u32 = 9600;
 ///
 /// let register = Register::CellVoltage(1);
 /// let regs = transport.read_holding_registers(0x01, register.address(), register.quantity()).await?;
-/// let value = register.parse_registers(&regs);
+/// let value = register.parse_registers(&regs)?;
 /// ```
 pub struct SerialTransport {
     ctx: Context,
     slave_id: u8,
+    timeout: Duration,
+    inter_request_delay: Duration,
+    /// When the previous exchange finished, for pacing.
+    last_exchange: Instant,
 }
 
 impl std::fmt::Debug for SerialTransport {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("SerialTransport")
             .field("slave_id", &self.slave_id)
+            .field("timeout", &self.timeout)
             .finish_non_exhaustive()
     }
 }
@@ -59,13 +163,31 @@ impl SerialTransport {
     /// * `baud_rate` - Baud rate (typically 9600 for Renogy BMS)
     /// * `slave_id` - Modbus slave address
     pub async fn new(path: &str, baud_rate: u32, slave_id: u8) -> Result<Self> {
-        let port = tokio_serial::new(path, baud_rate)
-            .open_native_async()
-            .map_err(|e| RenogyError::Io(std::io::Error::other(e.to_string())))?;
+        let config = SerialConfig {
+            baud_rate,
+            ..SerialConfig::default()
+        };
+        Self::with_config(path, &config, slave_id).await
+    }
 
+    /// Create a serial transport with full line settings.
+    ///
+    /// `rts_direction` needs the native framer (`rtu_serial::RtuSerialTransport`) and
+    /// is rejected with `UnsupportedOperation` here.
+    pub async fn with_config(path: &str, config: &SerialConfig, slave_id: u8) -> Result<Self> {
+        if config.rts_direction {
+            return Err(RenogyError::UnsupportedOperation);
+        }
+        let port = config.open(path)?;
         let ctx = tokio_modbus::client::rtu::attach_slave(port, Slave(slave_id));
 
-        Ok(Self { ctx, slave_id })
+        Ok(Self {
+            ctx,
+            slave_id,
+            timeout: config.timeout,
+            inter_request_delay: config.inter_request_delay,
+            last_exchange: Instant::now(),
+        })
     }
 
     /// Create a new serial transport with default baud rate (9600).
@@ -84,10 +206,19 @@ impl SerialTransport {
         self.slave_id
     }
 
-    fn ensure_slave(&mut self, slave: u8) {
+    /// Select `slave` and wait out the inter-request delay.
+    async fn prepare(&mut self, slave: u8) {
         if slave != self.slave_id {
             self.set_slave(slave);
         }
+        tokio::time::sleep_until(self.last_exchange + self.inter_request_delay).await;
+    }
+
+    fn finish<T>(&mut self, result: std::result::Result<std::io::Result<T>, Elapsed>) -> Result<T> {
+        self.last_exchange = Instant::now();
+        result
+            .map_err(|_| RenogyError::Timeout)?
+            .map_err(io_to_renogy_error)
     }
 }
 
@@ -99,19 +230,19 @@ impl Transport for SerialTransport {
         addr: u16,
         quantity: u16,
     ) -> Result<Vec<u16>> {
-        self.ensure_slave(slave);
-        self.ctx
-            .read_holding_registers(addr, quantity)
-            .await
-            .map_err(io_to_renogy_error)
+        self.prepare(slave).await;
+        let result = timeout(
+            self.timeout,
+            self.ctx.read_holding_registers(addr, quantity),
+        )
+        .await;
+        self.finish(result)
     }
 
     async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
-        self.ensure_slave(slave);
-        self.ctx
-            .write_single_register(addr, value)
-            .await
-            .map_err(io_to_renogy_error)
+        self.prepare(slave).await;
+        let result = timeout(self.timeout, self.ctx.write_single_register(addr, value)).await;
+        self.finish(result)
     }
 
     async fn write_multiple_registers(
@@ -120,19 +251,22 @@ impl Transport for SerialTransport {
         addr: u16,
         values: &[u16],
     ) -> Result<()> {
-        self.ensure_slave(slave);
-        self.ctx
-            .write_multiple_registers(addr, values)
-            .await
-            .map_err(io_to_renogy_error)
+        self.prepare(slave).await;
+        let result = timeout(
+            self.timeout,
+            self.ctx.write_multiple_registers(addr, values),
+        )
+        .await;
+        self.finish(result)
     }
 
     async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
         use tokio_modbus::prelude::Request;
 
-        self.ensure_slave(slave);
+        self.prepare(slave).await;
         let request = Request::Custom(function_code, data.to_vec());
-        let response = self.ctx.call(request).await.map_err(io_to_renogy_error)?;
+        let result = timeout(self.timeout, self.ctx.call(request)).await;
+        let response = self.finish(result)?;
 
         match response {
             tokio_modbus::prelude::Response::Custom(_fc, response_data) => Ok(response_data),
@@ -145,9 +279,46 @@ impl Transport for SerialTransport {
     }
 }
 
+/// What tokio-modbus's RTU decoder says about a bad checksum: "Invalid CRC" while it
+/// drops bytes to resynchronize, "Too many retries" once it gives up on the frame.
+const CRC_ERRORS: [&str; 2] = ["Invalid CRC", "Too many retries"];
+
 fn io_to_renogy_error(e: IoError) -> RenogyError {
     match e.kind() {
+        // A corrupted frame, so `RetryingTransport` retries it as it does on BT-2.
+        ErrorKind::InvalidData
+            if CRC_ERRORS
+                .iter()
+                .any(|message| e.to_string().starts_with(message)) =>
+        {
+            RenogyError::CrcMismatch
+        }
         ErrorKind::InvalidData => RenogyError::InvalidData,
+        ErrorKind::TimedOut => RenogyError::Timeout,
         _ => RenogyError::Io(e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::io_to_renogy_error;
+    use crate::error::RenogyError;
+    use crate::retry::is_transient;
+    use std::io::Error;
+    use std::io::ErrorKind;
+
+    #[test]
+    fn tokio_modbus_crc_failures_are_retried() {
+        for message in [
+            "Invalid CRC: expected = 0x1234, actual = 0x4321",
+            "Too many retries",
+        ] {
+            let err = io_to_renogy_error(Error::new(ErrorKind::InvalidData, message));
+            assert!(matches!(err, RenogyError::CrcMismatch), "{message}");
+            assert!(is_transient(&err));
+        }
+        let err = io_to_renogy_error(Error::new(ErrorKind::InvalidData, "Invalid byte count"));
+        assert!(matches!(err, RenogyError::InvalidData));
+        assert!(!is_transient(&err));
+    }
+}
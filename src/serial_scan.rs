@@ -0,0 +1,200 @@
+//! Serial port enumeration and baud/address probing for commissioning a site.
+//!
+//! `available_ports` lists candidate ports with their USB identity, and `scan_port`
+//! opens a port at each candidate baud rate and runs `discovery::discover` over the
+//! address range. A bus runs at a single baud rate, so scanning a port stops at the first
+//! rate where any battery answers.
+
+use crate::discovery::DiscoveredBattery;
+use crate::discovery::DiscoveryOptions;
+use crate::discovery::discover;
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::rtu_serial::RtuSerialTransport;
+use crate::serial::SerialConfig;
+use crate::transport::Transport;
+use serde::Serialize;
+use std::io::Error as IoError;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use tokio_serial::SerialPortType;
+
+/// Baud rates to try, most likely first. Renogy batteries ship at 9600.
+pub const COMMON_BAUD_RATES: [u32; 5] = [9600, 19_200, 4800, 38_400, 115_200];
+
+/// A serial port and, for USB adapters, what is plugged in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PortInfo {
+    pub path: String,
+    /// "usb", "pci", "bluetooth" or "unknown".
+    pub kind: &'static str,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+}
+
+impl PortInfo {
+    /// A port given by path alone, e.g. from the command line.
+    pub fn from_path(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            kind: "unknown",
+            vid: None,
+            pid: None,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+        }
+    }
+
+    pub fn is_usb(&self) -> bool {
+        self.kind == "usb"
+    }
+}
+
+/// Batteries found on one port at one baud rate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScanResult {
+    pub port: String,
+    pub baud_rate: u32,
+    pub batteries: Vec<DiscoveredBattery>,
+}
+
+/// Everything a scan saw, for machine-readable output.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScanReport {
+    pub ports: Vec<PortInfo>,
+    pub found: Vec<ScanResult>,
+}
+
+/// Serial ports the OS knows about.
+pub fn available_ports() -> Result<Vec<PortInfo>> {
+    let ports = tokio_serial::available_ports()
+        .map_err(|e| RenogyError::Io(IoError::other(e.to_string())))?;
+    Ok(ports
+        .into_iter()
+        .map(|port| {
+            let mut info = PortInfo::from_path(port.port_name);
+            match port.port_type {
+                SerialPortType::UsbPort(usb) => {
+                    info.kind = "usb";
+                    info.vid = Some(usb.vid);
+                    info.pid = Some(usb.pid);
+                    info.serial_number = usb.serial_number;
+                    info.manufacturer = usb.manufacturer;
+                    info.product = usb.product;
+                }
+                SerialPortType::PciPort => info.kind = "pci",
+                SerialPortType::BluetoothPort => info.kind = "bluetooth",
+                SerialPortType::Unknown => {}
+            }
+            info
+        })
+        .collect())
+}
+
+/// Probe `path` at each of `baud_rates` in turn, returning the first rate at which
+/// any address answers.
+pub async fn scan_port(
+    path: &str,
+    baud_rates: &[u32],
+    addresses: RangeInclusive<u8>,
+    probe_timeout: Duration,
+) -> Result<Option<ScanResult>> {
+    scan_with(path, baud_rates, addresses, probe_timeout, |baud_rate| {
+        let config = SerialConfig {
+            baud_rate,
+            ..SerialConfig::default()
+        };
+        RtuSerialTransport::open(path, &config)
+    })
+    .await
+}
+
+/// `scan_port` over whatever `open` returns for each baud rate.
+async fn scan_with<T: Transport + Send>(
+    path: &str,
+    baud_rates: &[u32],
+    addresses: RangeInclusive<u8>,
+    probe_timeout: Duration,
+    mut open: impl FnMut(u32) -> Result<T>,
+) -> Result<Option<ScanResult>> {
+    for &baud_rate in baud_rates {
+        tracing::info!("Probing {} at {} baud", path, baud_rate);
+        let mut transport = open(baud_rate)?;
+        let options = DiscoveryOptions {
+            probe_timeout: Some(probe_timeout),
+            ..DiscoveryOptions::default()
+        };
+        let batteries = discover(&mut transport, addresses.clone(), &options).await;
+        if !batteries.is_empty() {
+            return Ok(Some(ScanResult {
+                port: path.to_string(),
+                baud_rate,
+                batteries,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::COMMON_BAUD_RATES;
+    use super::scan_with;
+    use crate::emulator::EmulatedBattery;
+    use crate::emulator::EmulatedBus;
+    use std::time::Duration;
+
+    /// Scan a port whose bus only answers at `baud`, returning the result and the
+    /// rates tried.
+    async fn scan(baud: Option<u32>) -> (Option<super::ScanResult>, Vec<u32>) {
+        let mut tried = Vec::new();
+        let result = scan_with(
+            "/dev/ttyTEST",
+            &COMMON_BAUD_RATES,
+            0x01..=0x10,
+            Duration::from_millis(50),
+            |baud_rate| {
+                tried.push(baud_rate);
+                let batteries = if Some(baud_rate) == baud {
+                    vec![
+                        EmulatedBattery::lfp_12v(0x02),
+                        EmulatedBattery::lfp_12v(0x07),
+                    ]
+                } else {
+                    vec![]
+                };
+                Ok(EmulatedBus::new(batteries))
+            },
+        )
+        .await
+        .unwrap();
+        (result, tried)
+    }
+
+    #[tokio::test]
+    async fn scan_stops_at_the_first_rate_that_answers() {
+        let (result, tried) = scan(Some(19_200)).await;
+        let result = result.expect("found");
+
+        assert_eq!(tried, [9600, 19_200]);
+        assert_eq!(result.port, "/dev/ttyTEST");
+        assert_eq!(result.baud_rate, 19_200);
+        let found: Vec<_> = result
+            .batteries
+            .iter()
+            .map(|b| (b.address, b.serial.as_str()))
+            .collect();
+        assert_eq!(found, [(0x02, "SN0002"), (0x07, "SN0007")]);
+    }
+
+    #[tokio::test]
+    async fn silent_port_tries_every_rate() {
+        let (result, tried) = scan(None).await;
+        assert!(result.is_none());
+        assert_eq!(tried, COMMON_BAUD_RATES);
+    }
+}
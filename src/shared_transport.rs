@@ -0,0 +1,403 @@
+//! Sharing one link between tasks.
+//!
+//! `Transport` methods take `&mut self`, so a link can only have one user at a time.
+//! `TransportHandle::spawn` moves the transport into an owning task and hands out
+//! cloneable handles that queue requests to it. Interactive requests always go ahead of
+//! polling; within a priority, clients (handle clones) are served round-robin so a busy
+//! poller cannot starve a second one.
+
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::transport::Transport;
+use crate::transport::TransportType;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Priority {
+    /// Background polling, served when no interactive request is waiting.
+    #[default]
+    Polling,
+    /// User-initiated commands (config writes, TUI actions).
+    Interactive,
+}
+
+enum Request {
+    ReadHoldingRegisters {
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+        reply: oneshot::Sender<Result<Vec<u16>>>,
+    },
+    WriteSingleRegister {
+        slave: u8,
+        addr: u16,
+        value: u16,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    WriteMultipleRegisters {
+        slave: u8,
+        addr: u16,
+        values: Vec<u16>,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    SendCustom {
+        slave: u8,
+        function_code: u8,
+        data: Vec<u8>,
+        reply: oneshot::Sender<Result<Vec<u8>>>,
+    },
+}
+
+impl Request {
+    /// True once the requesting future was dropped; such requests are not sent.
+    fn is_abandoned(&self) -> bool {
+        match self {
+            Request::ReadHoldingRegisters { reply, .. } => reply.is_closed(),
+            Request::WriteSingleRegister { reply, .. }
+            | Request::WriteMultipleRegisters { reply, .. } => reply.is_closed(),
+            Request::SendCustom { reply, .. } => reply.is_closed(),
+        }
+    }
+
+    async fn execute(self, transport: &mut (impl Transport + Send)) {
+        match self {
+            Request::ReadHoldingRegisters {
+                slave,
+                addr,
+                quantity,
+                reply,
+            } => {
+                let _ = reply.send(
+                    transport
+                        .read_holding_registers(slave, addr, quantity)
+                        .await,
+                );
+            }
+            Request::WriteSingleRegister {
+                slave,
+                addr,
+                value,
+                reply,
+            } => {
+                let _ = reply.send(transport.write_single_register(slave, addr, value).await);
+            }
+            Request::WriteMultipleRegisters {
+                slave,
+                addr,
+                values,
+                reply,
+            } => {
+                let _ = reply.send(
+                    transport
+                        .write_multiple_registers(slave, addr, &values)
+                        .await,
+                );
+            }
+            Request::SendCustom {
+                slave,
+                function_code,
+                data,
+                reply,
+            } => {
+                let _ = reply.send(transport.send_custom(slave, function_code, &data).await);
+            }
+        }
+    }
+}
+
+struct Job<R> {
+    client: u64,
+    priority: Priority,
+    request: R,
+}
+
+/// Round-robin queues for one priority level.
+struct Lanes<R> {
+    /// Clients with pending work, in service order.
+    order: VecDeque<u64>,
+    pending: HashMap<u64, VecDeque<R>>,
+}
+
+impl<R> Default for Lanes<R> {
+    fn default() -> Self {
+        Self {
+            order: VecDeque::new(),
+            pending: HashMap::new(),
+        }
+    }
+}
+
+impl<R> Lanes<R> {
+    fn push(&mut self, client: u64, request: R) {
+        let queue = self.pending.entry(client).or_default();
+        if queue.is_empty() {
+            self.order.push_back(client);
+        }
+        queue.push_back(request);
+    }
+
+    fn pop(&mut self) -> Option<R> {
+        let client = self.order.pop_front()?;
+        let queue = self.pending.get_mut(&client)?;
+        let request = queue.pop_front();
+        if queue.is_empty() {
+            self.pending.remove(&client);
+        } else {
+            self.order.push_back(client);
+        }
+        request
+    }
+}
+
+struct Scheduler<R> {
+    interactive: Lanes<R>,
+    polling: Lanes<R>,
+}
+
+impl<R> Default for Scheduler<R> {
+    fn default() -> Self {
+        Self {
+            interactive: Lanes::default(),
+            polling: Lanes::default(),
+        }
+    }
+}
+
+impl<R> Scheduler<R> {
+    fn push(&mut self, job: Job<R>) {
+        match job.priority {
+            Priority::Interactive => self.interactive.push(job.client, job.request),
+            Priority::Polling => self.polling.push(job.client, job.request),
+        }
+    }
+
+    fn pop(&mut self) -> Option<R> {
+        self.interactive.pop().or_else(|| self.polling.pop())
+    }
+}
+
+/// Cloneable handle to a transport owned by a background task.
+///
+/// Every clone is its own client for fair scheduling and keeps the priority of the
+/// handle it was cloned from. The owning task exits when the last handle is dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// let poller = TransportHandle::spawn(Bt2Transport::connect_by_address(mac, "hci0").await?);
+/// let mut writer = poller.with_priority(Priority::Interactive);
+/// writer.write_single_register(0x30, 5223, 0x5A5A).await?;
+/// ```
+pub struct TransportHandle {
+    tx: mpsc::UnboundedSender<Job<Request>>,
+    client: u64,
+    next_client: Arc<AtomicU64>,
+    priority: Priority,
+    transport_type: TransportType,
+}
+
+impl TransportHandle {
+    pub fn spawn(transport: impl Transport + Send + 'static) -> Self {
+        let transport_type = transport.transport_type();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(Self::run(transport, rx));
+
+        Self {
+            tx,
+            client: 0,
+            next_client: Arc::new(AtomicU64::new(1)),
+            priority: Priority::default(),
+            transport_type,
+        }
+    }
+
+    /// A new client of the same transport with the given priority.
+    #[must_use]
+    pub fn with_priority(&self, priority: Priority) -> Self {
+        Self {
+            priority,
+            ..self.clone()
+        }
+    }
+
+    #[must_use]
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    async fn run(
+        mut transport: impl Transport + Send,
+        mut rx: mpsc::UnboundedReceiver<Job<Request>>,
+    ) {
+        let mut scheduler = Scheduler::default();
+        loop {
+            while let Ok(job) = rx.try_recv() {
+                scheduler.push(job);
+            }
+            let Some(request) = scheduler.pop() else {
+                match rx.recv().await {
+                    Some(job) => scheduler.push(job),
+                    None => return,
+                }
+                continue;
+            };
+            if !request.is_abandoned() {
+                request.execute(&mut transport).await;
+            }
+        }
+    }
+
+    async fn submit<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<Result<T>>) -> Request,
+    ) -> Result<T> {
+        let (reply, response) = oneshot::channel();
+        self.tx
+            .send(Job {
+                client: self.client,
+                priority: self.priority,
+                request: request(reply),
+            })
+            .map_err(|_| RenogyError::TransportClosed)?;
+        response.await.map_err(|_| RenogyError::TransportClosed)?
+    }
+}
+
+impl Clone for TransportHandle {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            client: self.next_client.fetch_add(1, Ordering::Relaxed),
+            next_client: Arc::clone(&self.next_client),
+            priority: self.priority,
+            transport_type: self.transport_type,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for TransportHandle {
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        self.submit(|reply| Request::ReadHoldingRegisters {
+            slave,
+            addr,
+            quantity,
+            reply,
+        })
+        .await
+    }
+
+    async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
+        self.submit(|reply| Request::WriteSingleRegister {
+            slave,
+            addr,
+            value,
+            reply,
+        })
+        .await
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        let values = values.to_vec();
+        self.submit(|reply| Request::WriteMultipleRegisters {
+            slave,
+            addr,
+            values,
+            reply,
+        })
+        .await
+    }
+
+    async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let data = data.to_vec();
+        self.submit(|reply| Request::SendCustom {
+            slave,
+            function_code,
+            data,
+            reply,
+        })
+        .await
+    }
+
+    fn transport_type(&self) -> TransportType {
+        self.transport_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Job;
+    use super::Priority;
+    use super::Scheduler;
+    use super::TransportHandle;
+    use crate::emulator::EmulatedBattery;
+    use crate::registers::Register;
+    use crate::transport::Transport;
+
+    fn job(client: u64, priority: Priority, request: &'static str) -> Job<&'static str> {
+        Job {
+            client,
+            priority,
+            request,
+        }
+    }
+
+    #[test]
+    fn interactive_requests_go_first() {
+        let mut scheduler = Scheduler::default();
+        scheduler.push(job(1, Priority::Polling, "poll"));
+        scheduler.push(job(2, Priority::Interactive, "write"));
+        assert_eq!(scheduler.pop(), Some("write"));
+        assert_eq!(scheduler.pop(), Some("poll"));
+        assert_eq!(scheduler.pop(), None);
+    }
+
+    #[test]
+    fn clients_of_equal_priority_take_turns() {
+        let mut scheduler = Scheduler::default();
+        for request in ["a1", "a2", "a3"] {
+            scheduler.push(job(1, Priority::Polling, request));
+        }
+        scheduler.push(job(2, Priority::Polling, "b1"));
+        scheduler.push(job(2, Priority::Polling, "b2"));
+
+        let order: Vec<_> = std::iter::from_fn(|| scheduler.pop()).collect();
+        assert_eq!(order, ["a1", "b1", "a2", "b2", "a3"]);
+    }
+
+    #[tokio::test]
+    async fn handles_share_one_transport() {
+        let addr = 0x30;
+        let mut poller = TransportHandle::spawn(EmulatedBattery::lfp_12v(addr));
+        let mut writer = poller.with_priority(Priority::Interactive);
+        assert_eq!(writer.transport_type(), poller.transport_type());
+
+        let register = Register::DeviceId.address();
+        let write =
+            tokio::spawn(async move { writer.write_single_register(addr, register, 0x31).await });
+        write.await.unwrap().unwrap();
+
+        let regs = poller
+            .read_holding_registers(addr, register, 1)
+            .await
+            .unwrap();
+        assert_eq!(regs, [0x31]);
+    }
+}
@@ -0,0 +1,423 @@
+//! Raw register snapshots: every word of the documented register ranges, decoded
+//! through `Register`, for seeing what a battery reports beyond `BatteryInfo` and for
+//! comparing two reads of it (or two batteries) with `diff`.
+
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::registers::DataType;
+use crate::registers::Register;
+use crate::registers::Value;
+use crate::registers::catalog;
+use crate::transport::Transport;
+use crate::util::clean_string;
+use bitflags::Flags;
+use chrono::DateTime;
+use chrono::Utc;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::ops::RangeInclusive;
+use uom::si::electric_current::ampere;
+use uom::si::electric_potential::volt;
+use uom::si::thermodynamic_temperature::degree_celsius;
+
+/// Register ranges a snapshot reads: cell and pack readings, alarms and identity,
+/// protection limits and control, and the ACP block.
+pub const SNAPSHOT_RANGES: [RangeInclusive<u16>; 4] =
+    [5000..=5052, 5100..=5141, 5200..=5229, 61440..=61442];
+
+/// Largest read sent at once. The BT-2 phone app never reads more than 34 registers.
+const MAX_READ: u16 = 34;
+
+/// Every word of `SNAPSHOT_RANGES` one battery returned.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken_at: DateTime<Utc>,
+    pub slave: u8,
+    /// Raw words by address. Words the BMS would not return are absent.
+    pub words: BTreeMap<u16, u16>,
+    /// `words` decoded, for reading the file; `diff` works from `words` alone.
+    pub registers: Vec<DecodedRegister>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecodedRegister {
+    pub address: u16,
+    pub name: String,
+    pub value: String,
+}
+
+impl Snapshot {
+    /// Read every range from `slave`, `MAX_READ` words at a time. A block the BMS
+    /// rejects with a Modbus exception is retried a register at a time, so one
+    /// unsupported register does not hide its neighbours; a block that fails otherwise
+    /// is skipped. Fails only if nothing at all could be read.
+    pub async fn read<T: Transport>(transport: &mut T, slave: u8) -> Result<Self> {
+        let mut words = BTreeMap::new();
+        let mut last_error = None;
+        for range in &SNAPSHOT_RANGES {
+            let mut start = *range.start();
+            while start <= *range.end() {
+                let quantity = (range.end() - start + 1).min(MAX_READ);
+                match transport
+                    .read_holding_registers(slave, start, quantity)
+                    .await
+                {
+                    Ok(block) => words.extend((start..).zip(block)),
+                    Err(RenogyError::ModbusException(e)) => {
+                        tracing::debug!(
+                            "Read of {start}+{quantity} at 0x{slave:02X} rejected ({e}), reading registers individually"
+                        );
+                        for register in registers_within(start..=start + quantity - 1) {
+                            let address = register.address();
+                            match transport
+                                .read_holding_registers(slave, address, register.quantity())
+                                .await
+                            {
+                                Ok(values) => words.extend((address..).zip(values)),
+                                Err(e) => last_error = Some(e),
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!("Read of {start}+{quantity} at 0x{slave:02X} failed: {e}");
+                        last_error = Some(e);
+                    }
+                }
+                start += quantity;
+            }
+        }
+        match last_error {
+            Some(e) if words.is_empty() => Err(e),
+            _ => Ok(Self::from_words(slave, Utc::now(), words)),
+        }
+    }
+
+    /// A snapshot of already-read `words`, decoding every register they cover.
+    #[must_use]
+    pub fn from_words(slave: u8, taken_at: DateTime<Utc>, words: BTreeMap<u16, u16>) -> Self {
+        let mut snapshot = Self {
+            taken_at,
+            slave,
+            words,
+            registers: Vec::new(),
+        };
+        snapshot.registers = all_registers()
+            .filter_map(|register| {
+                let value = snapshot.value(&register)?;
+                Some(DecodedRegister {
+                    address: register.address(),
+                    name: register.name(),
+                    value: describe(&register, &value),
+                })
+            })
+            .collect();
+        snapshot
+    }
+
+    /// `register` decoded from the snapshot, if all its words were read.
+    #[must_use]
+    pub fn value(&self, register: &Register) -> Option<Value> {
+        let words = self.raw(register)?;
+        register.parse_registers(&words).ok()
+    }
+
+    /// The register's words, if every one of them was read.
+    pub fn raw(&self, register: &Register) -> Option<Vec<u16>> {
+        let start = register.address();
+        (start..start + register.quantity())
+            .map(|address| self.words.get(&address).copied())
+            .collect()
+    }
+
+    /// File name for saving: serial number (or slave address) and UTC time taken.
+    #[must_use]
+    pub fn file_name(&self) -> String {
+        let serial = self
+            .value(&Register::SnNumber)
+            .and_then(|value| value.as_string().map(clean_string))
+            .map(|serial| {
+                serial
+                    .chars()
+                    .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+                    .collect::<String>()
+            })
+            .filter(|serial| !serial.is_empty())
+            .unwrap_or_else(|| format!("bms-0x{:02X}", self.slave));
+        format!("{serial}-{}.json", self.taken_at.format("%Y%m%dT%H%M%SZ"))
+    }
+}
+
+/// What kind of register changed, in the order `diff` lists them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    /// A writable limit or setting.
+    Limit,
+    /// An alarm or status bitfield.
+    Flags,
+    /// Anything else: readings, counters, identity, and words no register covers.
+    Reading,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub address: u16,
+    pub name: String,
+    pub kind: ChangeKind,
+    /// `None` if the register was not read.
+    pub before: Option<String>,
+    pub after: Option<String>,
+    /// For bitfields, the flags raised (`+NAME`) and cleared (`-NAME`), or the cells
+    /// whose alarm state changed.
+    pub flags: Vec<String>,
+}
+
+/// Registers and stray words that differ between two snapshots: limits first, then
+/// flags, then readings, each in address order.
+#[must_use]
+pub fn diff(before: &Snapshot, after: &Snapshot) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for register in all_registers() {
+        let (old, new) = (before.raw(&register), after.raw(&register));
+        if old == new {
+            continue;
+        }
+        let (old_value, new_value) = (before.value(&register), after.value(&register));
+        let info = register.info();
+        let kind = if register.is_writable() {
+            ChangeKind::Limit
+        } else if matches!(
+            info.data_type,
+            DataType::Integer
+                | DataType::Voltage
+                | DataType::Current
+                | DataType::Capacity
+                | DataType::Temperature
+                | DataType::String
+        ) {
+            ChangeKind::Reading
+        } else {
+            ChangeKind::Flags
+        };
+        let flags = match (&old_value, &new_value) {
+            (Some(old), Some(new)) => flag_changes(old, new),
+            _ => Vec::new(),
+        };
+        changes.push(Change {
+            address: register.address(),
+            name: register.name(),
+            kind,
+            before: old_value.map(|value| describe(&register, &value)),
+            after: new_value.map(|value| describe(&register, &value)),
+            flags,
+        });
+    }
+
+    let covered = |address: u16| {
+        all_registers().any(|register| {
+            (register.address()..register.address() + register.quantity()).contains(&address)
+        })
+    };
+    let addresses: std::collections::BTreeSet<u16> = before
+        .words
+        .keys()
+        .chain(after.words.keys())
+        .copied()
+        .collect();
+    for address in addresses.into_iter().filter(|address| !covered(*address)) {
+        let (old, new) = (before.words.get(&address), after.words.get(&address));
+        if old != new {
+            changes.push(Change {
+                address,
+                name: format!("word_{address}"),
+                kind: ChangeKind::Reading,
+                before: old.map(|word| format!("0x{word:04X}")),
+                after: new.map(|word| format!("0x{word:04X}")),
+                flags: Vec::new(),
+            });
+        }
+    }
+
+    changes.sort_by_key(|change| (change.kind, change.address));
+    changes
+}
+
+/// Every register in the catalog, one per cell or sensor.
+fn all_registers() -> impl Iterator<Item = Register> {
+    catalog().iter().flat_map(|info| info.registers())
+}
+
+fn registers_within(range: RangeInclusive<u16>) -> impl Iterator<Item = Register> {
+    all_registers().filter(move |register| {
+        range.contains(&register.address())
+            && range.contains(&(register.address() + register.quantity() - 1))
+    })
+}
+
+/// `value` at the register's full resolution, with its unit.
+fn describe(register: &Register, value: &Value) -> String {
+    let info = register.info();
+    let decimals = (-info.scale.log10()).round().max(0.0) as usize;
+    let unit = info.unit.unwrap_or_default();
+    match value {
+        Value::ElectricPotential(v) => format!("{:.decimals$} {unit}", v.get::<volt>()),
+        Value::ElectricCurrent(c) => format!("{:.decimals$} {unit}", c.get::<ampere>()),
+        Value::ThermodynamicTemperature(t) => {
+            format!("{:.decimals$} {unit}", t.get::<degree_celsius>())
+        }
+        Value::String(s) => format!("{:?}", clean_string(s)),
+        other => other.to_string(),
+    }
+}
+
+fn flag_changes(before: &Value, after: &Value) -> Vec<String> {
+    match (before, after) {
+        (Value::Status1(old), Value::Status1(new)) => bit_changes(*old, *new),
+        (Value::Status2(old), Value::Status2(new)) => bit_changes(*old, *new),
+        (Value::Status3(old), Value::Status3(new)) => bit_changes(*old, *new),
+        (Value::OtherAlarmInfo(old), Value::OtherAlarmInfo(new)) => bit_changes(*old, *new),
+        (Value::ChargeDischargeStatus(old), Value::ChargeDischargeStatus(new)) => {
+            bit_changes(*old, *new)
+        }
+        (Value::CellVoltageAlarms(old), Value::CellVoltageAlarms(new)) => {
+            cell_changes(&old.alarms, &new.alarms)
+        }
+        (Value::CellTemperatureAlarms(old), Value::CellTemperatureAlarms(new)) => {
+            cell_changes(&old.alarms, &new.alarms)
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn bit_changes<F: Flags + Copy>(before: F, after: F) -> Vec<String> {
+    let raised = after.difference(before);
+    let cleared = before.difference(after);
+    raised
+        .iter_names()
+        .map(|(name, _)| format!("+{name}"))
+        .chain(cleared.iter_names().map(|(name, _)| format!("-{name}")))
+        .collect()
+}
+
+fn cell_changes<A: PartialEq + Debug>(before: &[A], after: &[A]) -> Vec<String> {
+    before
+        .iter()
+        .zip(after)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(i, (old, new))| format!("cell {}: {old:?} -> {new:?}", i + 1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChangeKind;
+    use super::Snapshot;
+    use super::diff;
+    use crate::alarm::Status1;
+    use crate::emulator::EmulatedBattery;
+    use crate::emulator::Fault;
+    use crate::emulator::FaultyTransport;
+    use crate::error::ModbusExceptionCode;
+    use crate::error::RenogyError;
+    use crate::registers::Register;
+    use crate::registers::Value;
+
+    #[tokio::test]
+    async fn reads_every_range() {
+        let snapshot = Snapshot::read(&mut EmulatedBattery::lfp_12v(0x30), 0x30)
+            .await
+            .unwrap();
+        assert_eq!(snapshot.words.len(), 53 + 42 + 30 + 3);
+        assert_eq!(
+            snapshot.value(&Register::CellCount),
+            Some(Value::Integer(4))
+        );
+        assert_eq!(snapshot.value(&Register::AcpShake), Some(Value::Integer(1)));
+        let decoded = |name: &str| {
+            snapshot
+                .registers
+                .iter()
+                .find(|register| register.name == name)
+                .map(|register| register.value.as_str())
+        };
+        assert_eq!(decoded("cell_voltage_2"), Some("3.3 V"));
+        assert_eq!(decoded("total_capacity"), Some("100.000 Ah"));
+        assert_eq!(decoded("sn_number"), Some("\"SN0030\""));
+        assert!(snapshot.file_name().starts_with("SN0030-"));
+    }
+
+    #[tokio::test]
+    async fn rejected_block_is_read_per_register() {
+        // Some firmware rejects any read that touches the unassigned word 5034.
+        let mut link =
+            FaultyTransport::new(EmulatedBattery::lfp_12v(0x30)).on_read(|_, addr, quantity| {
+                if (addr..addr + quantity).contains(&5034) {
+                    Fault::Answer(Err(RenogyError::ModbusException(
+                        ModbusExceptionCode::IllegalDataAddress,
+                    )))
+                } else {
+                    Fault::Pass
+                }
+            });
+        let snapshot = Snapshot::read(&mut link, 0x30).await.unwrap();
+        assert!(!snapshot.words.contains_key(&5034));
+        assert!(snapshot.value(&Register::BmsTemperature).is_some());
+        assert!(snapshot.value(&Register::ChargeCurrentLimit).is_some());
+        assert_eq!(snapshot.words.len(), 53 + 42 + 30 + 3 - 1);
+    }
+
+    #[tokio::test]
+    async fn missing_battery_is_an_error() {
+        assert!(
+            Snapshot::read(&mut EmulatedBattery::lfp_12v(0x30), 0x31)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn diff_puts_limits_and_flags_first() {
+        let mut bms = EmulatedBattery::lfp_12v(0x30);
+        let before = Snapshot::read(&mut bms, 0x30).await.unwrap();
+        bms.set_voltage(Register::CellVoltage(3), 3.2).unwrap();
+        bms.set_voltage(Register::CellOverVoltageLimit, 3.6)
+            .unwrap();
+        bms.set(
+            Register::Status1,
+            &Value::Status1(Status1::SHORT_CIRCUIT | Status1::CHARGE_MOSFET),
+        )
+        .unwrap();
+        let after = Snapshot::read(&mut bms, 0x30).await.unwrap();
+
+        let changes = diff(&before, &after);
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|change| (change.kind, change.name.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (ChangeKind::Limit, "cell_over_voltage_limit"),
+                (ChangeKind::Flags, "status1"),
+                (ChangeKind::Reading, "cell_voltage_3"),
+            ]
+        );
+        assert_eq!(changes[0].before.as_deref(), Some("3.7 V"));
+        assert_eq!(changes[0].after.as_deref(), Some("3.6 V"));
+        assert_eq!(changes[1].flags, ["+CHARGE_MOSFET", "+SHORT_CIRCUIT"]);
+        assert!(diff(&after, &after).is_empty());
+    }
+
+    #[tokio::test]
+    async fn snapshot_survives_json() {
+        let snapshot = Snapshot::read(&mut EmulatedBattery::lfp_12v(0x30), 0x30)
+            .await
+            .unwrap();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let loaded: Snapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded, snapshot);
+        assert!(diff(&snapshot, &loaded).is_empty());
+    }
+}
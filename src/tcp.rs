@@ -0,0 +1,349 @@
+//! Modbus TCP transport for Ethernet RS-485 gateways.
+//!
+//! Each request is framed as an MBAP ADU: transaction id, protocol id (always 0),
+//! length, unit id, then the function code and payload. The unit id carries the BMS
+//! address, which the gateway forwards onto its RS-485 bus unchanged.
+
+use crate::error::RenogyError;
+use crate::error::Result;
+use crate::pdu::FunctionCode;
+use crate::pdu::Pdu;
+use crate::transport::Transport;
+use crate::transport::TransportType;
+use async_trait::async_trait;
+use std::io::Error as IoError;
+use std::io::ErrorKind;
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::net::ToSocketAddrs;
+use tokio::time::timeout;
+
+pub const DEFAULT_TCP_PORT: u16 = 502;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const MBAP_HEADER_LEN: usize = 6;
+/// Unit id + function code + at most 252 data bytes (Modbus application spec).
+const MAX_ADU_BODY_LEN: usize = 254;
+
+/// One MBAP frame off the wire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Adu {
+    pub transaction_id: u16,
+    /// Unit id, function code and data: the same layout as `Pdu::to_bytes`.
+    pub body: Vec<u8>,
+}
+
+impl Adu {
+    #[must_use]
+    pub fn new(transaction_id: u16, pdu: &Pdu) -> Self {
+        Self {
+            transaction_id,
+            body: pdu.to_bytes(),
+        }
+    }
+
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(MBAP_HEADER_LEN + self.body.len());
+        frame.extend_from_slice(&self.transaction_id.to_be_bytes());
+        frame.extend_from_slice(&0u16.to_be_bytes());
+        frame.extend_from_slice(&(self.body.len() as u16).to_be_bytes());
+        frame.extend_from_slice(&self.body);
+        frame
+    }
+
+    /// Split the first complete ADU off the front of `buf`.
+    ///
+    /// Returns `Ok(None)` when more bytes are needed. A header that cannot belong to a
+    /// Modbus TCP frame is an error; the stream is out of sync and should be dropped.
+    pub fn take(buf: &mut Vec<u8>) -> Result<Option<Self>> {
+        if buf.len() < MBAP_HEADER_LEN {
+            return Ok(None);
+        }
+
+        let transaction_id = u16::from_be_bytes([buf[0], buf[1]]);
+        let protocol_id = u16::from_be_bytes([buf[2], buf[3]]);
+        let length = u16::from_be_bytes([buf[4], buf[5]]) as usize;
+
+        if protocol_id != 0 || !(2..=MAX_ADU_BODY_LEN).contains(&length) {
+            return Err(RenogyError::InvalidData);
+        }
+        if buf.len() < MBAP_HEADER_LEN + length {
+            return Ok(None);
+        }
+
+        let body = buf[MBAP_HEADER_LEN..MBAP_HEADER_LEN + length].to_vec();
+        buf.drain(..MBAP_HEADER_LEN + length);
+        Ok(Some(Self {
+            transaction_id,
+            body,
+        }))
+    }
+}
+
+/// Modbus TCP transport.
+///
+/// # Example
+///
+/// ```ignore
+/// use renogy::tcp::TcpTransport;
+/// use renogy::transport::Transport;
+///
+/// let mut transport = TcpTransport::connect("192.168.1.50:502").await?;
+/// let regs = transport.read_holding_registers(0x01, 5000, 1).await?;
+/// ```
+pub struct TcpTransport {
+    stream: TcpStream,
+    rx_buf: Vec<u8>,
+    transaction_id: u16,
+    timeout: Duration,
+}
+
+impl std::fmt::Debug for TcpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TcpTransport")
+            .field("peer", &self.stream.peer_addr().ok())
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TcpTransport {
+    /// Connect to a Modbus TCP gateway (e.g. "192.168.1.50:502").
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = timeout(DEFAULT_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| RenogyError::Timeout)??;
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream,
+            rx_buf: Vec::new(),
+            transaction_id: 0,
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    async fn send_pdu(&mut self, pdu: &Pdu) -> Result<Pdu> {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let transaction_id = self.transaction_id;
+
+        self.stream
+            .write_all(&Adu::new(transaction_id, pdu).encode())
+            .await?;
+
+        timeout(self.timeout, self.receive(transaction_id))
+            .await
+            .map_err(|_| RenogyError::Timeout)?
+            .and_then(|body| Pdu::from_bytes(&body))
+            .and_then(|response| {
+                if response.address == pdu.address && response.function_code == pdu.function_code {
+                    Ok(response)
+                } else {
+                    Err(RenogyError::InvalidData)
+                }
+            })
+    }
+
+    /// Read ADUs until the one answering `transaction_id` arrives. Responses to
+    /// earlier, timed-out requests are discarded. Cancel-safe: partial frames stay in
+    /// `rx_buf` for the next call.
+    async fn receive(&mut self, transaction_id: u16) -> Result<Vec<u8>> {
+        loop {
+            while let Some(adu) = Adu::take(&mut self.rx_buf)? {
+                if adu.transaction_id == transaction_id {
+                    return Ok(adu.body);
+                }
+                tracing::debug!(
+                    "Discarding stale Modbus TCP response (transaction {})",
+                    adu.transaction_id
+                );
+            }
+
+            let mut chunk = [0u8; 256];
+            let n = self.stream.read(&mut chunk).await?;
+            if n == 0 {
+                return Err(RenogyError::Io(IoError::new(
+                    ErrorKind::UnexpectedEof,
+                    "Modbus TCP gateway closed the connection",
+                )));
+            }
+            self.rx_buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for TcpTransport {
+    async fn read_holding_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>> {
+        self.send_pdu(&Pdu::read_holding_registers(slave, addr, quantity))
+            .await?
+            .register_values(quantity)
+    }
+
+    async fn write_single_register(&mut self, slave: u8, addr: u16, value: u16) -> Result<()> {
+        self.send_pdu(&Pdu::write_single_register(slave, addr, value))
+            .await?;
+        Ok(())
+    }
+
+    async fn write_multiple_registers(
+        &mut self,
+        slave: u8,
+        addr: u16,
+        values: &[u16],
+    ) -> Result<()> {
+        self.send_pdu(&Pdu::write_multiple_registers(slave, addr, values))
+            .await?;
+        Ok(())
+    }
+
+    async fn send_custom(&mut self, slave: u8, function_code: u8, data: &[u8]) -> Result<Vec<u8>> {
+        let fc = FunctionCode::from_u8(function_code).ok_or(RenogyError::InvalidData)?;
+        Ok(self
+            .send_pdu(&Pdu::new(slave, fc, data.to_vec()))
+            .await?
+            .payload)
+    }
+
+    fn transport_type(&self) -> TransportType {
+        TransportType::Tcp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Adu;
+    use super::TcpTransport;
+    use crate::emulator::EmulatedBattery;
+    use crate::error::ModbusExceptionCode;
+    use crate::error::RenogyError;
+    use crate::pdu::Pdu;
+    use crate::query::query_battery;
+    use crate::registers::Register;
+    use crate::transport::Transport;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    /// Minimal Modbus TCP server answering from an `EmulatedBattery`.
+    async fn spawn_server(mut bms: EmulatedBattery) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let local = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            loop {
+                let mut chunk = [0u8; 256];
+                let n = socket.read(&mut chunk).await.unwrap();
+                if n == 0 {
+                    return;
+                }
+                buf.extend_from_slice(&chunk[..n]);
+                while let Some(adu) = Adu::take(&mut buf).unwrap() {
+                    let request = Pdu::from_bytes(&adu.body).unwrap();
+                    let body = match bms.respond(&request) {
+                        Ok(response) => response.to_bytes(),
+                        Err(_) => vec![
+                            request.address,
+                            request.function_code as u8 | 0x80,
+                            ModbusExceptionCode::IllegalDataAddress as u8,
+                        ],
+                    };
+                    let reply = Adu {
+                        transaction_id: adu.transaction_id,
+                        body,
+                    };
+                    socket.write_all(&reply.encode()).await.unwrap();
+                }
+            }
+        });
+        local
+    }
+
+    #[test]
+    fn adu_encode_take_roundtrip() {
+        let pdu = Pdu::read_holding_registers(0x01, 5000, 4);
+        let adu = Adu::new(7, &pdu);
+        let mut buf = adu.encode();
+        assert_eq!(&buf[..6], [0x00, 0x07, 0x00, 0x00, 0x00, 0x06]);
+        buf.extend_from_slice(&[0x00]);
+        assert_eq!(Adu::take(&mut buf).unwrap(), Some(adu));
+        assert_eq!(buf, [0x00]);
+    }
+
+    #[test]
+    fn adu_take_waits_for_complete_frame() {
+        let mut buf = Adu::new(1, &Pdu::read_holding_registers(0x01, 5000, 4)).encode();
+        buf.pop();
+        assert_eq!(Adu::take(&mut buf).unwrap(), None);
+    }
+
+    #[test]
+    fn adu_take_rejects_foreign_protocol() {
+        let mut buf = vec![0x00, 0x01, 0x00, 0x01, 0x00, 0x02, 0x01, 0x03];
+        assert!(matches!(Adu::take(&mut buf), Err(RenogyError::InvalidData)));
+    }
+
+    #[tokio::test]
+    async fn query_battery_over_tcp() {
+        let addr = 0x01;
+        let mut transport =
+            TcpTransport::connect(spawn_server(EmulatedBattery::lfp_12v(addr)).await)
+                .await
+                .unwrap();
+        let info = query_battery(&mut transport, addr).await.expect("info");
+
+        assert_eq!(info.serial, "SN0001");
+        assert_eq!(info.cell_voltages.len(), 4);
+        assert!((info.module_voltage - 13.2).abs() < 1e-2);
+    }
+
+    #[tokio::test]
+    async fn write_single_register_over_tcp() {
+        let addr = 0x01;
+        let mut transport =
+            TcpTransport::connect(spawn_server(EmulatedBattery::lfp_12v(addr)).await)
+                .await
+                .unwrap();
+        transport
+            .write_single_register(addr, Register::DeviceId.address(), 0x02)
+            .await
+            .unwrap();
+        let regs = transport
+            .read_holding_registers(addr, Register::DeviceId.address(), 1)
+            .await
+            .unwrap();
+        assert_eq!(regs, [0x02]);
+    }
+
+    #[tokio::test]
+    async fn exception_response_maps_to_modbus_exception() {
+        let mut transport =
+            TcpTransport::connect(spawn_server(EmulatedBattery::lfp_12v(0x01)).await)
+                .await
+                .unwrap();
+        transport.set_timeout(Duration::from_secs(1));
+        let err = transport
+            .read_holding_registers(0x02, 5000, 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            RenogyError::ModbusException(ModbusExceptionCode::IllegalDataAddress)
+        ));
+    }
+}
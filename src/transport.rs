@@ -1,10 +1,14 @@
 use crate::error::Result;
 use async_trait::async_trait;
+use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TransportType {
     Bt2,
     Serial,
+    Tcp,
+    RtuOverTcp,
 }
 
 /// Transport trait for Modbus communication over any physical layer.
@@ -1,6 +1,21 @@
 use crate::alarm::Status1;
 use crate::alarm::Status2;
+use crate::any_transport::AnyTransport;
+use crate::any_transport::BT2_SCAN_RANGE;
+use crate::any_transport::SERIAL_SCAN_RANGE;
+use crate::bt2::Bt2Device;
+use crate::bt2::Bt2Discovery;
+use crate::bt2::Bt2Transport;
+use crate::bt2::select_device;
+use crate::error::Result as RenogyResult;
 use crate::query::BatteryInfo;
+use crate::rtu_serial::RtuSerialTransport;
+use crate::serial::SerialConfig;
+use crate::serial::SerialTransport;
+use std::time::Duration;
+use tokio_serial::DataBits;
+use tokio_serial::Parity;
+use tokio_serial::StopBits;
 
 /// Parse a BMS address given as decimal or `0x`-prefixed hex.
 pub fn parse_address(s: &str) -> Result<u8, String> {
@@ -13,6 +28,198 @@ pub fn parse_address(s: &str) -> Result<u8, String> {
     }
 }
 
+/// A string register's text without the NUL and space padding the BMS fills it with.
+pub fn clean_string(s: &str) -> String {
+    s.trim_matches(['\0', ' ']).to_string()
+}
+
+pub(crate) fn parse_data_bits(s: &str) -> Result<DataBits, String> {
+    match s {
+        "5" => Ok(DataBits::Five),
+        "6" => Ok(DataBits::Six),
+        "7" => Ok(DataBits::Seven),
+        "8" => Ok(DataBits::Eight),
+        _ => Err("expected 5, 6, 7 or 8".into()),
+    }
+}
+
+pub(crate) fn parse_parity(s: &str) -> Result<Parity, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "none" | "n" => Ok(Parity::None),
+        "odd" | "o" => Ok(Parity::Odd),
+        "even" | "e" => Ok(Parity::Even),
+        _ => Err("expected none, odd or even".into()),
+    }
+}
+
+pub(crate) fn parse_stop_bits(s: &str) -> Result<StopBits, String> {
+    match s {
+        "1" => Ok(StopBits::One),
+        "2" => Ok(StopBits::Two),
+        _ => Err("expected 1 or 2".into()),
+    }
+}
+
+/// Serial link options shared by the `serial` subcommands.
+#[derive(clap::Args, Debug, Clone)]
+pub struct SerialArgs {
+    /// Serial port path (e.g., /dev/ttyUSB0 or COM3)
+    #[arg(short, long)]
+    pub port: String,
+
+    /// Baud rate
+    #[arg(short = 'r', long, default_value_t = 9600)]
+    pub baud_rate: u32,
+
+    /// Data bits (5-8)
+    #[arg(long, default_value = "8", value_parser = parse_data_bits)]
+    pub data_bits: DataBits,
+
+    /// Parity (none, odd, even)
+    #[arg(long, default_value = "none", value_parser = parse_parity)]
+    pub parity: Parity,
+
+    /// Stop bits (1 or 2)
+    #[arg(long, default_value = "1", value_parser = parse_stop_bits)]
+    pub stop_bits: StopBits,
+
+    /// Per-request response timeout in milliseconds
+    #[arg(long, default_value_t = 1000)]
+    pub timeout_ms: u64,
+
+    /// Minimum quiet time between requests in milliseconds
+    #[arg(long, default_value_t = 0)]
+    pub inter_request_delay_ms: u64,
+
+    /// Enable kernel RS-485 mode (Linux TIOCSRS485) so the driver switches the transceiver
+    #[arg(long)]
+    pub rs485: bool,
+
+    /// Drive the transceiver direction from RTS (requires --native-rtu)
+    #[arg(long, requires = "native_rtu")]
+    pub rts_direction: bool,
+
+    /// Use the built-in RTU framer instead of tokio-modbus
+    #[arg(long)]
+    pub native_rtu: bool,
+}
+
+impl SerialArgs {
+    pub fn config(&self) -> SerialConfig {
+        SerialConfig {
+            baud_rate: self.baud_rate,
+            data_bits: self.data_bits,
+            parity: self.parity,
+            stop_bits: self.stop_bits,
+            timeout: Duration::from_millis(self.timeout_ms),
+            inter_request_delay: Duration::from_millis(self.inter_request_delay_ms),
+            rs485: self.rs485,
+            rts_direction: self.rts_direction,
+        }
+    }
+
+    /// Open the port with the selected framer; `slave` is the tokio-modbus default.
+    pub async fn open(&self, slave: u8) -> RenogyResult<AnyTransport> {
+        open_serial(&self.port, &self.config(), self.native_rtu, slave).await
+    }
+}
+
+/// Open `port` with the native RTU framer or tokio-modbus; `slave` is the tokio-modbus
+/// default.
+pub async fn open_serial(
+    port: &str,
+    config: &SerialConfig,
+    native_rtu: bool,
+    slave: u8,
+) -> RenogyResult<AnyTransport> {
+    Ok(if native_rtu {
+        RtuSerialTransport::open(port, config)?.into()
+    } else {
+        SerialTransport::with_config(port, config, slave)
+            .await?
+            .into()
+    })
+}
+
+/// BT-2 link options shared by the `bt2` subcommands.
+#[derive(clap::Args, Debug, Clone)]
+pub struct Bt2Args {
+    /// BT-2 MAC address. If not specified, discovers BT-2s and uses the only one found
+    #[arg(short, long, conflicts_with = "alias")]
+    pub mac: Option<String>,
+
+    /// Pick the discovered BT-2 with this BlueZ alias (or MAC address)
+    #[arg(long)]
+    pub alias: Option<String>,
+
+    /// Bluetooth adapter name
+    #[arg(short, long, default_value = "hci0")]
+    pub adapter: String,
+
+    /// How long to scan when discovering, in seconds
+    #[arg(long, default_value_t = 5)]
+    pub scan_seconds: u64,
+
+    /// Ignore BT-2s heard weaker than this (dBm, e.g. -85) when discovering
+    #[arg(long, allow_negative_numbers = true)]
+    pub min_rssi: Option<i16>,
+}
+
+impl Bt2Args {
+    pub fn discovery(&self) -> Bt2Discovery {
+        let discovery = Bt2Discovery::new()
+            .adapter(&self.adapter)
+            .duration(Duration::from_secs(self.scan_seconds));
+        match self.min_rssi {
+            Some(dbm) => discovery.min_rssi(dbm),
+            None => discovery,
+        }
+    }
+
+    /// Choose among `devices` by `--alias`, refusing an ambiguous pick.
+    pub fn select<'a>(&self, devices: &'a [Bt2Device]) -> RenogyResult<&'a Bt2Device> {
+        select_device(devices, self.alias.as_deref())
+    }
+
+    /// Connect to the BT-2 given by `--mac`, or else the one discovery picks.
+    pub async fn connect(&self) -> RenogyResult<Bt2Transport> {
+        let mac = match &self.mac {
+            Some(mac) => mac.clone(),
+            None => {
+                let devices = self.discovery().scan().await?;
+                self.select(&devices)?.address.clone()
+            }
+        };
+        Bt2Transport::connect_by_address(&mac, &self.adapter).await
+    }
+}
+
+/// The link to the batteries, as a `serial` or `bt2` subcommand.
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum LinkArgs {
+    /// RS-485 serial port
+    Serial(SerialArgs),
+    /// BT-2 Bluetooth module
+    Bt2(Bt2Args),
+}
+
+impl LinkArgs {
+    pub async fn open(&self) -> RenogyResult<AnyTransport> {
+        match self {
+            LinkArgs::Serial(serial) => serial.open(*SERIAL_SCAN_RANGE.start()).await,
+            LinkArgs::Bt2(bt2) => Ok(bt2.connect().await?.into()),
+        }
+    }
+
+    /// Where a lone battery answers on this link: 0x01 on RS-485, 0x30 behind a BT-2.
+    pub fn default_address(&self) -> u8 {
+        match self {
+            LinkArgs::Serial(_) => *SERIAL_SCAN_RANGE.start(),
+            LinkArgs::Bt2(_) => *BT2_SCAN_RANGE.start(),
+        }
+    }
+}
+
 /// Pretty-print a full battery snapshot to stdout (used by the query/example bins).
 pub fn print_battery_info(addr: u8, info: &BatteryInfo) {
     println!("===========================================================");
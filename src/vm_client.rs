@@ -97,6 +97,10 @@ fn assemble_battery_info(
 
     Some(BatteryInfo {
         serial: battery.to_string(),
+        link: samples
+            .iter()
+            .find_map(|(labels, _)| labels.get("link").cloned())
+            .unwrap_or_default(),
         model: String::new(),
         software_version: String::new(),
         manufacturer: String::new(),